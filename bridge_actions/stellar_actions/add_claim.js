@@ -100,7 +100,7 @@ exports.handler = async function (payload) {
             networkPassphrase: Networks.TESTNET,
         })
             .addOperation(
-                contract.call("get_identity", walletParam)
+                contract.call("try_get_identity", walletParam)
             )
             .setTimeout(30)
             .build();
@@ -127,6 +127,9 @@ exports.handler = async function (payload) {
                 let transactionMeta = getResponse.resultMetaXdr;
                 let returnValue = transactionMeta.v3().sorobanMeta().returnValue();
 
+                if (returnValue.switch() === xdr.ScValType.scvVoid()) {
+                    throw new Error('Wallet is not linked to an identity');
+                }
                 buffer = returnValue.value()._value;
 
                 decodedAddress = StrKey.encodeContract(buffer);
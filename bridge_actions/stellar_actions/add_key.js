@@ -88,7 +88,7 @@ exports.handler = async function (payload) {
             networkPassphrase: Networks.TESTNET,
         })
             .addOperation(
-                contract.call("get_identity", walletParam)
+                contract.call("try_get_identity", walletParam)
             )
             .setTimeout(30)
             .build();
@@ -117,6 +117,9 @@ exports.handler = async function (payload) {
                 let transactionMeta = getResponse.resultMetaXdr;
                 let returnValue = transactionMeta.v3().sorobanMeta().returnValue();
 
+                if (returnValue.switch() === xdr.ScValType.scvVoid()) {
+                    throw new Error('Wallet is not linked to an identity');
+                }
                 const buffer = returnValue.value()._value;
                 decodedAddress = StrKey.encodeContract(buffer);
 
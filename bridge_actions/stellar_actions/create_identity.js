@@ -12,11 +12,6 @@ exports.handler = async function (payload) {
         StrKey
     } = require("@stellar/stellar-sdk");
     const axios = require('axios');
-    const crypto = require('crypto');
- 
-    function generateSalt() {
-        return crypto.randomBytes(32).toString('hex');
-    }
     const conditionRequest = payload.request?.body;
     let evmWalletAddress;
     // Check if conditionRequest exists and is valid
@@ -60,47 +55,30 @@ exports.handler = async function (payload) {
         const data = response.data;
         const walletAddress = data.data.stellar_address;
         console.log('Stellar Address:', walletAddress);
-        // Define params for the create_identity function
-        // Parameters
-        const wasmHashHex = "a4e521074eb42b5309cf174f379154a7eff5b6bc0874baf3334513c7cdd33d9a";
+        // The factory deploys its approved identity wasm with both the source
+        // account and the wallet as management keys, so only the wallet and which
+        // of its identities to deploy are passed. The source account must be the
+        // owner or an identity creator and can then sign add_key and add_claim
         // const walletAddress = "GCKDZSO5Z2XLD4LJSA67ER3YSRBHYGRZN2PTANPK25THWKB72T3S5XSB";
-        const saltHex = generateSalt();
-        console.log('Generated Salt:', saltHex);
-        const initFunction = "initialize";
-        const initArgs = [
-            { type: "address", value: sourceAccount.accountId() }
-            // Replace this with the correct xdr format for your arguments
-        ];
+        const identityIndex = 0;
 
 
         // Prepare parameters using ScVal and ScAddress
-        const wasmHashParam = xdr.ScVal.scvBytes(Buffer.from(wasmHashHex, 'hex'));
-
         const walletPublicKey = StrKey.decodeEd25519PublicKey(walletAddress);
         const walletAddressParam = xdr.ScAddress.scAddressTypeAccount(new xdr.PublicKey.publicKeyTypeEd25519(walletPublicKey));
         const walletParam = xdr.ScVal.scvAddress(walletAddressParam);
 
-        const saltParam = xdr.ScVal.scvBytes(Buffer.from(saltHex, 'hex'));
-        const initFnParam = xdr.ScVal.scvSymbol(initFunction);
+        const indexParam = xdr.ScVal.scvU32(identityIndex);
 
-        // Prepare initArgs (conversion required to xdr format)
-        const initArgsParams = xdr.ScVal.scvVec(
-            initArgs.map(arg => {
-                if (arg.type === "address") {
-                    const publicKey = StrKey.decodeEd25519PublicKey(arg.value);
-                    const scAddress = xdr.ScAddress.scAddressTypeAccount(new xdr.PublicKey.publicKeyTypeEd25519(publicKey));
-                    return xdr.ScVal.scvAddress(scAddress);
-                }
-                throw new Error(`Unsupported argument type ${arg.type}`);
-            })
-        );
+        const senderAddressParam = xdr.ScAddress.scAddressTypeAccount(new xdr.PublicKey.publicKeyTypeEd25519(sourceKeypair.rawPublicKey()));
+        const senderParam = xdr.ScVal.scvAddress(senderAddressParam);
 
         let builtTransaction = new TransactionBuilder(sourceAccount, {
             fee: BASE_FEE,
             networkPassphrase: Networks.TESTNET,
         })
             .addOperation(
-                contract.call("create_identity", wasmHashParam, walletParam, saltParam, initFnParam, initArgsParams)
+                contract.call("create_identity", senderParam, walletParam, indexParam)
 
             )
             .setTimeout(30)
@@ -44,7 +44,7 @@ async function getContractOutput() {
             networkPassphrase: Networks.TESTNET,
         })
             .addOperation(
-                contract.call("get_identity", walletParam)
+                contract.call("try_get_identity", walletParam)
             )
             .setTimeout(30)
             .build();
@@ -71,6 +71,9 @@ async function getContractOutput() {
                     let transactionMeta = getResponse.resultMetaXdr;
                     let returnValue = transactionMeta.v3().sorobanMeta().returnValue();
                     
+                    if (returnValue.switch() === xdr.ScValType.scvVoid()) {
+                        throw new Error('Wallet is not linked to an identity');
+                    }
                     buffer = returnValue.value()._value; 
                     
                     decodedAddress = StrKey.encodeContract(buffer); 
@@ -45,12 +45,12 @@
             networkPassphrase: Networks.TESTNET,
         })
             .addOperation(
-                contract.call("get_identity", walletParam)
+                contract.call("try_get_identity", walletParam)
             )
             .setTimeout(30)
             .build();
 
-            console.log(`get_identity builtTransactiongetIdentity=${getIdentity.toXDR()}`);
+            console.log(`try_get_identity builtTransactiongetIdentity=${getIdentity.toXDR()}`);
 
             console.log('Transaction builtgetIdentity:', getIdentity.toXDR());
             let prepareGetID = await server.prepareTransaction(getIdentity);
@@ -74,6 +74,9 @@
                     let transactionMeta = getResponse.resultMetaXdr;
                     let returnValue = transactionMeta.v3().sorobanMeta().returnValue();
                     
+                    if (returnValue.switch() === xdr.ScValType.scvVoid()) {
+                        throw new Error('Wallet is not linked to an identity');
+                    }
                     const buffer = returnValue.value()._value;
                     
     
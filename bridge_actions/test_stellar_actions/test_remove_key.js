@@ -43,7 +43,7 @@
             networkPassphrase: Networks.TESTNET,
         })
             .addOperation(
-                contract.call("get_identity", walletParam)
+                contract.call("try_get_identity", walletParam)
 
             )
             .setTimeout(30)
@@ -73,6 +73,9 @@
                     let transactionMeta = getResponse.resultMetaXdr;
                     let returnValue = transactionMeta.v3().sorobanMeta().returnValue();
                     
+                    if (returnValue.switch() === xdr.ScValType.scvVoid()) {
+                        throw new Error('Wallet is not linked to an identity');
+                    }
                     const buffer = returnValue.value()._value;
                     
     
@@ -0,0 +1,373 @@
+#![no_std]
+
+// Claim issuer for claims mirrored from EVM identities. A claim is written only
+// once `threshold` of the registered bridge validators have signed the source
+// event, so no single relayer key has to be trusted.
+
+use soroban_sdk::{contract, contractimpl, xdr::ToXdr, Address, Bytes, BytesN, Env, Vec, U256};
+
+use onchainid_common::events::{
+    publish_event, ClaimBridgedEvent, ClaimRevokedEvent, ThresholdSetEvent, ValidatorEvent,
+};
+use onchainid_common::{
+    get_schema_version, hash_claim, identity_require_auth, Error, Key, KeyManager, KeyPurpose,
+    CONTRACT_SCHEME,
+};
+
+mod identity {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/identity.wasm"
+    );
+}
+
+mod state;
+use state::{
+    BridgeAction, BridgeDataKey, BridgeMessage, BridgedClaim, SourceEvent, ValidatorSignature,
+};
+
+#[contract]
+pub struct BridgeClaimIssuerContract;
+
+#[contractimpl]
+impl BridgeClaimIssuerContract {
+    pub fn get_initialized(env: Env) -> Result<bool, Error> {
+        KeyManager::get_initialized(&env)
+    }
+
+    pub fn initialize(env: Env, initial_management_key: Address) -> Result<(), Error> {
+        KeyManager::initialize(&env, initial_management_key)
+    }
+
+    pub fn get_schema_version(env: Env) -> u32 {
+        get_schema_version(&env)
+    }
+
+    pub fn get_key(env: Env, key: Address) -> Result<Key, Error> {
+        KeyManager::get_key(&env, key)
+    }
+
+    pub fn get_keys(env: Env) -> Result<Vec<Key>, Error> {
+        KeyManager::get_keys(&env)
+    }
+
+    pub fn add_key(
+        env: Env,
+        manager: Address,
+        key: Address,
+        purpose: u32,
+        key_type: u32,
+    ) -> Result<(), Error> {
+        KeyManager::add_key(&env, manager, key, purpose, key_type)
+    }
+
+    pub fn remove_key(env: Env, manager: Address, key: Address, purpose: u32) -> Result<(), Error> {
+        KeyManager::remove_key(&env, manager, key, purpose)
+    }
+
+    pub fn add_validator(env: Env, manager: Address, validator: BytesN<32>) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        let mut validators = get_validators(&env);
+        if validators.contains(&validator) {
+            return Err(Error::ValidatorConflict);
+        }
+        validators.push_back(validator.clone());
+
+        env.storage()
+            .persistent()
+            .set(&BridgeDataKey::Validators, &validators);
+
+        publish_event(
+            &env,
+            "validator_added",
+            &env.current_contract_address(),
+            ValidatorEvent { manager, validator },
+        );
+
+        Ok(())
+    }
+
+    // The threshold has to stay reachable, lower it before shrinking the set below it
+    pub fn remove_validator(
+        env: Env,
+        manager: Address,
+        validator: BytesN<32>,
+    ) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        let mut validators = get_validators(&env);
+        let pos = validators
+            .iter()
+            .position(|v| v == validator)
+            .ok_or(Error::ValidatorNotFound)?;
+
+        if validators.len() - 1 < get_threshold(&env) {
+            return Err(Error::InvalidThreshold);
+        }
+        validators.remove(pos as u32);
+
+        env.storage()
+            .persistent()
+            .set(&BridgeDataKey::Validators, &validators);
+
+        publish_event(
+            &env,
+            "validator_removed",
+            &env.current_contract_address(),
+            ValidatorEvent { manager, validator },
+        );
+
+        Ok(())
+    }
+
+    pub fn set_threshold(env: Env, manager: Address, threshold: u32) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        if threshold == 0 || threshold > get_validators(&env).len() {
+            return Err(Error::InvalidThreshold);
+        }
+
+        env.storage()
+            .persistent()
+            .set(&BridgeDataKey::Threshold, &threshold);
+
+        publish_event(
+            &env,
+            "threshold_set",
+            &env.current_contract_address(),
+            ThresholdSetEvent { manager, threshold },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_validators(env: Env) -> Result<Vec<BytesN<32>>, Error> {
+        Ok(get_validators(&env))
+    }
+
+    pub fn get_threshold(env: Env) -> Result<u32, Error> {
+        Ok(get_threshold(&env))
+    }
+
+    // The bytes each validator signs for `message`
+    pub fn get_message_digest(env: Env, message: BridgeMessage) -> Result<Bytes, Error> {
+        Ok(message_digest(&env, &message))
+    }
+
+    // Writes a claim seen in a ClaimAdded log into the identity, which must have
+    // approved this issuer. Anyone may submit, the validator signatures are the proof.
+    pub fn bridge_claim(
+        env: Env,
+        message: BridgeMessage,
+        signatures: Vec<ValidatorSignature>,
+    ) -> Result<BytesN<32>, Error> {
+        if message.action != BridgeAction::Add {
+            return Err(Error::InvalidClaim);
+        }
+        consume_source(&env, &message, &signatures)?;
+
+        let bridged = BridgedClaim {
+            source: message.source.clone(),
+            data_hash: env.crypto().keccak256(&message.data),
+        };
+        env.storage().persistent().set(
+            &BridgeDataKey::Bridged(message.identity.clone(), message.topic.clone()),
+            &bridged,
+        );
+
+        let client = identity::Client::new(&env, &message.identity);
+        let claim_id = client.add_claim_by_issuer(
+            &env.current_contract_address(),
+            &message.topic,
+            &message.data,
+            &message.uri,
+        );
+
+        publish_event(
+            &env,
+            "claim_bridged",
+            &message.identity,
+            ClaimBridgedEvent {
+                chain_id: message.source.chain_id,
+                tx_hash: message.source.tx_hash,
+                log_index: message.source.log_index,
+                claim_id: claim_id.clone(),
+                topic: message.topic,
+            },
+        );
+
+        Ok(claim_id)
+    }
+
+    // Invalidates a bridged claim after a ClaimRemoved log. Only the claim
+    // carrying the removed data is dropped, a newer one on the same topic stays.
+    pub fn unbridge_claim(
+        env: Env,
+        message: BridgeMessage,
+        signatures: Vec<ValidatorSignature>,
+    ) -> Result<(), Error> {
+        if message.action != BridgeAction::Remove {
+            return Err(Error::InvalidClaim);
+        }
+
+        let bridged_key = BridgeDataKey::Bridged(message.identity.clone(), message.topic.clone());
+        let bridged = env
+            .storage()
+            .persistent()
+            .get::<BridgeDataKey, BridgedClaim>(&bridged_key)
+            .ok_or(Error::BridgedClaimNotFound)?;
+        if bridged.data_hash != env.crypto().keccak256(&message.data) {
+            return Err(Error::BridgedClaimNotFound);
+        }
+
+        consume_source(&env, &message, &signatures)?;
+        env.storage().persistent().remove(&bridged_key);
+
+        publish_event(
+            &env,
+            "claim_revoked",
+            &message.identity,
+            ClaimRevokedEvent {
+                sender: env.current_contract_address(),
+                claim_id: hash_claim(&env, &env.current_contract_address(), &message.topic),
+            },
+        );
+
+        Ok(())
+    }
+
+    // Manual override for the management key, e.g. after a bad source chain reorg
+    pub fn revoke_claim(
+        env: Env,
+        sender: Address,
+        identity: Address,
+        topic: U256,
+    ) -> Result<(), Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Management)?;
+
+        let bridged_key = BridgeDataKey::Bridged(identity.clone(), topic.clone());
+        if !env.storage().persistent().has(&bridged_key) {
+            return Err(Error::BridgedClaimNotFound);
+        }
+        env.storage().persistent().remove(&bridged_key);
+
+        publish_event(
+            &env,
+            "claim_revoked",
+            &identity,
+            ClaimRevokedEvent {
+                sender,
+                claim_id: hash_claim(&env, &env.current_contract_address(), &topic),
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_bridged_claim(
+        env: Env,
+        identity: Address,
+        topic: U256,
+    ) -> Result<Option<BridgedClaim>, Error> {
+        Ok(env
+            .storage()
+            .persistent()
+            .get::<BridgeDataKey, BridgedClaim>(&BridgeDataKey::Bridged(identity, topic)))
+    }
+
+    pub fn is_source_used(env: Env, source: SourceEvent) -> Result<bool, Error> {
+        Ok(env
+            .storage()
+            .persistent()
+            .has(&BridgeDataKey::Source(source)))
+    }
+
+    // Bridged claims use the contract scheme: no signature, this contract as the
+    // issuer wallet, and valid while the registry still holds their data.
+    pub fn is_claim_valid(
+        env: &Env,
+        issuer_wallet: Address,
+        identity: Address,
+        topic: U256,
+        scheme: U256,
+        signature: Bytes,
+        data: Bytes,
+    ) -> Result<bool, Error> {
+        if issuer_wallet != env.current_contract_address()
+            || scheme != U256::from_u32(env, CONTRACT_SCHEME)
+            || !signature.is_empty()
+        {
+            return Ok(false);
+        }
+
+        Ok(
+            match env
+                .storage()
+                .persistent()
+                .get::<BridgeDataKey, BridgedClaim>(&BridgeDataKey::Bridged(identity, topic))
+            {
+                Some(bridged) => bridged.data_hash == env.crypto().keccak256(&data),
+                None => false,
+            },
+        )
+    }
+}
+
+fn get_validators(env: &Env) -> Vec<BytesN<32>> {
+    env.storage()
+        .persistent()
+        .get::<BridgeDataKey, Vec<BytesN<32>>>(&BridgeDataKey::Validators)
+        .unwrap_or(Vec::new(env))
+}
+
+fn get_threshold(env: &Env) -> u32 {
+    env.storage()
+        .persistent()
+        .get::<BridgeDataKey, u32>(&BridgeDataKey::Threshold)
+        .unwrap_or(0)
+}
+
+fn message_digest(env: &Env, message: &BridgeMessage) -> Bytes {
+    let mut concatenated_bytes = Bytes::new(env);
+    concatenated_bytes.append(&env.current_contract_address().to_xdr(env));
+    concatenated_bytes.append(&message.clone().to_xdr(env));
+
+    env.crypto().keccak256(&concatenated_bytes).to_xdr(env)
+}
+
+// Checks the committee signed `message` and marks its source event as used.
+// Unknown and repeated validators are skipped, a bad signature from a
+// registered validator traps like any other ed25519 check.
+fn consume_source(
+    env: &Env,
+    message: &BridgeMessage,
+    signatures: &Vec<ValidatorSignature>,
+) -> Result<(), Error> {
+    let source_key = BridgeDataKey::Source(message.source.clone());
+    if env.storage().persistent().has(&source_key) {
+        return Err(Error::SourceEventAlreadyBridged);
+    }
+
+    let validators = get_validators(env);
+    let digest = message_digest(env, message);
+
+    let mut signers: Vec<BytesN<32>> = Vec::new(env);
+    for signature in signatures.iter() {
+        if !validators.contains(&signature.validator) || signers.contains(&signature.validator) {
+            continue;
+        }
+        env.crypto()
+            .ed25519_verify(&signature.validator, &digest, &signature.signature);
+        signers.push_back(signature.validator);
+    }
+
+    let threshold = get_threshold(env);
+    if threshold == 0 || signers.len() < threshold {
+        return Err(Error::InsufficientSignatures);
+    }
+
+    env.storage().persistent().set(&source_key, &true);
+    Ok(())
+}
+
+mod test;
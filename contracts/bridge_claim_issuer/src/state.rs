@@ -0,0 +1,59 @@
+use soroban_sdk::{contracttype, Address, Bytes, BytesN, U256};
+
+// Bridge specific storage, shared entries live in onchainid_common::DataKey
+#[contracttype]
+#[derive(Clone)]
+pub enum BridgeDataKey {
+    // ed25519 public keys of the bridge validators
+    Validators,
+    Threshold,
+    // Set once a source event has been acted on, so it can't be replayed
+    Source(SourceEvent),
+    Bridged(Address, U256),
+}
+
+// The EVM log a claim was mirrored from
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceEvent {
+    pub chain_id: u64,
+    pub tx_hash: BytesN<32>,
+    pub log_index: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BridgeAction {
+    // A ClaimAdded log
+    Add = 1,
+    // A ClaimRemoved log
+    Remove = 2,
+}
+
+// What validators attest to. They sign keccak256(bridge XDR ‖ message XDR),
+// XDR-encoded again like claim digests.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BridgeMessage {
+    pub action: BridgeAction,
+    pub source: SourceEvent,
+    pub identity: Address,
+    pub topic: U256,
+    pub data: Bytes,
+    pub uri: Bytes,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidatorSignature {
+    pub validator: BytesN<32>,
+    pub signature: BytesN<64>,
+}
+
+// Registry entry for a claim written into an identity with `bridge_claim`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BridgedClaim {
+    pub source: SourceEvent,
+    pub data_hash: BytesN<32>,
+}
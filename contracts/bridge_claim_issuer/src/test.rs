@@ -0,0 +1,282 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use ed25519_dalek::{Signer, SigningKey};
+use soroban_sdk::{testutils::Address as _, vec, Address, Env};
+
+struct Bridge<'a> {
+    env: Env,
+    client: BridgeClaimIssuerContractClient<'a>,
+    contract_id: Address,
+    manager: Address,
+    validators: std::vec::Vec<SigningKey>,
+}
+
+// Bad signatures trap, which only surfaces as an error from a wasm instance
+mod bridge_claim_issuer {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/bridge_claim_issuer.wasm"
+    );
+}
+
+// A bridge with validators seeded 1..=n and the given threshold
+fn setup<'a>(n: u8, threshold: u32) -> Bridge<'a> {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, BridgeClaimIssuerContract);
+    deploy(env, contract_id, n, threshold)
+}
+
+fn deploy<'a>(env: Env, contract_id: Address, n: u8, threshold: u32) -> Bridge<'a> {
+    env.mock_all_auths();
+    let client = BridgeClaimIssuerContractClient::new(&env, &contract_id);
+
+    let manager = Address::generate(&env);
+    client.initialize(&manager);
+
+    let validators: std::vec::Vec<SigningKey> =
+        (1..=n).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+    for validator in &validators {
+        client.add_validator(&manager, &public_key(&env, validator));
+    }
+    client.set_threshold(&manager, &threshold);
+
+    Bridge {
+        env,
+        client,
+        contract_id,
+        manager,
+        validators,
+    }
+}
+
+fn public_key(env: &Env, key: &SigningKey) -> BytesN<32> {
+    BytesN::from_array(env, &key.verifying_key().to_bytes())
+}
+
+fn sign(bridge: &Bridge, message: &BridgeMessage, signers: &[usize]) -> Vec<ValidatorSignature> {
+    let digest: std::vec::Vec<u8> = bridge.client.get_message_digest(message).iter().collect();
+    let mut signatures = Vec::new(&bridge.env);
+    for i in signers {
+        let key = &bridge.validators[*i];
+        signatures.push_back(ValidatorSignature {
+            validator: public_key(&bridge.env, key),
+            signature: BytesN::from_array(&bridge.env, &key.sign(&digest).to_bytes()),
+        });
+    }
+    signatures
+}
+
+fn message(
+    env: &Env,
+    action: BridgeAction,
+    log_index: u64,
+    identity: &Address,
+    data: &str,
+) -> BridgeMessage {
+    BridgeMessage {
+        action,
+        source: SourceEvent {
+            chain_id: 1,
+            tx_hash: BytesN::from_array(env, &[0xaa; 32]),
+            log_index,
+        },
+        identity: identity.clone(),
+        topic: U256::from_u32(env, 1),
+        data: Bytes::from_slice(env, data.as_bytes()),
+        uri: Bytes::from_slice(env, "https://issuer.example/1".as_bytes()),
+    }
+}
+
+// An identity owned by the bridge's manager that approved the bridge
+fn approved_identity(bridge: &Bridge) -> (Address, identity::Client<'static>) {
+    let identity_id = bridge.env.register_contract_wasm(None, identity::WASM);
+    let identity = identity::Client::new(&bridge.env, &identity_id);
+    identity.initialize(&bridge.manager);
+    identity.approve_issuer(&bridge.manager, &bridge.contract_id, &vec![&bridge.env]);
+    (identity_id, identity)
+}
+
+#[test]
+fn test_validator_set() {
+    let bridge = setup(3, 2);
+    let client = &bridge.client;
+    let env = &bridge.env;
+
+    assert_eq!(client.get_validators().len(), 3);
+    assert_eq!(client.get_threshold(), 2);
+
+    let first = public_key(env, &bridge.validators[0]);
+    assert_eq!(
+        client.try_add_validator(&bridge.manager, &first),
+        Err(Ok(Error::ValidatorConflict))
+    );
+    assert_eq!(
+        client.try_set_threshold(&bridge.manager, &0),
+        Err(Ok(Error::InvalidThreshold))
+    );
+    assert_eq!(
+        client.try_set_threshold(&bridge.manager, &4),
+        Err(Ok(Error::InvalidThreshold))
+    );
+
+    // Removing down to the threshold is fine, below it is not
+    client.remove_validator(&bridge.manager, &first);
+    let second = public_key(env, &bridge.validators[1]);
+    assert_eq!(
+        client.try_remove_validator(&bridge.manager, &second),
+        Err(Ok(Error::InvalidThreshold))
+    );
+    assert_eq!(
+        client.try_remove_validator(&bridge.manager, &first),
+        Err(Ok(Error::ValidatorNotFound))
+    );
+}
+
+#[test]
+fn test_bridge_claim() {
+    let bridge = setup(3, 2);
+    let client = &bridge.client;
+    let env = &bridge.env;
+    let (identity_id, identity) = approved_identity(&bridge);
+
+    let message = message(env, BridgeAction::Add, 0, &identity_id, "kyc");
+
+    // One signature, the same validator twice or an outsider do not reach 2 of 3
+    let outsider = SigningKey::from_bytes(&[9; 32]);
+    let mut with_outsider = sign(&bridge, &message, &[0]);
+    let digest: std::vec::Vec<u8> = client.get_message_digest(&message).iter().collect();
+    with_outsider.push_back(ValidatorSignature {
+        validator: public_key(env, &outsider),
+        signature: BytesN::from_array(env, &outsider.sign(&digest).to_bytes()),
+    });
+    for signatures in [
+        sign(&bridge, &message, &[0]),
+        sign(&bridge, &message, &[0, 0]),
+        with_outsider,
+    ] {
+        assert_eq!(
+            client.try_bridge_claim(&message, &signatures),
+            Err(Ok(Error::InsufficientSignatures))
+        );
+    }
+
+    let claim_id = client.bridge_claim(&message, &sign(&bridge, &message, &[0, 2]));
+    assert_eq!(
+        claim_id,
+        hash_claim(env, &bridge.contract_id, &message.topic)
+    );
+    assert!(client.is_source_used(&message.source));
+
+    // The identity holds a contract scheme claim this issuer vouches for
+    let claim = identity.get_claim(&claim_id).unwrap();
+    assert_eq!(claim.issuer, bridge.contract_id);
+    assert!(client.is_claim_valid(
+        &claim.issuer_wallet,
+        &identity_id,
+        &claim.topic,
+        &claim.scheme,
+        &claim.signature,
+        &claim.data
+    ));
+    assert!(!client.is_claim_valid(
+        &claim.issuer_wallet,
+        &identity_id,
+        &claim.topic,
+        &claim.scheme,
+        &claim.signature,
+        &Bytes::from_slice(env, "other".as_bytes())
+    ));
+    assert!(!client.is_claim_valid(
+        &claim.issuer_wallet,
+        &identity_id,
+        &claim.topic,
+        &U256::from_u32(env, 1),
+        &claim.signature,
+        &claim.data
+    ));
+
+    // A source event is only ever bridged once
+    assert_eq!(
+        client.try_bridge_claim(&message, &sign(&bridge, &message, &[0, 1])),
+        Err(Ok(Error::SourceEventAlreadyBridged))
+    );
+}
+
+#[test]
+fn test_signatures_bound_to_message() {
+    let env = Env::default();
+    let contract_id = env.register_contract_wasm(None, bridge_claim_issuer::WASM);
+    let bridge = deploy(env, contract_id, 3, 2);
+    let client = &bridge.client;
+    let env = &bridge.env;
+    let (identity_id, _) = approved_identity(&bridge);
+
+    let message = message(env, BridgeAction::Add, 0, &identity_id, "kyc");
+    let mut forged = message.clone();
+    forged.source.log_index = 1;
+    forged.data = Bytes::from_slice(env, "forged".as_bytes());
+    assert!(client
+        .try_bridge_claim(&forged, &sign(&bridge, &message, &[0, 1]))
+        .is_err());
+    assert!(!client.is_source_used(&forged.source));
+}
+
+#[test]
+fn test_unbridge_and_revoke() {
+    let bridge = setup(2, 2);
+    let client = &bridge.client;
+    let env = &bridge.env;
+    let (identity_id, identity) = approved_identity(&bridge);
+
+    let added = message(env, BridgeAction::Add, 0, &identity_id, "kyc");
+    let claim_id = client.bridge_claim(&added, &sign(&bridge, &added, &[0, 1]));
+    let claim = identity.get_claim(&claim_id).unwrap();
+
+    // An add attestation can't be used for a removal
+    assert_eq!(
+        client.try_bridge_claim(
+            &message(env, BridgeAction::Remove, 1, &identity_id, "kyc"),
+            &sign(&bridge, &added, &[0, 1])
+        ),
+        Err(Ok(Error::InvalidClaim))
+    );
+
+    // Removing data that is not the bridged claim's leaves it in place
+    let stale = message(env, BridgeAction::Remove, 1, &identity_id, "kyc-v0");
+    assert_eq!(
+        client.try_unbridge_claim(&stale, &sign(&bridge, &stale, &[0, 1])),
+        Err(Ok(Error::BridgedClaimNotFound))
+    );
+
+    let removed = message(env, BridgeAction::Remove, 2, &identity_id, "kyc");
+    client.unbridge_claim(&removed, &sign(&bridge, &removed, &[0, 1]));
+    assert!(client
+        .get_bridged_claim(&identity_id, &claim.topic)
+        .is_none());
+    assert!(!client.is_claim_valid(
+        &claim.issuer_wallet,
+        &identity_id,
+        &claim.topic,
+        &claim.scheme,
+        &claim.signature,
+        &claim.data
+    ));
+
+    // The management key can revoke without the committee
+    let readded = message(env, BridgeAction::Add, 3, &identity_id, "kyc");
+    client.bridge_claim(&readded, &sign(&bridge, &readded, &[0, 1]));
+    client.revoke_claim(&bridge.manager, &identity_id, &claim.topic);
+    assert!(!client.is_claim_valid(
+        &claim.issuer_wallet,
+        &identity_id,
+        &claim.topic,
+        &claim.scheme,
+        &claim.signature,
+        &claim.data
+    ));
+    assert_eq!(
+        client.try_revoke_claim(&bridge.manager, &identity_id, &claim.topic),
+        Err(Ok(Error::BridgedClaimNotFound))
+    );
+}
@@ -1,7 +1,18 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, log, symbol_short, vec, xdr::ToXdr, Address, Bytes, BytesN, Env,
-    FromVal, Symbol, Vec, U256,
+    contract, contractclient, contractimpl, Address, Bytes, BytesN, Env, String, Symbol, Vec, U256,
+};
+
+use onchainid_common::events::{
+    publish_event, ClaimIssuedEvent, ClaimRevokedEvent, DelegationAddedEvent,
+    DelegationRevokedEvent, KeyScopeRemovedEvent, KeyScopeSetEvent, MetadataSetEvent,
+    StatusRegisteredEvent, StatusRevokedEvent, TopicSupportedEvent, TopicUnsupportedEvent,
+    UpgradedEvent,
+};
+use onchainid_common::{
+    get_schema_version, hash_key, identity_require_auth, key_has_purpose, migrate_legacy,
+    verify_claim_signature, Claim, ClaimHolder, Error, Key, KeyManager, KeyPurpose,
+    CONTRACT_SCHEME,
 };
 
 mod identity {
@@ -9,14 +20,31 @@ mod identity {
         file = "../../target/wasm32-unknown-unknown/release/identity.wasm"
     );
 }
-mod claim_issuer {
-    soroban_sdk::contractimport!(
-        file = "../../target/wasm32-unknown-unknown/release/claim_issuer.wasm"
-    );
-}
 
 mod state;
-use state::{Claim, Error, Key, KeyPurpose, KeyType};
+use state::{Delegation, IssuedClaim, IssuerDataKey, IssuerMetadata, KeyScope, SupportedTopic};
+
+// How many sub-issuer links `is_claim_valid` will follow below this issuer
+const MAX_DELEGATION_DEPTH: u32 = 3;
+
+// What a parent issuer calls on its sub-issuers while walking the delegation chain
+#[contractclient(name = "SubIssuerClient")]
+pub trait SubIssuer {
+    fn is_delegated_claim_valid(
+        env: Env,
+        issuer_wallet: Address,
+        identity: Address,
+        topic: U256,
+        scheme: U256,
+        signature: Bytes,
+        data: Bytes,
+        depth: u32,
+    ) -> Result<bool, Error>;
+}
+
+// Bits per status list, index 0 is the most significant bit of the first byte
+// as in the W3C status list format
+const STATUS_LIST_SIZE: u32 = 8192;
 
 #[contract]
 pub struct ClaimIssuerContract;
@@ -24,282 +52,472 @@ pub struct ClaimIssuerContract;
 #[contractimpl]
 impl ClaimIssuerContract {
     pub fn get_initialized(env: Env) -> Result<bool, Error> {
-        Ok(env
-            .storage()
-            .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false))
+        KeyManager::get_initialized(&env)
     }
 
     pub fn initialize(env: Env, initial_management_key: Address) -> Result<(), Error> {
-        let init_symbol = Symbol::new(&env, "initialized");
+        KeyManager::initialize(&env, initial_management_key)
+    }
+
+    pub fn get_schema_version(env: Env) -> u32 {
+        get_schema_version(&env)
+    }
+
+    // Moves an issuer deployed before schema versioning to the typed layout
+    pub fn migrate(env: Env, manager: Address) -> Result<(), Error> {
+        migrate_legacy(&env, &manager)?;
 
-        let initialized = env
+        let revoked_symbol = Symbol::new(&env, "revoked_claims");
+        if let Some(revoked) = env
             .storage()
-            .instance()
-            .get::<Symbol, bool>(&init_symbol)
-            .unwrap_or(false);
+            .persistent()
+            .get::<Symbol, Vec<Bytes>>(&revoked_symbol)
+        {
+            env.storage().persistent().remove(&revoked_symbol);
+            env.storage()
+                .persistent()
+                .set(&IssuerDataKey::RevokedClaims, &revoked);
+        }
+
+        Ok(())
+    }
+
+    // Replaces the contract code, signed by a management key. The new code takes over
+    // once this invocation has finished.
+    pub fn upgrade(env: Env, manager: Address, wasm_hash: BytesN<32>) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        env.deployer()
+            .update_current_contract_wasm(wasm_hash.clone());
+
+        publish_event(
+            &env,
+            "upgraded",
+            &env.current_contract_address(),
+            UpgradedEvent {
+                authority: manager,
+                wasm_hash,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get_key(env: Env, key: Address) -> Result<Key, Error> {
+        KeyManager::get_key(&env, key)
+    }
+
+    pub fn get_keys(env: Env) -> Result<Vec<Key>, Error> {
+        KeyManager::get_keys(&env)
+    }
+
+    pub fn add_key(
+        env: Env,
+        manager: Address,
+        key: Address,
+        purpose: u32,
+        key_type: u32,
+    ) -> Result<(), Error> {
+        KeyManager::add_key(&env, manager, key, purpose, key_type)
+    }
+
+    pub fn remove_key(env: Env, manager: Address, key: Address, purpose: u32) -> Result<(), Error> {
+        KeyManager::remove_key(&env, manager, key.clone(), purpose)?;
 
-        if initialized {
-            return Err(Error::AlreadyInitialized);
+        // A scope only makes sense for a Claim key, drop it with the purpose
+        if purpose == KeyPurpose::Claim as u32 {
+            env.storage()
+                .persistent()
+                .remove(&IssuerDataKey::Scope(hash_key(&env, &key)));
         }
-        env.storage().instance().set(&init_symbol, &true);
 
-        let key_hash = hash_key(&env, &initial_management_key);
-        let key = Key {
-            purposes: vec![&env, KeyPurpose::Management],
-            key_type: KeyType::ECDSA,
-            key: key_hash.clone(),
-        };
+        Ok(())
+    }
 
-        let keys = vec![&env, key];
+    pub fn set_key_scope(
+        env: Env,
+        manager: Address,
+        key: Address,
+        topics: Vec<U256>,
+        schemes: Vec<U256>,
+    ) -> Result<(), Error> {
+        // Only the manager can change what a key may sign
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        let key_hash = hash_key(&env, &key);
+        if !key_has_purpose(&env, &key_hash, KeyPurpose::Claim) {
+            return Err(Error::KeyDoesNotHavePurpose);
+        }
+
+        let scope = KeyScope {
+            topics: topics.clone(),
+            schemes: schemes.clone(),
+        };
         env.storage()
             .persistent()
-            .set(&symbol_short!("keys"), &keys);
+            .set(&IssuerDataKey::Scope(key_hash), &scope);
 
-        log!(
+        publish_event(
             &env,
-            "Identity contract initialized with management key: {:?}",
-            initial_management_key
+            "key_scope_set",
+            &env.current_contract_address(),
+            KeyScopeSetEvent {
+                manager,
+                key,
+                topics,
+                schemes,
+            },
         );
 
-        env.events().publish((init_symbol,), initial_management_key);
         Ok(())
     }
 
-    pub fn get_key(env: Env, key: Address) -> Result<Key, Error> {
+    pub fn remove_key_scope(env: Env, manager: Address, key: Address) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        let scope_key = IssuerDataKey::Scope(hash_key(&env, &key));
+        if !env.storage().persistent().has(&scope_key) {
+            return Err(Error::KeyScopeNotFound);
+        }
+        env.storage().persistent().remove(&scope_key);
+
+        publish_event(
+            &env,
+            "key_scope_removed",
+            &env.current_contract_address(),
+            KeyScopeRemovedEvent { manager, key },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_key_scope(env: Env, key: Address) -> Result<Option<KeyScope>, Error> {
+        Ok(env
+            .storage()
+            .persistent()
+            .get::<IssuerDataKey, KeyScope>(&IssuerDataKey::Scope(hash_key(&env, &key))))
+    }
+
+    // Whether `key` is a Claim key allowed to sign `topic` with `scheme`
+    pub fn is_key_allowed(
+        env: Env,
+        key: Address,
+        topic: U256,
+        scheme: U256,
+    ) -> Result<bool, Error> {
         let key_hash = hash_key(&env, &key);
-        let keys = env
+
+        Ok(key_has_purpose(&env, &key_hash, KeyPurpose::Claim)
+            && key_scope_allows(&env, &key_hash, &topic, &scheme))
+    }
+
+    pub fn set_metadata(env: Env, manager: Address, metadata: IssuerMetadata) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        env.storage()
+            .persistent()
+            .set(&IssuerDataKey::Metadata, &metadata);
+
+        publish_event(
+            &env,
+            "metadata_set",
+            &env.current_contract_address(),
+            MetadataSetEvent {
+                manager,
+                name: metadata.name,
+                jurisdiction: metadata.jurisdiction,
+                uri: metadata.uri,
+                contact: metadata.contact,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_metadata(env: Env) -> Result<Option<IssuerMetadata>, Error> {
+        Ok(env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
-            .unwrap_or(Vec::new(&env));
+            .get::<IssuerDataKey, IssuerMetadata>(&IssuerDataKey::Metadata))
+    }
 
-        keys.iter()
-            .find(|k| k.key == key_hash)
-            .clone()
-            .ok_or(Error::KeyNotFound)
+    // Declares `topic` as issued by us, or updates its schema reference
+    pub fn set_supported_topic(
+        env: Env,
+        manager: Address,
+        topic: U256,
+        schema: String,
+    ) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        let mut topics = Self::get_supported_topics(env.clone())?;
+        let supported = SupportedTopic {
+            topic: topic.clone(),
+            schema: schema.clone(),
+        };
+
+        match topics.iter().position(|t| t.topic == topic) {
+            Some(pos) => topics.set(pos as u32, supported),
+            None => topics.push_back(supported),
+        }
+
+        env.storage()
+            .persistent()
+            .set(&IssuerDataKey::Topics, &topics);
+
+        publish_event(
+            &env,
+            "topic_supported",
+            &env.current_contract_address(),
+            TopicSupportedEvent {
+                manager,
+                topic,
+                schema,
+            },
+        );
+
+        Ok(())
     }
 
-    pub fn get_keys(env: Env) -> Result<Vec<Key>, Error> {
+    pub fn remove_supported_topic(env: Env, manager: Address, topic: U256) -> Result<(), Error> {
+        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
+
+        let mut topics = Self::get_supported_topics(env.clone())?;
+        let pos = topics
+            .iter()
+            .position(|t| t.topic == topic)
+            .ok_or(Error::TopicNotSupported)?;
+        topics.remove(pos as u32);
+
+        env.storage()
+            .persistent()
+            .set(&IssuerDataKey::Topics, &topics);
+
+        publish_event(
+            &env,
+            "topic_unsupported",
+            &env.current_contract_address(),
+            TopicUnsupportedEvent { manager, topic },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_supported_topics(env: Env) -> Result<Vec<SupportedTopic>, Error> {
         Ok(env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
+            .get::<IssuerDataKey, Vec<SupportedTopic>>(&IssuerDataKey::Topics)
             .unwrap_or(Vec::new(&env)))
     }
 
-    pub fn add_key(
+    pub fn supports_topic(env: Env, topic: U256) -> Result<bool, Error> {
+        Ok(Self::get_supported_topics(env)?
+            .iter()
+            .any(|t| t.topic == topic))
+    }
+
+    pub fn add_delegation(
         env: Env,
         manager: Address,
-        key: Address,
-        purpose: u32,
-        key_type: u32,
+        sub_issuer: Address,
+        topics: Vec<U256>,
+        expiry: u64,
     ) -> Result<(), Error> {
-        // Only the manager can add keys
         identity_require_auth(&env, &manager, KeyPurpose::Management)?;
 
-        // Make a Kecak256 hash of the key
-        let key_hash = hash_key(&env, &key);
-        let key_purpose = KeyPurpose::try_from(purpose).map_err(|_| Error::InvalidKeyPurpose)?;
+        if sub_issuer == env.current_contract_address() || expiry <= env.ledger().timestamp() {
+            return Err(Error::InvalidDelegation);
+        }
 
-        // Retrieve and mutate the list of keys
-        let mut keys: Vec<Key> = env
+        let mut delegations = env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
+            .get::<IssuerDataKey, Vec<Delegation>>(&IssuerDataKey::Delegations)
             .unwrap_or(Vec::new(&env));
 
-        let mut key_found = false;
-
-        for i in 0..keys.len() {
-            let mut k = keys.get(i).ok_or(Error::IndexOutOfBounds)?;
-            if k.key == key_hash {
-                if k.purposes.contains(&key_purpose) {
-                    return Err(Error::KeyConflict);
-                } else {
-                    k.purposes.push_back(key_purpose);
-                    keys.set(i, k);
-                    key_found = true;
-                    break;
+        let delegation = Delegation {
+            sub_issuer: sub_issuer.clone(),
+            topics: topics.clone(),
+            expiry,
+            revoked: false,
+        };
+
+        // A revoked delegation can be replaced, an active one has to be revoked first
+        match delegations.iter().position(|d| d.sub_issuer == sub_issuer) {
+            Some(pos) => {
+                if !delegations
+                    .get(pos as u32)
+                    .ok_or(Error::IndexOutOfBounds)?
+                    .revoked
+                {
+                    return Err(Error::DelegationConflict);
                 }
+                delegations.set(pos as u32, delegation);
             }
+            None => delegations.push_back(delegation),
         }
 
-        if !key_found {
-            let key = Key {
-                purposes: vec![&env, key_purpose],
-                key_type: KeyType::try_from(key_type).map_err(|_| Error::InvalidKeyType)?,
-                key: key_hash.clone(),
-            };
-            keys.push_back(key);
-        }
         env.storage()
             .persistent()
-            .set(&symbol_short!("keys"), &keys);
+            .set(&IssuerDataKey::Delegations, &delegations);
 
-        env.events().publish(
-            (symbol_short!("add_key"),),
-            (manager, key, purpose, key_type),
+        publish_event(
+            &env,
+            "delegation_added",
+            &env.current_contract_address(),
+            DelegationAddedEvent {
+                manager,
+                sub_issuer,
+                topics,
+                expiry,
+            },
         );
 
         Ok(())
     }
 
-    pub fn remove_key(env: Env, manager: Address, key: Address, purpose: u32) -> Result<(), Error> {
-        // Only the manager can remove keys
+    pub fn revoke_delegation(env: Env, manager: Address, sub_issuer: Address) -> Result<(), Error> {
         identity_require_auth(&env, &manager, KeyPurpose::Management)?;
 
-        // Make a Kecak256 hash of the key
-        let key_hash = hash_key(&env, &key);
-        let key_purpose = KeyPurpose::try_from(purpose).map_err(|_| Error::InvalidKeyPurpose)?;
-
-        // Retrieve and mutate the list of keys
-        let mut keys: Vec<Key> = env
+        let mut delegations = env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
+            .get::<IssuerDataKey, Vec<Delegation>>(&IssuerDataKey::Delegations)
             .unwrap_or(Vec::new(&env));
 
-        if !keys.iter().any(|k| k.key == key_hash) {
-            return Err(Error::KeyNotFound);
-        }
+        let pos = delegations
+            .iter()
+            .position(|d| d.sub_issuer == sub_issuer)
+            .ok_or(Error::DelegationNotFound)? as u32;
 
-        for i in 0..keys.len() {
-            if let Some(mut k) = keys.get(i) {
-                if k.key == key_hash {
-                    if let Some(pos) = k.purposes.iter().position(|p| p == key_purpose) {
-                        k.purposes.remove(pos as u32);
-
-                        if k.purposes.is_empty() {
-                            keys.remove(i as u32);
-                        } else {
-                            keys.set(i, k);
-                        }
-                    } else {
-                        return Err(Error::KeyDoesNotHavePurpose);
-                    }
-                    break;
-                }
-            } else {
-                return Err(Error::IndexOutOfBounds);
-            }
+        let mut delegation = delegations.get(pos).ok_or(Error::IndexOutOfBounds)?;
+        if delegation.revoked {
+            return Err(Error::DelegationAlreadyRevoked);
         }
+        delegation.revoked = true;
+        delegations.set(pos, delegation);
 
         env.storage()
             .persistent()
-            .set(&symbol_short!("keys"), &keys);
+            .set(&IssuerDataKey::Delegations, &delegations);
 
-        env.events()
-            .publish((Symbol::new(&env, "remove_key"),), (manager, key, purpose));
+        publish_event(
+            &env,
+            "delegation_revoked",
+            &env.current_contract_address(),
+            DelegationRevokedEvent {
+                manager,
+                sub_issuer,
+            },
+        );
 
         Ok(())
     }
 
-    pub fn get_claim(env: Env, claim_id: BytesN<32>) -> Result<Option<Claim>, Error> {
-        Ok(env
-            .storage()
-            .persistent()
-            .get::<BytesN<32>, Claim>(&claim_id))
+    pub fn get_delegation(env: Env, sub_issuer: Address) -> Result<Option<Delegation>, Error> {
+        Ok(Self::get_delegations(env)?
+            .iter()
+            .find(|d| d.sub_issuer == sub_issuer))
     }
 
-    pub fn get_claim_ids(env: Env) -> Result<Vec<BytesN<32>>, Error> {
+    pub fn get_delegations(env: Env) -> Result<Vec<Delegation>, Error> {
         Ok(env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
+            .get::<IssuerDataKey, Vec<Delegation>>(&IssuerDataKey::Delegations)
             .unwrap_or(Vec::new(&env)))
     }
 
-    pub fn add_claim(
+    // Writes a claim straight into `identity`, which must have approved this issuer.
+    // No off-chain signature is involved, validity comes from our own registry.
+    pub fn issue_claim(
         env: Env,
         sender: Address,
+        identity: Address,
         topic: U256,
-        scheme: U256,
-        issuer_wallet: Address,
-        issuer: Address,
-        signature: Bytes,
         data: Bytes,
         uri: Bytes,
     ) -> Result<BytesN<32>, Error> {
         identity_require_auth(&env, &sender, KeyPurpose::Claim)?;
 
-        let current_contact = env.current_contract_address();
-
-        if current_contact != issuer {
-            let client = claim_issuer::Client::new(&env, &issuer);
-            if !client.is_claim_valid(&issuer_wallet, &current_contact, &topic, &signature, &data) {
-                return Err(Error::InvalidClaim);
-            }
+        let key_hash = hash_key(&env, &sender);
+        let scheme = U256::from_u32(&env, CONTRACT_SCHEME);
+        if !key_scope_allows(&env, &key_hash, &topic, &scheme) {
+            return Err(Error::InsufficientPermissions);
         }
 
-        let claim_id = hash_claim(&env, &issuer, &topic);
-        let claim: Claim = Claim {
-            topic,
-            scheme,
-            issuer,
-            issuer_wallet,
-            signature,
-            data,
-            uri,
-        };
-        env.storage().persistent().set(&claim_id, &claim);
-
-        let mut claims = env
+        // Re-issuing replaces the claim in the identity, so the status index
+        // handed out for the previous one is revoked
+        let issued_key = IssuerDataKey::Issued(identity.clone(), topic.clone());
+        if let Some(previous) = env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
-            .unwrap_or(Vec::new(&env));
-
-        claims.push_back(claim_id.clone());
+            .get::<IssuerDataKey, IssuedClaim>(&issued_key)
+        {
+            if !status_bit(&env, previous.status_index) {
+                set_status_bit(&env, previous.status_index)?;
+            }
+        }
 
-        env.storage()
-            .persistent()
-            .set(&symbol_short!("claims"), &claims);
+        let issued = IssuedClaim {
+            data_hash: env.crypto().keccak256(&data),
+            status_index: assign_status_index(&env),
+        };
+        env.storage().persistent().set(&issued_key, &issued);
 
-        log!(&env, "Claim added: {:?}", claim);
+        let client = identity::Client::new(&env, &identity);
+        let claim_id =
+            client.add_claim_by_issuer(&env.current_contract_address(), &topic, &data, &uri);
 
-        env.events().publish(
-            (symbol_short!("add_claim"),),
-            (sender, claim_id.clone(), claim.topic, claim.scheme, claim.issuer, claim.issuer_wallet,claim.signature, claim.data, claim.uri)
+        publish_event(
+            &env,
+            "claim_issued",
+            &identity,
+            ClaimIssuedEvent {
+                sender,
+                claim_id: claim_id.clone(),
+                topic,
+                status_index: issued.status_index,
+            },
         );
 
         Ok(claim_id)
     }
 
-    pub fn remove_claim(env: Env, sender: Address, claim_id: BytesN<32>) -> Result<(), Error> {
-        identity_require_auth(&env, &sender, KeyPurpose::Claim)?;
-
-        let claim = env
-            .storage()
-            .persistent()
-            .get::<BytesN<32>, Claim>(&claim_id)
-            .ok_or(Error::ClaimNotFound)?;
-
-        env.storage().persistent().remove(&claim_id);
-
-        let mut claims = env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
-            .unwrap_or(Vec::new(&env));
-
-        if let Some(pos) = claims.iter().position(|c| c == claim_id) {
-            claims.remove(pos as u32);
-        }
-
-        env.storage()
-            .persistent()
-            .set(&symbol_short!("claims"), &claims);
+    pub fn get_claim(env: Env, claim_id: BytesN<32>) -> Result<Option<Claim>, Error> {
+        ClaimHolder::get_claim(&env, claim_id)
+    }
 
-        log!(&env, "Claim removed: {:?}", claim);
+    pub fn get_claim_ids(env: Env) -> Result<Vec<BytesN<32>>, Error> {
+        ClaimHolder::get_claim_ids(&env)
+    }
 
-        env.events().publish(
-            (Symbol::new(&env, "remove_claim"),),
-            (sender, claim_id)
-        );
+    pub fn add_claim(
+        env: Env,
+        sender: Address,
+        topic: U256,
+        scheme: U256,
+        issuer_wallet: Address,
+        issuer: Address,
+        signature: Bytes,
+        data: Bytes,
+        uri: Bytes,
+    ) -> Result<BytesN<32>, Error> {
+        ClaimHolder::add_claim(
+            &env,
+            sender,
+            topic,
+            scheme,
+            issuer_wallet,
+            issuer,
+            signature,
+            data,
+            uri,
+        )
+    }
 
-        Ok(())
+    pub fn remove_claim(env: Env, sender: Address, claim_id: BytesN<32>) -> Result<(), Error> {
+        ClaimHolder::remove_claim(&env, sender, claim_id)
     }
 
     pub fn is_claim_valid(
@@ -307,35 +525,45 @@ impl ClaimIssuerContract {
         issuer_wallet: Address,
         identity: Address,
         topic: U256,
+        scheme: U256,
         signature: Bytes,
         data: Bytes,
     ) -> Result<bool, Error> {
-        let mut concatenated_bytes = Bytes::new(env);
-        concatenated_bytes.append(&identity.clone().to_xdr(&env));
-        concatenated_bytes.append(&topic.to_xdr(&env));
-        concatenated_bytes.append(&data);
-
-        let data_digest = env.crypto().keccak256(&concatenated_bytes).to_xdr(&env);
-
-        let signature_slice: BytesN<64> = match signature.slice(..64).try_into() {
-            Ok(slice) => slice,
-            Err(_) => return Err(Error::InvalidSignature),
-        };
-
-        let issuer_xdr = issuer_wallet.clone().to_xdr(env);
-
-        let issuer_bytes: BytesN<32> = match issuer_xdr.slice(12..44).try_into() {
-            Ok(slice) => slice,
-            Err(_) => return Err(Error::InvalidAddressBytes),
-        };
-
-        env.crypto()
-            .ed25519_verify(&issuer_bytes, &data_digest, &signature_slice);
-
-        let hashed_addr = hash_key(env, &issuer_wallet);
+        claim_valid_at_depth(
+            env,
+            issuer_wallet,
+            identity,
+            topic,
+            scheme,
+            signature,
+            data,
+            MAX_DELEGATION_DEPTH,
+        )
+    }
 
-        Ok(key_has_purpose(env, &hashed_addr, KeyPurpose::Claim)
-            && !Self::is_claim_revoked(env, signature)?)
+    // Only meant for parent issuers walking the delegation chain, with `depth`
+    // links left below this issuer. Clamped, so calling it directly doesn't
+    // follow more links than `is_claim_valid` would.
+    pub fn is_delegated_claim_valid(
+        env: Env,
+        issuer_wallet: Address,
+        identity: Address,
+        topic: U256,
+        scheme: U256,
+        signature: Bytes,
+        data: Bytes,
+        depth: u32,
+    ) -> Result<bool, Error> {
+        claim_valid_at_depth(
+            &env,
+            issuer_wallet,
+            identity,
+            topic,
+            scheme,
+            signature,
+            data,
+            depth.min(MAX_DELEGATION_DEPTH),
+        )
     }
 
     pub fn revoke_claim(
@@ -349,77 +577,308 @@ impl ClaimIssuerContract {
 
         let claim = client.get_claim(&claim_id).ok_or(Error::ClaimNotFound)?;
 
-        let revoked_symbol = Symbol::new(&env, "revoked_claims");
+        // Same test as validation: the holder picks the scheme, only claims we
+        // wrote ourselves come without a signature
+        if claim.signature.is_empty() {
+            // Claims we wrote ourselves are revoked by dropping them from the registry
+            let issued_key = IssuerDataKey::Issued(contract.clone(), claim.topic);
+            let issued = env
+                .storage()
+                .persistent()
+                .get::<IssuerDataKey, IssuedClaim>(&issued_key)
+                .ok_or(Error::ClaimAlreadyRevoked)?;
+
+            if !status_bit(&env, issued.status_index) {
+                set_status_bit(&env, issued.status_index)?;
+            }
+            env.storage().persistent().remove(&issued_key);
+        } else {
+            // Keep the status list in line with the signature list
+            if let Some(index) = get_status_index(&env, &claim.signature) {
+                if !status_bit(&env, index) {
+                    set_status_bit(&env, index)?;
+                }
+            }
 
-        let mut claims = env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<Bytes>>(&revoked_symbol)
-            .unwrap_or(Vec::new(&env));
+            let mut claims = env
+                .storage()
+                .persistent()
+                .get::<IssuerDataKey, Vec<Bytes>>(&IssuerDataKey::RevokedClaims)
+                .unwrap_or(Vec::new(&env));
 
-        if claims.contains(&claim.signature) {
-            return Err(Error::ClaimAlreadyRevoked);
-        }
+            if claims.contains(&claim.signature) {
+                return Err(Error::ClaimAlreadyRevoked);
+            }
 
-        claims.push_back(claim.signature.clone());
+            claims.push_back(claim.signature.clone());
 
-        env.storage().persistent().set(&revoked_symbol, &claims);
+            env.storage()
+                .persistent()
+                .set(&IssuerDataKey::RevokedClaims, &claims);
+        }
 
-        env.events().publish(
-            (Symbol::new(&env, "revoke_claim"),),
-            (sender, claim_id)
+        publish_event(
+            &env,
+            "claim_revoked",
+            &contract,
+            ClaimRevokedEvent { sender, claim_id },
         );
 
         Ok(())
     }
 
     pub fn is_claim_revoked(env: &Env, signature: Bytes) -> Result<bool, Error> {
-        let revoked_symbol = Symbol::new(&env, "revoked_claims");
-
         let claims = env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<Bytes>>(&revoked_symbol)
+            .get::<IssuerDataKey, Vec<Bytes>>(&IssuerDataKey::RevokedClaims)
             .unwrap_or(Vec::new(&env));
 
-        Ok(claims.contains(&signature))
+        if claims.contains(&signature) {
+            return Ok(true);
+        }
+
+        Ok(match get_status_index(env, &signature) {
+            Some(index) => status_bit(env, index),
+            None => false,
+        })
     }
-}
 
-fn hash_key(env: &Env, key: &Address) -> BytesN<32> {
-    let address_bytes = Bytes::from_val(env, &key.to_xdr(&env));
-    env.crypto().keccak256(&address_bytes)
-}
+    // Assigns the next status list index to an off-chain signed claim.
+    // Issuers call this when signing so the claim can later be revoked by index.
+    pub fn register_claim_status(
+        env: Env,
+        sender: Address,
+        signature: Bytes,
+    ) -> Result<u32, Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Claim)?;
 
-fn hash_claim(env: &Env, issuer: &Address, topic: &U256) -> BytesN<32> {
-    let address_bytes = Bytes::from_val(env, &issuer.to_xdr(&env));
-    let topic_bytes = Bytes::from_val(env, &topic.to_xdr(env));
+        if get_status_index(&env, &signature).is_some() {
+            return Err(Error::StatusAlreadyAssigned);
+        }
+
+        let index = assign_status_index(&env);
+        env.storage().persistent().set(
+            &IssuerDataKey::StatusOf(env.crypto().keccak256(&signature)),
+            &index,
+        );
+
+        publish_event(
+            &env,
+            "status_registered",
+            &env.current_contract_address(),
+            StatusRegisteredEvent { sender, index },
+        );
+
+        Ok(index)
+    }
+
+    pub fn get_claim_status_index(env: Env, signature: Bytes) -> Result<Option<u32>, Error> {
+        Ok(get_status_index(&env, &signature))
+    }
+
+    pub fn revoke_status(env: Env, sender: Address, index: u32) -> Result<(), Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Management)?;
 
-    let mut concatenated_bytes = Bytes::new(env);
-    concatenated_bytes.append(&address_bytes);
-    concatenated_bytes.append(&topic_bytes);
-    env.crypto().keccak256(&concatenated_bytes)
+        set_status_bit(&env, index)?;
+
+        publish_event(
+            &env,
+            "status_revoked",
+            &env.current_contract_address(),
+            StatusRevokedEvent {
+                sender,
+                list_id: index / STATUS_LIST_SIZE,
+                index,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Lets a verifier check a claim by its index alone, without revealing the claim
+    pub fn is_status_revoked(env: Env, index: u32) -> Result<bool, Error> {
+        if index >= next_status_index(&env) {
+            return Err(Error::StatusIndexOutOfRange);
+        }
+        Ok(status_bit(&env, index))
+    }
+
+    // Raw bitstring of a status list, for verifiers checking off-chain
+    pub fn get_status_list(env: Env, list_id: u32) -> Result<Bytes, Error> {
+        match list_id.checked_mul(STATUS_LIST_SIZE) {
+            Some(start) if start < next_status_index(&env) => {}
+            _ => return Err(Error::StatusIndexOutOfRange),
+        }
+        Ok(load_status_list(&env, list_id))
+    }
+
+    pub fn get_next_status_index(env: Env) -> Result<u32, Error> {
+        Ok(next_status_index(&env))
+    }
 }
 
-fn key_has_purpose(env: &Env, key_hash: &BytesN<32>, purpose: KeyPurpose) -> bool {
-    if let Some(keys) = env
+fn is_issued(env: &Env, identity: &Address, topic: &U256, data: &Bytes) -> bool {
+    match env
         .storage()
         .persistent()
-        .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
+        .get::<IssuerDataKey, IssuedClaim>(&IssuerDataKey::Issued(identity.clone(), topic.clone()))
     {
-        keys.iter()
-            .any(|k| k.key == *key_hash && k.purposes.contains(&purpose))
-    } else {
-        false
+        Some(issued) => {
+            issued.data_hash == env.crypto().keccak256(data)
+                && !status_bit(env, issued.status_index)
+        }
+        None => false,
+    }
+}
+
+fn next_status_index(env: &Env) -> u32 {
+    env.storage()
+        .persistent()
+        .get::<IssuerDataKey, u32>(&IssuerDataKey::NextStatusIndex)
+        .unwrap_or(0)
+}
+
+fn assign_status_index(env: &Env) -> u32 {
+    let index = next_status_index(env);
+    env.storage()
+        .persistent()
+        .set(&IssuerDataKey::NextStatusIndex, &(index + 1));
+    index
+}
+
+fn get_status_index(env: &Env, signature: &Bytes) -> Option<u32> {
+    env.storage()
+        .persistent()
+        .get::<IssuerDataKey, u32>(&IssuerDataKey::StatusOf(env.crypto().keccak256(signature)))
+}
+
+fn load_status_list(env: &Env, list_id: u32) -> Bytes {
+    env.storage()
+        .persistent()
+        .get::<IssuerDataKey, Bytes>(&IssuerDataKey::StatusList(list_id))
+        .unwrap_or(Bytes::from_array(
+            env,
+            &[0u8; (STATUS_LIST_SIZE / 8) as usize],
+        ))
+}
+
+fn status_bit(env: &Env, index: u32) -> bool {
+    let list = load_status_list(env, index / STATUS_LIST_SIZE);
+    let bit = index % STATUS_LIST_SIZE;
+
+    match list.get(bit / 8) {
+        Some(byte) => byte & (0x80 >> (bit % 8)) != 0,
+        None => false,
     }
 }
-fn identity_require_auth(env: &Env, sender: &Address, key_type: KeyPurpose) -> Result<(), Error> {
-    let key_hash = hash_key(env, sender);
 
-    if !key_has_purpose(env, &key_hash, key_type) {
-        return Err(Error::InsufficientPermissions);
+fn set_status_bit(env: &Env, index: u32) -> Result<(), Error> {
+    if index >= next_status_index(env) {
+        return Err(Error::StatusIndexOutOfRange);
     }
 
-    sender.require_auth();
+    let list_id = index / STATUS_LIST_SIZE;
+    let bit = index % STATUS_LIST_SIZE;
+
+    let mut list = load_status_list(env, list_id);
+    let byte = list.get(bit / 8).ok_or(Error::IndexOutOfBounds)?;
+    let mask = 0x80 >> (bit % 8);
+
+    if byte & mask != 0 {
+        return Err(Error::StatusAlreadyRevoked);
+    }
+    list.set(bit / 8, byte | mask);
+
+    env.storage()
+        .persistent()
+        .set(&IssuerDataKey::StatusList(list_id), &list);
+
     Ok(())
 }
+
+// `is_claim_valid`, following at most `depth` sub-issuer links
+fn claim_valid_at_depth(
+    env: &Env,
+    issuer_wallet: Address,
+    identity: Address,
+    topic: U256,
+    scheme: U256,
+    signature: Bytes,
+    data: Bytes,
+    depth: u32,
+) -> Result<bool, Error> {
+    if signature.is_empty() {
+        // Contract scheme claims are only valid while the writing issuer still lists them
+        if issuer_wallet == env.current_contract_address() {
+            return Ok(is_issued(env, &identity, &topic, &data));
+        }
+    } else {
+        verify_claim_signature(env, &issuer_wallet, &identity, &topic, &signature, &data)?;
+
+        // A claim revoked anywhere along the chain is invalid
+        if ClaimIssuerContract::is_claim_revoked(env, signature.clone())? {
+            return Ok(false);
+        }
+
+        let hashed_addr = hash_key(env, &issuer_wallet);
+
+        if key_has_purpose(env, &hashed_addr, KeyPurpose::Claim)
+            && key_scope_allows(env, &hashed_addr, &topic, &scheme)
+        {
+            return Ok(true);
+        }
+    }
+
+    if depth == 0 {
+        return Ok(false);
+    }
+
+    let now = env.ledger().timestamp();
+    let delegations = env
+        .storage()
+        .persistent()
+        .get::<IssuerDataKey, Vec<Delegation>>(&IssuerDataKey::Delegations)
+        .unwrap_or(Vec::new(env));
+
+    for delegation in delegations.iter() {
+        if delegation.revoked
+            || delegation.expiry <= now
+            || !(delegation.topics.is_empty() || delegation.topics.contains(&topic))
+        {
+            continue;
+        }
+
+        // A sub-issuer that fails or traps simply does not vouch for the claim
+        let client = SubIssuerClient::new(env, &delegation.sub_issuer);
+        if let Ok(Ok(true)) = client.try_is_delegated_claim_valid(
+            &issuer_wallet,
+            &identity,
+            &topic,
+            &scheme,
+            &signature,
+            &data,
+            &(depth - 1),
+        ) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn key_scope_allows(env: &Env, key_hash: &BytesN<32>, topic: &U256, scheme: &U256) -> bool {
+    match env
+        .storage()
+        .persistent()
+        .get::<IssuerDataKey, KeyScope>(&IssuerDataKey::Scope(key_hash.clone()))
+    {
+        Some(scope) => {
+            (scope.topics.is_empty() || scope.topics.contains(topic))
+                && (scope.schemes.is_empty() || scope.schemes.contains(scheme))
+        }
+        None => true,
+    }
+}
+
+mod test;
@@ -1,84 +1,60 @@
-use soroban_sdk::{contracttype, contracterror, Address, Bytes, BytesN, Vec, U256};
+use soroban_sdk::{contracttype, Address, BytesN, String, Vec, U256};
 
+// Issuer specific storage, shared entries live in onchainid_common::DataKey
 #[contracttype]
-#[derive(Clone,Copy)]
-pub enum KeyType {
-    ECDSA = 1,
-    RSA = 2,
+#[derive(Clone)]
+pub enum IssuerDataKey {
+    RevokedClaims,
+    Scope(BytesN<32>),
+    Metadata,
+    Topics,
+    Delegations,
+    Issued(Address, U256),
+    StatusOf(BytesN<32>),
+    NextStatusIndex,
+    StatusList(u32),
 }
 
-impl TryFrom<u32> for KeyType {
-    type Error = ();
-
-    fn try_from(value: u32) -> Result<Self, Self::Error> {
-        match value {
-            1 => Ok(KeyType::ECDSA),
-            2 => Ok(KeyType::RSA),
-            _ => Err(()),
-        }
-    }
+// Restricts what a Claim key may sign. An empty list means no restriction.
+#[contracttype]
+#[derive(Clone)]
+pub struct KeyScope {
+    pub topics: Vec<U256>,
+    pub schemes: Vec<U256>,
 }
 
-
+// A sub-issuer contract allowed to issue `topics` on our behalf until `expiry`.
+// An empty topic list means any topic.
 #[contracttype]
-#[derive(Clone, Copy, PartialEq)]
-pub enum KeyPurpose {
-    Management = 1,
-    Action = 2,
-    Claim = 3,
-    Encryption = 4,
+#[derive(Clone)]
+pub struct Delegation {
+    pub sub_issuer: Address,
+    pub topics: Vec<U256>,
+    pub expiry: u64,
+    pub revoked: bool,
 }
 
-impl TryFrom<u32> for KeyPurpose {
-    type Error = ();
-
-    fn try_from(value: u32) -> Result<Self, Self::Error> {
-        match value {
-            1 => Ok(KeyPurpose::Management),
-            2 => Ok(KeyPurpose::Action),
-            3 => Ok(KeyPurpose::Claim),
-            4 => Ok(KeyPurpose::Encryption),
-            _ => Err(()),
-        }
-    }
+// Registry entry for a claim written into an identity with `issue_claim`
+#[contracttype]
+#[derive(Clone)]
+pub struct IssuedClaim {
+    pub data_hash: BytesN<32>,
+    pub status_index: u32,
 }
 
 #[contracttype]
 #[derive(Clone)]
-pub struct Key {
-    pub purposes: Vec<KeyPurpose>,
-    pub key_type: KeyType,
-    pub key: BytesN<32>,
+pub struct IssuerMetadata {
+    pub name: String,
+    pub jurisdiction: String,
+    pub uri: String,
+    pub contact: String,
 }
 
+// A topic this issuer issues, with a reference to the schema of its claim data
 #[contracttype]
 #[derive(Clone)]
-pub struct Claim {
+pub struct SupportedTopic {
     pub topic: U256,
-    pub scheme: U256,
-    pub issuer: Address,
-    pub issuer_wallet: Address,
-    pub signature: Bytes,
-    pub data: Bytes,
-    pub uri: Bytes,
-}
-
-#[contracterror]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum Error {
-    AlreadyInitialized = 1,
-    KeyNotFound = 2,
-    InvalidKeyPurpose = 3,
-    InvalidKeyType = 4,
-    KeyConflict = 5,
-    IndexOutOfBounds = 6,
-    ClaimNotFound = 7,
-    KeyDoesNotHavePurpose = 8,
-    ClaimAlreadyRevoked = 9,
-    InsufficientPermissions = 10,
-    InvalidSignature = 11,
-    InvalidClaim = 12,
-    InvalidIssuer = 13,
-    InvalidAddressBytes = 14,
+    pub schema: String,
 }
@@ -0,0 +1,770 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use ed25519_dalek::SigningKey;
+use onchainid_common::hash_claim;
+use proptest::prelude::*;
+use soroban_sdk::testutils::ed25519::Sign;
+use soroban_sdk::testutils::EnvTestConfig;
+use soroban_sdk::xdr::{ScVal, ToXdr};
+use soroban_sdk::{
+    testutils::{Address as _, Ledger},
+    vec, Address, Env,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+mod claim_issuer {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/claim_issuer.wasm"
+    );
+}
+
+// Same test key as the identity tests, do not use it anywhere else
+const ISSUER_SECRET: &str = "b51a482a459d1b2f8f1ff5b7159cdbf0ab23ee46422ed0724f2822cd550ecf71";
+const ISSUER_WALLET: &str = "GARPZXSZVTI7WG3ADZQB5QGH67WS6XW6ISHIO2N4AOAC32PLNYBH7QCY";
+
+fn sign_claim(env: &Env, identity: &Address, topic: &U256, data: &Bytes) -> Bytes {
+    let sk = SigningKey::from_bytes(&hex::decode(ISSUER_SECRET).unwrap().try_into().unwrap());
+
+    let mut concatenated_bytes = Bytes::new(env);
+    concatenated_bytes.append(&identity.clone().to_xdr(env));
+    concatenated_bytes.append(&topic.clone().to_xdr(env));
+    concatenated_bytes.append(data);
+
+    let hashed_bytes: ScVal = env
+        .crypto()
+        .keccak256(&concatenated_bytes)
+        .to_array()
+        .try_into()
+        .unwrap();
+
+    let sigtest_val: [u8; 64] = sk.sign(hashed_bytes).unwrap();
+    Bytes::from_slice(env, &sigtest_val)
+}
+
+#[test]
+fn test_initialize() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    assert!(client.get_initialized(), "Contract should be initialized");
+}
+
+#[test]
+fn test_upgrade() {
+    let env = Env::default();
+    env.mock_all_auths();
+    // Running the contract as wasm exceeds a transaction's budget
+    env.budget().reset_unlimited();
+
+    let contract_id = env.register_contract_wasm(None, claim_issuer::WASM);
+    let client = claim_issuer::Client::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    // Only a management key replaces the code, the storage stays as it is
+    let wasm_hash = env.deployer().upload_contract_wasm(claim_issuer::WASM);
+    let claim_key = Address::generate(&env);
+    client.add_key(&management_key, &claim_key, &3, &1);
+    assert_eq!(
+        client.try_upgrade(&claim_key, &wasm_hash),
+        Err(Ok(Error::InsufficientPermissions.into()))
+    );
+    client.upgrade(&management_key, &wasm_hash);
+    assert_eq!(env.auths()[0].0, management_key);
+    assert!(client
+        .get_key(&claim_key)
+        .purposes
+        .contains(claim_issuer::KeyPurpose::Claim));
+    assert_eq!(
+        client.try_migrate(&management_key),
+        Err(Ok(Error::AlreadyMigrated.into()))
+    );
+}
+
+#[test]
+fn test_key_scope_limits_topics() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(&env, ISSUER_WALLET));
+    client.add_key(&management_key, &issuer_wallet, &3, &1);
+
+    let identity = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, 1);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+    let signature = sign_claim(&env, &identity, &topic, &data);
+
+    // Unscoped keys can sign any topic
+    assert!(client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+
+    // Restrict the key to another topic
+    client.set_key_scope(
+        &management_key,
+        &issuer_wallet,
+        &vec![&env, U256::from_u32(&env, 1)],
+        &vec![&env],
+    );
+    assert!(
+        !client.is_claim_valid(
+            &issuer_wallet,
+            &identity,
+            &topic,
+            &scheme,
+            &signature,
+            &data
+        ),
+        "Key should not be allowed to sign this topic"
+    );
+
+    // Allow the topic again
+    client.set_key_scope(
+        &management_key,
+        &issuer_wallet,
+        &vec![&env, U256::from_u32(&env, 1), topic.clone()],
+        &vec![&env],
+    );
+    assert!(client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+
+    // Claims held under another scheme are rejected once schemes are scoped
+    client.set_key_scope(
+        &management_key,
+        &issuer_wallet,
+        &vec![&env],
+        &vec![&env, U256::from_u32(&env, 2)],
+    );
+    assert!(
+        !client.is_claim_valid(
+            &issuer_wallet,
+            &identity,
+            &topic,
+            &scheme,
+            &signature,
+            &data
+        ),
+        "Key should not be allowed to sign this scheme"
+    );
+
+    client.remove_key_scope(&management_key, &issuer_wallet);
+    assert!(
+        client.get_key_scope(&issuer_wallet).is_none(),
+        "Scope should be removed"
+    );
+}
+
+#[test]
+fn test_key_scope_limits_schemes() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let claim_key = Address::generate(&env);
+    client.add_key(&management_key, &claim_key, &3, &1);
+
+    let topic = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, 1);
+    client.set_key_scope(
+        &management_key,
+        &claim_key,
+        &vec![&env, topic.clone()],
+        &vec![&env, scheme.clone()],
+    );
+
+    assert!(client.is_key_allowed(&claim_key, &topic, &scheme));
+    assert!(!client.is_key_allowed(&claim_key, &topic, &U256::from_u32(&env, 2)));
+    assert!(!client.is_key_allowed(&claim_key, &U256::from_u32(&env, 7), &scheme));
+
+    // Losing the Claim purpose drops the scope
+    client.remove_key(&management_key, &claim_key, &3);
+    assert!(
+        client.get_key_scope(&claim_key).is_none(),
+        "Scope should be removed"
+    );
+}
+
+#[test]
+fn test_key_scope_requires_claim_key() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let result = client.try_set_key_scope(
+        &management_key,
+        &management_key,
+        &vec![&env, U256::from_u32(&env, 1)],
+        &vec![&env],
+    );
+    assert_eq!(result, Err(Ok(Error::KeyDoesNotHavePurpose)));
+}
+
+#[test]
+fn test_delegated_sub_issuer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let parent_id = env.register_contract(None, ClaimIssuerContract);
+    let parent_client = ClaimIssuerContractClient::new(&env, &parent_id);
+    let sub_issuer_id = env.register_contract(None, ClaimIssuerContract);
+    let sub_issuer_client = ClaimIssuerContractClient::new(&env, &sub_issuer_id);
+
+    let management_key = Address::generate(&env);
+    parent_client.initialize(&management_key);
+    sub_issuer_client.initialize(&management_key);
+
+    // Only the sub-issuer holds the signing key
+    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(&env, ISSUER_WALLET));
+    sub_issuer_client.add_key(&management_key, &issuer_wallet, &3, &1);
+
+    let identity = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, 1);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+    let signature = sign_claim(&env, &identity, &topic, &data);
+
+    assert!(!parent_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+
+    // Delegations outside the claim topic do not help
+    parent_client.add_delegation(
+        &management_key,
+        &sub_issuer_id,
+        &vec![&env, U256::from_u32(&env, 1)],
+        &1000,
+    );
+    assert!(!parent_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+
+    parent_client.revoke_delegation(&management_key, &sub_issuer_id);
+    parent_client.add_delegation(
+        &management_key,
+        &sub_issuer_id,
+        &vec![&env, topic.clone()],
+        &1000,
+    );
+    assert!(
+        parent_client.is_claim_valid(
+            &issuer_wallet,
+            &identity,
+            &topic,
+            &scheme,
+            &signature,
+            &data
+        ),
+        "Claim from a delegated sub-issuer should be valid"
+    );
+
+    // The delegation stops counting once it expires
+    env.ledger().with_mut(|li| li.timestamp = 1000);
+    assert!(!parent_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+}
+
+#[test]
+fn test_delegation_chain() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let root_id = env.register_contract(None, ClaimIssuerContract);
+    let root_client = ClaimIssuerContractClient::new(&env, &root_id);
+    let regional_id = env.register_contract(None, ClaimIssuerContract);
+    let regional_client = ClaimIssuerContractClient::new(&env, &regional_id);
+    let partner_id = env.register_contract(None, ClaimIssuerContract);
+    let partner_client = ClaimIssuerContractClient::new(&env, &partner_id);
+
+    let management_key = Address::generate(&env);
+    root_client.initialize(&management_key);
+    regional_client.initialize(&management_key);
+    partner_client.initialize(&management_key);
+
+    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(&env, ISSUER_WALLET));
+    partner_client.add_key(&management_key, &issuer_wallet, &3, &1);
+
+    root_client.add_delegation(&management_key, &regional_id, &vec![&env], &1000);
+    regional_client.add_delegation(&management_key, &partner_id, &vec![&env], &1000);
+
+    let identity = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, 1);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+    let signature = sign_claim(&env, &identity, &topic, &data);
+
+    assert!(root_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+
+    // The chain is two links deep
+    assert!(!root_client.is_delegated_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data,
+        &1
+    ));
+
+    // Revoking a link in the middle breaks the whole chain
+    regional_client.revoke_delegation(&management_key, &partner_id);
+    assert!(!root_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+}
+
+#[test]
+fn test_delegation_depth_is_clamped() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let management_key = Address::generate(&env);
+    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(&env, ISSUER_WALLET));
+
+    // root -> first -> second -> third -> partner, one link more than allowed
+    let mut issuers = std::vec::Vec::new();
+    for _ in 0..MAX_DELEGATION_DEPTH + 2 {
+        let id = env.register_contract(None, ClaimIssuerContract);
+        ClaimIssuerContractClient::new(&env, &id).initialize(&management_key);
+        issuers.push(id);
+    }
+    for pair in issuers.windows(2) {
+        ClaimIssuerContractClient::new(&env, &pair[0]).add_delegation(
+            &management_key,
+            &pair[1],
+            &vec![&env],
+            &1000,
+        );
+    }
+    let partner_client = ClaimIssuerContractClient::new(&env, issuers.last().unwrap());
+    partner_client.add_key(&management_key, &issuer_wallet, &3, &1);
+
+    let identity = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, 1);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+    let signature = sign_claim(&env, &identity, &topic, &data);
+
+    let root_client = ClaimIssuerContractClient::new(&env, &issuers[0]);
+    let first_client = ClaimIssuerContractClient::new(&env, &issuers[1]);
+    assert!(first_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+    assert!(!root_client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
+
+    // A caller passing its own depth gets no further
+    assert!(!root_client.is_delegated_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data,
+        &u32::MAX
+    ));
+}
+
+#[test]
+fn test_add_delegation_conflict() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let sub_issuer = Address::generate(&env);
+    client.add_delegation(&management_key, &sub_issuer, &vec![&env], &1000);
+
+    assert_eq!(
+        client.try_add_delegation(&management_key, &sub_issuer, &vec![&env], &2000),
+        Err(Ok(Error::DelegationConflict))
+    );
+    assert_eq!(
+        client.try_add_delegation(&management_key, &contract_id, &vec![&env], &2000),
+        Err(Ok(Error::InvalidDelegation))
+    );
+}
+
+#[test]
+fn test_status_list_revocation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(&env, ISSUER_WALLET));
+    client.add_key(&management_key, &issuer_wallet, &3, &1);
+
+    let identity = Address::generate(&env);
+    let scheme = U256::from_u32(&env, 1);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+
+    // Two claims get consecutive indexes
+    let first_topic = U256::from_u32(&env, 6);
+    let first_signature = sign_claim(&env, &identity, &first_topic, &data);
+    let second_topic = U256::from_u32(&env, 7);
+    let second_signature = sign_claim(&env, &identity, &second_topic, &data);
+
+    assert_eq!(
+        client.register_claim_status(&issuer_wallet, &first_signature),
+        0
+    );
+    assert_eq!(
+        client.register_claim_status(&issuer_wallet, &second_signature),
+        1
+    );
+    assert_eq!(
+        client.try_register_claim_status(&issuer_wallet, &first_signature),
+        Err(Ok(Error::StatusAlreadyAssigned))
+    );
+
+    // Revoking by index only touches that claim
+    client.revoke_status(&management_key, &1);
+
+    assert!(client.is_status_revoked(&1));
+    assert!(!client.is_status_revoked(&0));
+    assert!(client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &first_topic,
+        &scheme,
+        &first_signature,
+        &data
+    ));
+    assert!(!client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &second_topic,
+        &scheme,
+        &second_signature,
+        &data
+    ));
+
+    // The published bitstring matches the on-chain check
+    let list = client.get_status_list(&0);
+    assert_eq!(list.len(), STATUS_LIST_SIZE / 8);
+    assert_eq!(list.get(0), Some(0b0100_0000));
+
+    assert_eq!(
+        client.try_revoke_status(&management_key, &1),
+        Err(Ok(Error::StatusAlreadyRevoked))
+    );
+    assert_eq!(
+        client.try_is_status_revoked(&2),
+        Err(Ok(Error::StatusIndexOutOfRange))
+    );
+    assert_eq!(
+        client.try_get_status_list(&1),
+        Err(Ok(Error::StatusIndexOutOfRange))
+    );
+    assert_eq!(
+        client.try_get_status_list(&u32::MAX),
+        Err(Ok(Error::StatusIndexOutOfRange))
+    );
+}
+
+#[test]
+fn test_issuer_metadata() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, ClaimIssuerContract);
+    let client = ClaimIssuerContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    assert!(client.get_metadata().is_none(), "Metadata should be empty");
+
+    let metadata = IssuerMetadata {
+        name: String::from_str(&env, "0xAuth KYC"),
+        jurisdiction: String::from_str(&env, "CH"),
+        uri: String::from_str(&env, "https://0xauth.co"),
+        contact: String::from_str(&env, "compliance@0xauth.co"),
+    };
+    client.set_metadata(&management_key, &metadata);
+
+    let stored = client.get_metadata().unwrap();
+    assert_eq!(stored.name, metadata.name);
+    assert_eq!(stored.jurisdiction, metadata.jurisdiction);
+
+    let kyc = U256::from_u32(&env, 1);
+    client.set_supported_topic(
+        &management_key,
+        &kyc,
+        &String::from_str(&env, "ipfs://kyc-v1"),
+    );
+    client.set_supported_topic(
+        &management_key,
+        &kyc,
+        &String::from_str(&env, "ipfs://kyc-v2"),
+    );
+
+    let topics = client.get_supported_topics();
+    assert_eq!(topics.len(), 1, "Updating a topic should not duplicate it");
+    assert_eq!(
+        topics.get(0).unwrap().schema,
+        String::from_str(&env, "ipfs://kyc-v2")
+    );
+    assert!(client.supports_topic(&kyc));
+
+    client.remove_supported_topic(&management_key, &kyc);
+    assert!(!client.supports_topic(&kyc));
+    assert_eq!(
+        client.try_remove_supported_topic(&management_key, &kyc),
+        Err(Ok(Error::TopicNotSupported))
+    );
+}
+
+// Model-based property test across a holder and an issuer, both claim issuer
+// contracts: claims signed by the issuer wallet are added, removed and revoked
+// while the wallet's Claim key comes and goes.
+
+const PROP_TOPICS: u32 = 3;
+const PROP_DATA: u8 = 3;
+
+#[derive(Clone, Debug)]
+enum IssuerOp {
+    AddClaim { topic: u32, data: u8 },
+    RemoveClaim { topic: u32 },
+    Revoke { topic: u32 },
+    SetClaimKey(bool),
+}
+
+fn issuer_op() -> impl Strategy<Value = IssuerOp> {
+    prop_oneof![
+        3 => (0..PROP_TOPICS, 0..PROP_DATA).prop_map(|(topic, data)| IssuerOp::AddClaim { topic, data }),
+        1 => (0..PROP_TOPICS).prop_map(|topic| IssuerOp::RemoveClaim { topic }),
+        2 => (0..PROP_TOPICS).prop_map(|topic| IssuerOp::Revoke { topic }),
+        1 => any::<bool>().prop_map(IssuerOp::SetClaimKey),
+    ]
+}
+
+struct IssuerModel {
+    // Topic to data of the claims held
+    claims: BTreeMap<u32, u8>,
+    revoked: BTreeSet<(u32, u8)>,
+    claim_key: bool,
+}
+
+impl IssuerModel {
+    fn valid(&self, topic: u32, data: u8) -> bool {
+        self.claim_key && !self.revoked.contains(&(topic, data))
+    }
+
+    // The outcome the contracts should report, applied to the model on success
+    fn apply(&mut self, op: &IssuerOp) -> Result<(), Error> {
+        match *op {
+            IssuerOp::AddClaim { topic, data } => {
+                if !self.valid(topic, data) {
+                    return Err(Error::InvalidClaim);
+                }
+                self.claims.insert(topic, data);
+            }
+            IssuerOp::RemoveClaim { topic } => {
+                self.claims.remove(&topic).ok_or(Error::ClaimNotFound)?;
+            }
+            IssuerOp::Revoke { topic } => {
+                let data = *self.claims.get(&topic).ok_or(Error::ClaimNotFound)?;
+                if !self.revoked.insert((topic, data)) {
+                    return Err(Error::ClaimAlreadyRevoked);
+                }
+            }
+            IssuerOp::SetClaimKey(on) => {
+                if on && self.claim_key {
+                    return Err(Error::KeyConflict);
+                }
+                if !on && !self.claim_key {
+                    return Err(Error::KeyNotFound);
+                }
+                self.claim_key = on;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn outcome<T, C: core::fmt::Debug, I: core::fmt::Debug>(
+    result: Result<Result<T, C>, Result<Error, I>>,
+) -> Result<(), Error> {
+    match result {
+        Ok(Ok(_)) => Ok(()),
+        Err(Ok(error)) => Err(error),
+        other => panic!(
+            "unexpected host failure: {:?}",
+            other.map(|r| r.map(|_| ()))
+        ),
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn test_revocation_invariants(ops in prop::collection::vec(issuer_op(), 1..20)) {
+        // Cases are random, a snapshot of one would change on every run
+        let env = Env::new_with_config(EnvTestConfig {
+            capture_snapshot_at_drop: false,
+        });
+        env.mock_all_auths();
+        // The invariant checks alone exceed a transaction's budget
+        env.budget().reset_unlimited();
+
+        let holder_id = env.register_contract(None, ClaimIssuerContract);
+        let holder = ClaimIssuerContractClient::new(&env, &holder_id);
+        let issuer_id = env.register_contract(None, ClaimIssuerContract);
+        let issuer = ClaimIssuerContractClient::new(&env, &issuer_id);
+
+        let management_key = Address::generate(&env);
+        holder.initialize(&management_key);
+        holder.add_key(&management_key, &management_key, &3, &1);
+        issuer.initialize(&management_key);
+        let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(&env, ISSUER_WALLET));
+        issuer.add_key(&management_key, &issuer_wallet, &3, &1);
+
+        let topic = |topic: u32| U256::from_u32(&env, topic);
+        let scheme = U256::from_u32(&env, 1);
+        let data = |data: u8| Bytes::from_slice(&env, &[data]);
+        let claim_id = |t: u32| hash_claim(&env, &issuer_id, &topic(t));
+        let mut signatures = BTreeMap::new();
+        for t in 0..PROP_TOPICS {
+            for d in 0..PROP_DATA {
+                signatures.insert((t, d), sign_claim(&env, &holder_id, &topic(t), &data(d)));
+            }
+        }
+
+        let mut model = IssuerModel {
+            claims: BTreeMap::new(),
+            revoked: BTreeSet::new(),
+            claim_key: true,
+        };
+
+        for op in &ops {
+            let expected = model.apply(op);
+            let actual = match *op {
+                IssuerOp::AddClaim { topic: t, data: d } => outcome(holder.try_add_claim(
+                    &management_key,
+                    &topic(t),
+                    &scheme,
+                    &issuer_wallet,
+                    &issuer_id,
+                    &signatures[&(t, d)],
+                    &data(d),
+                    &Bytes::new(&env),
+                )),
+                IssuerOp::RemoveClaim { topic: t } => {
+                    outcome(holder.try_remove_claim(&management_key, &claim_id(t)))
+                }
+                IssuerOp::Revoke { topic: t } => {
+                    outcome(issuer.try_revoke_claim(&management_key, &holder_id, &claim_id(t)))
+                }
+                IssuerOp::SetClaimKey(true) => {
+                    outcome(issuer.try_add_key(&management_key, &issuer_wallet, &3, &1))
+                }
+                IssuerOp::SetClaimKey(false) => {
+                    outcome(issuer.try_remove_key(&management_key, &issuer_wallet, &3))
+                }
+            };
+            prop_assert_eq!(actual, expected, "{:?}", op);
+
+            // Revoked claims never validate, others do while the key is there
+            for ((t, d), signature) in &signatures {
+                let valid = issuer.is_claim_valid(&issuer_wallet, &holder_id, &topic(*t), &scheme, signature, &data(*d));
+                prop_assert_eq!(valid, model.valid(*t, *d));
+                prop_assert_eq!(issuer.is_claim_revoked(signature), model.revoked.contains(&(*t, *d)));
+            }
+
+            // The claim list matches the stored claims
+            let ids = holder.get_claim_ids();
+            prop_assert_eq!(ids.len() as usize, model.claims.len());
+            for t in 0..PROP_TOPICS {
+                let stored = holder.get_claim(&claim_id(t));
+                prop_assert_eq!(stored.as_ref().map(|c| c.data.clone()), model.claims.get(&t).map(|d| data(*d)));
+                prop_assert_eq!(ids.contains(claim_id(t)), stored.is_some());
+            }
+        }
+    }
+}
@@ -1,14 +1,135 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contracterror, contractimpl, symbol_short, Address, BytesN, Env, Symbol, Val, Vec,
+    contract, contracterror, contractimpl, contracttype, symbol_short, token, vec, xdr::ToXdr,
+    Address, Bytes, BytesN, Env, IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+
+use onchainid_common::events::{
+    publish_event, AllowlistSetEvent, CreationFeeSetEvent, EvmLinkEvent, IdentitiesUpgradedEvent,
+    IdentityCreatedEvent, IdentityWasmSetEvent, InitializedEvent, MigratedEvent, OwnerChangedEvent,
+    SelfServiceSetEvent, TokenIdentityCreatedEvent, UpgradedEvent, WalletLinkEvent,
+};
+use onchainid_common::{
+    identity_salt, AccessControl, AccessError, KeyHolderClient, KeyPurpose, KeyType, Role,
+    UpgradeableClient,
 };
 
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
-pub enum Error {
+pub enum FactoryError {
     AlreadyInitialized = 1,
     InsufficientPermissions = 2,
+    NotInitialized = 3,
+    IdentityNotFound = 4,
+    WalletNotLinked = 5,
+    IdentityWasmNotSet = 6,
+    SelfServiceDisabled = 7,
+    WalletNotAllowed = 8,
+    IdentityAlreadyExists = 9,
+    InvalidFee = 10,
+    WalletAlreadyLinked = 11,
+    TokenIdentityAlreadyExists = 12,
+    NoPendingOwner = 15,
+    EvmAddressAlreadyLinked = 16,
+    EvmAddressNotLinked = 17,
+    InvalidEvmSignature = 18,
+}
+
+impl From<AccessError> for FactoryError {
+    fn from(error: AccessError) -> Self {
+        match error {
+            AccessError::Unauthorized => FactoryError::InsufficientPermissions,
+            AccessError::NoPendingOwner => FactoryError::NoPendingOwner,
+        }
+    }
+}
+
+// Version of the storage layout, contracts from before versioning report 0.
+// Version 1 kept the mappings in instance storage, version 2 in persistent
+// entries.
+const SCHEMA_VERSION: u32 = 2;
+
+// Registered identities whose mappings one `migrate` call moves out of the
+// instance
+const MIGRATION_BATCH: u32 = 10;
+
+// Mappings are extended whenever they are read or written, and the instance
+// with them, so entries of active users don't expire
+const DAY_IN_LEDGERS: u32 = 17280;
+const MAPPING_TTL_THRESHOLD: u32 = 7 * DAY_IN_LEDGERS;
+const MAPPING_TTL_EXTEND_TO: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_TTL_THRESHOLD: u32 = 7 * DAY_IN_LEDGERS;
+const INSTANCE_TTL_EXTEND_TO: u32 = 30 * DAY_IN_LEDGERS;
+
+// Most identities `get_identities` returns per call
+pub const MAX_PAGE_SIZE: u32 = 50;
+
+#[contracttype]
+#[derive(Clone)]
+pub enum FactoryDataKey {
+    Initialized,
+    SchemaVersion,
+    // Next registry position a schema 1 migration moves
+    MigrationCursor,
+    Owner,
+    // Per wallet and identity mappings, kept in persistent storage
+    // wallet -> identity
+    Identity(Address),
+    // identity -> linked wallets
+    Wallets(Address),
+    // Every identity the factory created or linked, in registration order,
+    // token identities included
+    IdentityCount,
+    IdentityAt(u32),
+    IdentityIndex(Address),
+    // Approved identity implementations, the latest one is deployed
+    IdentityWasmVersion,
+    IdentityWasm(u32),
+    // identity -> implementation version it runs, unknown for linked identities
+    IdentityVersion(Address),
+    SelfService,
+    CreationFee,
+    // Wallets allowed to call `create_my_identity` while the allowlist is on,
+    // persistent
+    Allowed(Address),
+    // Token identities, kept apart from the wallet mappings. The token
+    // mappings are persistent.
+    TokenIdentity(Address),
+    IdentityToken(Address),
+    // 20-byte EVM address -> identity, and back, persistent
+    EvmIdentity(BytesN<20>),
+    EvmAddresses(Address),
+    // Links made by an EVM address so far, part of the message it signs
+    EvmNonce(BytesN<20>),
+}
+
+// How wallets may create their own identity with `create_my_identity`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SelfService {
+    pub enabled: bool,
+    // Only wallets the owner allowed
+    pub allowlist: bool,
+}
+
+// Paid by the wallet to the owner on `create_my_identity`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CreationFee {
+    pub token: Address,
+    pub amount: i128,
+}
+
+// Outcome of one `upgrade_identities` batch. Identities already on `version`
+// are in neither list.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UpgradeReport {
+    pub version: u32,
+    pub upgraded: Vec<Address>,
+    // Not opted in, or the upgrade failed
+    pub failed: Vec<Address>,
 }
 
 #[contract]
@@ -16,152 +137,1122 @@ pub struct FactoryContract;
 
 #[contractimpl]
 impl FactoryContract {
-    pub fn get_initialized(env: Env) -> Result<bool, Error> {
+    pub fn get_initialized(env: Env) -> Result<bool, FactoryError> {
         Ok(env
             .storage()
             .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false))
+            .get::<FactoryDataKey, bool>(&FactoryDataKey::Initialized)
+            .unwrap_or(false)
+            || is_legacy(&env))
     }
 
-    pub fn initialize(env: Env, owner: Address) -> Result<(), Error> {
-        let init_symbol = Symbol::new(&env, "initialized");
+    pub fn initialize(env: Env, owner: Address) -> Result<(), FactoryError> {
+        if Self::get_initialized(env.clone())? {
+            return Err(FactoryError::AlreadyInitialized);
+        }
 
-        let initialized = env
-            .storage()
+        env.storage()
             .instance()
-            .get::<Symbol, bool>(&init_symbol)
-            .unwrap_or(false);
+            .set(&FactoryDataKey::Initialized, &true);
+        env.storage()
+            .instance()
+            .set(&FactoryDataKey::SchemaVersion, &SCHEMA_VERSION);
 
-        if initialized {
-            return Err(Error::AlreadyInitialized);
-        }
+        env.storage().instance().set(&FactoryDataKey::Owner, &owner);
 
-        env.storage().instance().set(&init_symbol, &true);
+        publish_event(
+            &env,
+            "initialized",
+            &env.current_contract_address(),
+            InitializedEvent { owner },
+        );
 
+        Ok(())
+    }
+
+    pub fn get_schema_version(env: Env) -> u32 {
         env.storage()
             .instance()
-            .set(&symbol_short!("owner"), &owner);
+            .get::<FactoryDataKey, u32>(&FactoryDataKey::SchemaVersion)
+            .unwrap_or(0)
+    }
+
+    // Moves a factory deployed before schema versioning to the typed layout.
+    // Wallet mappings can't be enumerated on chain, so the owner passes the
+    // linked wallets (from link events) and may do so over several calls.
+    //
+    // A schema 1 factory moves the mappings of MIGRATION_BATCH registered
+    // identities to persistent storage per call and reaches schema 2 once the
+    // whole registry is moved. Allowlisted wallets aren't enumerable either and
+    // are moved when passed in `wallets` before that. Reads fall back to the
+    // instance until then.
+    pub fn migrate(env: Env, wallets: Vec<Address>) -> Result<(), FactoryError> {
+        let owner = if is_legacy(&env) {
+            let owner: Address = env
+                .storage()
+                .instance()
+                .get(&symbol_short!("owner"))
+                .ok_or(FactoryError::NotInitialized)?;
+            owner.require_auth();
 
-        env.events().publish((init_symbol,), owner);
+            env.storage()
+                .instance()
+                .remove(&Symbol::new(&env, "initialized"));
+            env.storage().instance().remove(&symbol_short!("owner"));
+            env.storage()
+                .instance()
+                .set(&FactoryDataKey::Initialized, &true);
+            env.storage().instance().set(&FactoryDataKey::Owner, &owner);
+            env.storage()
+                .instance()
+                .set(&FactoryDataKey::SchemaVersion, &SCHEMA_VERSION);
+            owner
+        } else if Self::get_schema_version(env.clone()) == 0 {
+            return Err(FactoryError::NotInitialized);
+        } else {
+            only_owner(&env)?
+        };
+
+        for wallet in wallets.iter() {
+            if is_migrating(&env) {
+                move_entry::<bool>(&env, &FactoryDataKey::Allowed(wallet.clone()));
+            }
+
+            let identity = match env.storage().instance().get::<Address, Address>(&wallet) {
+                Some(identity) => identity,
+                None => continue,
+            };
+            env.storage().instance().remove(&wallet);
+            set_entry(&env, &FactoryDataKey::Identity(wallet.clone()), &identity);
+
+            if let Some(linked) = env
+                .storage()
+                .instance()
+                .get::<Address, Vec<Address>>(&identity)
+            {
+                env.storage().instance().remove(&identity);
+                set_entry(&env, &FactoryDataKey::Wallets(identity.clone()), &linked);
+            }
+            register_identity(&env, &identity);
+        }
+
+        if is_migrating(&env) {
+            migrate_batch(&env);
+        }
+
+        // Not while a schema 1 migration is still under way
+        if !is_migrating(&env) {
+            publish_event(
+                &env,
+                "migrated",
+                &env.current_contract_address(),
+                MigratedEvent {
+                    by: owner,
+                    version: SCHEMA_VERSION,
+                },
+            );
+        }
 
         Ok(())
     }
 
-    pub fn create_identity(
+    // Replaces the factory's own code, signed by an admin. The new code takes
+    // over once this invocation has finished, `migrate` then moves the storage
+    // to its layout.
+    pub fn upgrade(env: Env, sender: Address, wasm_hash: BytesN<32>) -> Result<(), FactoryError> {
+        require_role(&env, Role::Admin, &sender)?;
+
+        env.deployer()
+            .update_current_contract_wasm(wasm_hash.clone());
+
+        publish_event(
+            &env,
+            "upgraded",
+            &env.current_contract_address(),
+            UpgradedEvent {
+                authority: sender,
+                wasm_hash,
+            },
+        );
+        Ok(())
+    }
+
+    // Approves a new identity implementation for `create_identity` and returns its
+    // version. Identities already deployed keep the wasm they were created with.
+    pub fn set_identity_wasm(
         env: Env,
+        sender: Address,
         wasm_hash: BytesN<32>,
-        wallet: Address,
-        salt: BytesN<32>,
-        init_fn: Symbol,
-        init_args: Vec<Val>,
-    ) -> (Address, Val) {
-        let owner = only_owner(&env);
+    ) -> Result<u32, FactoryError> {
+        require_role(&env, Role::Admin, &sender)?;
 
-        let identity_address = env.deployer().with_address(owner, salt.clone()).deploy(wasm_hash);
+        let version = identity_wasm_version(&env) + 1;
+        env.storage()
+            .instance()
+            .set(&FactoryDataKey::IdentityWasm(version), &wasm_hash);
+        env.storage()
+            .instance()
+            .set(&FactoryDataKey::IdentityWasmVersion, &version);
 
-        let res: Val = env.invoke_contract(&identity_address, &init_fn, init_args.clone());
+        publish_event(
+            &env,
+            "identity_wasm_set",
+            &env.current_contract_address(),
+            IdentityWasmSetEvent { version, wasm_hash },
+        );
 
-        env.storage().instance().set(&wallet, &identity_address);
+        Ok(version)
+    }
 
-        let mut wallets: Vec<Address> = env
-            .storage()
+    // Version of the implementation `create_identity` deploys, 0 before one is set
+    pub fn get_identity_wasm_version(env: Env) -> Result<u32, FactoryError> {
+        Ok(identity_wasm_version(&env))
+    }
+
+    pub fn get_identity_wasm(env: Env, version: u32) -> Result<BytesN<32>, FactoryError> {
+        env.storage()
             .instance()
-            .get::<Address, Vec<Address>>(&identity_address)
-            .unwrap_or(Vec::new(&env));
+            .get::<FactoryDataKey, BytesN<32>>(&FactoryDataKey::IdentityWasm(version))
+            .ok_or(FactoryError::IdentityWasmNotSet)
+    }
+
+    // Implementation version `identity` runs, 0 if the factory doesn't know it
+    pub fn get_identity_version(env: Env, identity: Address) -> Result<u32, FactoryError> {
+        Ok(get_entry::<u32>(&env, &FactoryDataKey::IdentityVersion(identity)).unwrap_or(0))
+    }
 
-        wallets.push_back(wallet.clone());
+    // Upgrades the registered identities from position `start` to the latest
+    // implementation, at most MAX_PAGE_SIZE per call. Only identities that made
+    // the factory their upgrade authority accept it, the others are reported.
+    pub fn upgrade_identities(
+        env: Env,
+        sender: Address,
+        start: u32,
+        limit: u32,
+    ) -> Result<UpgradeReport, FactoryError> {
+        require_role(&env, Role::Admin, &sender)?;
+        let version = identity_wasm_version(&env);
+        let wasm_hash = Self::get_identity_wasm(env.clone(), version)?;
 
-        env.storage().instance().set(&identity_address, &wallets);
+        let mut report = UpgradeReport {
+            version,
+            upgraded: Vec::new(&env),
+            failed: Vec::new(&env),
+        };
+        for identity in Self::get_identities(env.clone(), start, limit)?.iter() {
+            if Self::get_identity_version(env.clone(), identity.clone())? == version {
+                continue;
+            }
+            match UpgradeableClient::new(&env, &identity).try_upgrade(&wasm_hash) {
+                Ok(Ok(())) => {
+                    set_entry(
+                        &env,
+                        &FactoryDataKey::IdentityVersion(identity.clone()),
+                        &version,
+                    );
+                    report.upgraded.push_back(identity);
+                }
+                _ => report.failed.push_back(identity),
+            }
+        }
 
-        env.events().publish((Symbol::new(&env, "create_identity"),), (wallet, identity_address.clone(), salt, init_fn, init_args));
+        publish_event(
+            &env,
+            "identities_upgraded",
+            &env.current_contract_address(),
+            IdentitiesUpgradedEvent {
+                version,
+                upgraded: report.upgraded.len(),
+                failed: report.failed.clone(),
+            },
+        );
 
-        (identity_address, res)
+        Ok(report)
     }
 
-    pub fn link_wallet(env: Env, wallet: Address, identity: Address) {
-        only_owner(&env);
+    // Deploys the approved identity implementation as `wallet`'s `index`-th
+    // identity with both `sender`, an identity creator, and `wallet` as its
+    // management keys, so the creator can keep managing it for the wallet.
+    pub fn create_identity(
+        env: Env,
+        sender: Address,
+        wallet: Address,
+        index: u32,
+    ) -> Result<Address, FactoryError> {
+        require_role(&env, Role::IdentityCreator, &sender)?;
+        if has_entry(&env, &FactoryDataKey::Identity(wallet.clone())) {
+            return Err(FactoryError::IdentityAlreadyExists);
+        }
+        let wasm_hash = Self::get_identity_wasm(env.clone(), identity_wasm_version(&env))?;
 
-        let mut wallets: Vec<Address> = env
-            .storage()
-            .instance()
-            .get::<Address, Vec<Address>>(&identity)
-            .unwrap_or(Vec::new(&env));
+        deploy_identity(&env, wasm_hash, &sender, wallet, index)
+    }
 
-        wallets.push_back(wallet.clone());
+    pub fn set_self_service(
+        env: Env,
+        sender: Address,
+        config: SelfService,
+    ) -> Result<(), FactoryError> {
+        require_role(&env, Role::Admin, &sender)?;
 
-        env.storage().instance().set(&identity, &wallets);
+        env.storage()
+            .instance()
+            .set(&FactoryDataKey::SelfService, &config);
+
+        publish_event(
+            &env,
+            "self_service_set",
+            &env.current_contract_address(),
+            SelfServiceSetEvent {
+                enabled: config.enabled,
+                allowlist: config.allowlist,
+            },
+        );
 
-        env.storage().instance().set(&wallet, &identity);
+        Ok(())
+    }
 
-        env.events().publish((Symbol::new(&env, "link_wallet"),), (wallet, identity));
+    pub fn get_self_service(env: Env) -> Result<SelfService, FactoryError> {
+        Ok(self_service(&env))
     }
 
-    pub fn unlink_wallet(env: Env, wallet: Address, identity: Address) {
-        only_owner(&env);
+    // An amount of 0 removes the fee
+    pub fn set_creation_fee(
+        env: Env,
+        sender: Address,
+        token: Address,
+        amount: i128,
+    ) -> Result<(), FactoryError> {
+        require_role(&env, Role::Admin, &sender)?;
+
+        match amount {
+            0 => env
+                .storage()
+                .instance()
+                .remove(&FactoryDataKey::CreationFee),
+            1.. => env.storage().instance().set(
+                &FactoryDataKey::CreationFee,
+                &CreationFee {
+                    token: token.clone(),
+                    amount,
+                },
+            ),
+            _ => return Err(FactoryError::InvalidFee),
+        }
+
+        publish_event(
+            &env,
+            "creation_fee_set",
+            &env.current_contract_address(),
+            CreationFeeSetEvent { token, amount },
+        );
 
-        let mut wallets: Vec<Address> = env
+        Ok(())
+    }
+
+    pub fn get_creation_fee(env: Env) -> Result<Option<CreationFee>, FactoryError> {
+        Ok(env
             .storage()
             .instance()
-            .get::<Address, Vec<Address>>(&identity)
-            .unwrap_or(Vec::new(&env));
+            .get::<FactoryDataKey, CreationFee>(&FactoryDataKey::CreationFee))
+    }
+
+    pub fn set_allowed(
+        env: Env,
+        sender: Address,
+        wallet: Address,
+        allowed: bool,
+    ) -> Result<(), FactoryError> {
+        require_role(&env, Role::Admin, &sender)?;
+
+        let key = FactoryDataKey::Allowed(wallet.clone());
+        if allowed {
+            set_entry(&env, &key, &true);
+        } else {
+            remove_entry(&env, &key);
+        }
+
+        publish_event(
+            &env,
+            "allowlist_set",
+            &env.current_contract_address(),
+            AllowlistSetEvent { wallet, allowed },
+        );
+
+        Ok(())
+    }
+
+    pub fn is_allowed(env: Env, wallet: Address) -> Result<bool, FactoryError> {
+        Ok(get_entry::<bool>(&env, &FactoryDataKey::Allowed(wallet)).unwrap_or(false))
+    }
+
+    // Self-service onboarding: the wallet authorizes, pays the fee if one is set
+    // and gets its first identity, managed by the wallet and linked to it.
+    // Deployed by the factory itself, see `predict_my_identity_address`.
+    pub fn create_my_identity(env: Env, wallet: Address) -> Result<Address, FactoryError> {
+        wallet.require_auth();
+
+        let config = self_service(&env);
+        if !config.enabled {
+            return Err(FactoryError::SelfServiceDisabled);
+        }
+        if config.allowlist && !Self::is_allowed(env.clone(), wallet.clone())? {
+            return Err(FactoryError::WalletNotAllowed);
+        }
+        if has_entry(&env, &FactoryDataKey::Identity(wallet.clone())) {
+            return Err(FactoryError::IdentityAlreadyExists);
+        }
+        let wasm_hash = Self::get_identity_wasm(env.clone(), identity_wasm_version(&env))?;
+        let identity = deploy_identity(&env, wasm_hash, &wallet, wallet.clone(), 0)?;
+
+        if let Some(fee) = Self::get_creation_fee(env.clone())? {
+            let owner = Self::get_owner(env.clone())?;
+            token::Client::new(&env, &fee.token).transfer(&wallet, &owner, &fee.amount);
+        }
+
+        Ok(identity)
+    }
+
+    // Where `create_my_identity` deploys the identity of `wallet`, the same
+    // address as its first identity from `create_identity`
+    pub fn predict_my_identity_address(env: Env, wallet: Address) -> Result<Address, FactoryError> {
+        Self::predict_identity_address(env, wallet, 0)
+    }
+
+    // Where `wallet`'s `index`-th identity is deployed, whoever creates it
+    pub fn predict_identity_address(
+        env: Env,
+        wallet: Address,
+        index: u32,
+    ) -> Result<Address, FactoryError> {
+        Ok(env
+            .deployer()
+            .with_current_contract(identity_deploy_salt(&env, &wallet, index))
+            .deployed_address())
+    }
+
+    // Deploys the onchainID of a token, managed by `token_owner`. `sender` is an
+    // identity creator or a token factory. Each token and each salt is used once.
+    // Token identities are registered next to the wallet ones, so `is_identity`
+    // and `get_identities` include them.
+    pub fn create_token_identity(
+        env: Env,
+        sender: Address,
+        token: Address,
+        token_owner: Address,
+        salt: BytesN<32>,
+    ) -> Result<Address, FactoryError> {
+        let owner = Self::get_owner(env.clone())?;
+        if !AccessControl::has_role(&env, &owner, Role::TokenFactory, &sender)
+            && !AccessControl::has_role(&env, &owner, Role::IdentityCreator, &sender)
+        {
+            return Err(FactoryError::InsufficientPermissions);
+        }
+        sender.require_auth();
+
+        if has_entry(&env, &FactoryDataKey::TokenIdentity(token.clone())) {
+            return Err(FactoryError::TokenIdentityAlreadyExists);
+        }
+        let wasm_hash = Self::get_identity_wasm(env.clone(), identity_wasm_version(&env))?;
 
-        if wallets.contains(&wallet) {
-            let index = wallets.iter().position(|x| x == wallet).unwrap();
-            wallets.remove(index as u32);
+        // Deploying over the identity of an earlier token with this salt would trap
+        let deployer = env
+            .deployer()
+            .with_current_contract(domain_salt(&env, b"token", &salt));
+        if has_entry(
+            &env,
+            &FactoryDataKey::IdentityIndex(deployer.deployed_address()),
+        ) {
+            return Err(FactoryError::TokenIdentityAlreadyExists);
         }
+        let identity = deployer.deploy(wasm_hash);
+        initialize_identity(&env, &identity, &token_owner);
 
-        env.storage().instance().set(&identity, &wallets);
+        set_entry(
+            &env,
+            &FactoryDataKey::TokenIdentity(token.clone()),
+            &identity,
+        );
+        set_entry(
+            &env,
+            &FactoryDataKey::IdentityToken(identity.clone()),
+            &token,
+        );
+        register_identity(&env, &identity);
 
-        env.storage().instance().remove(&wallet);
+        publish_event(
+            &env,
+            "token_identity_created",
+            &identity,
+            TokenIdentityCreatedEvent {
+                token,
+                token_owner,
+                salt,
+            },
+        );
 
-        env.events().publish((Symbol::new(&env, "unlink_wallet"),), (wallet, identity));
+        Ok(identity)
+    }
+
+    pub fn predict_token_identity_address(
+        env: Env,
+        salt: BytesN<32>,
+    ) -> Result<Address, FactoryError> {
+        Ok(env
+            .deployer()
+            .with_current_contract(domain_salt(&env, b"token", &salt))
+            .deployed_address())
+    }
+
+    pub fn get_token_identity(env: Env, token: Address) -> Result<Address, FactoryError> {
+        get_entry(&env, &FactoryDataKey::TokenIdentity(token)).ok_or(FactoryError::IdentityNotFound)
+    }
+
+    // The token `identity` was created for
+    pub fn get_token(env: Env, identity: Address) -> Result<Address, FactoryError> {
+        get_entry(&env, &FactoryDataKey::IdentityToken(identity))
+            .ok_or(FactoryError::IdentityNotFound)
+    }
+
+    // Links another wallet to `identity`. Both the wallet and a management key of
+    // the identity authorize. A wallet belongs to at most one identity.
+    pub fn link_wallet(
+        env: Env,
+        wallet: Address,
+        identity: Address,
+        manager: Address,
+    ) -> Result<(), FactoryError> {
+        wallet.require_auth();
+        require_management(&env, &identity, &manager)?;
+
+        if has_entry(&env, &FactoryDataKey::Identity(wallet.clone())) {
+            return Err(FactoryError::WalletAlreadyLinked);
+        }
+        add_wallet(&env, &wallet, &identity);
+
+        Ok(())
+    }
+
+    // Moves a linked wallet to `identity` in one call, authorized like `link_wallet`
+    pub fn relink_wallet(
+        env: Env,
+        wallet: Address,
+        identity: Address,
+        manager: Address,
+    ) -> Result<(), FactoryError> {
+        wallet.require_auth();
+        require_management(&env, &identity, &manager)?;
+
+        let current = Self::try_get_identity(env.clone(), wallet.clone())
+            .ok_or(FactoryError::WalletNotLinked)?;
+        if current == identity {
+            return Err(FactoryError::WalletAlreadyLinked);
+        }
+        remove_wallet(&env, &wallet, &current)?;
+        add_wallet(&env, &wallet, &identity);
+
+        Ok(())
+    }
+
+    // Removes a wallet from `identity`, authorized by a management key of the
+    // identity or, passed as `manager`, by the wallet itself
+    pub fn unlink_wallet(
+        env: Env,
+        wallet: Address,
+        identity: Address,
+        manager: Address,
+    ) -> Result<(), FactoryError> {
+        if manager == wallet {
+            wallet.require_auth();
+        } else {
+            require_management(&env, &identity, &manager)?;
+        }
+        remove_wallet(&env, &wallet, &identity)
     }
 
     pub fn get_wallets(env: Env, identity: Address) -> Vec<Address> {
-        let wallets: Vec<Address> = env
-            .storage()
-            .instance()
-            .get::<Address, Vec<Address>>(&identity)
-            .unwrap_or(Vec::new(&env));
+        get_entry::<Vec<Address>>(&env, &FactoryDataKey::Wallets(identity))
+            .unwrap_or(Vec::new(&env))
+    }
 
-        wallets
+    // The identity `wallet` is linked to, None for a wallet the factory doesn't
+    // know
+    pub fn try_get_identity(env: Env, wallet: Address) -> Option<Address> {
+        get_entry(&env, &FactoryDataKey::Identity(wallet))
     }
 
-    pub fn get_identity(env: Env, wallet: Address) -> Address {
-        let identity: Address = env.storage().instance().get(&wallet).unwrap();
-        identity
+    // Keeps the mappings of `wallet` and its identity from expiring. Anyone may
+    // call it, reads and writes extend them as well.
+    pub fn extend_wallet_ttl(env: Env, wallet: Address) -> Result<(), FactoryError> {
+        let identity: Address = get_entry(&env, &FactoryDataKey::Identity(wallet))
+            .ok_or(FactoryError::IdentityNotFound)?;
+
+        get_entry::<Vec<Address>>(&env, &FactoryDataKey::Wallets(identity.clone()));
+        get_entry::<u32>(&env, &FactoryDataKey::IdentityVersion(identity.clone()));
+        if let Some(index) = get_entry::<u32>(&env, &FactoryDataKey::IdentityIndex(identity)) {
+            get_entry::<Address>(&env, &FactoryDataKey::IdentityAt(index));
+        }
+        Ok(())
     }
 
-    pub fn get_owner(env: Env) -> Address {
-        let owner: Address = env
-            .storage()
-            .instance()
-            .get(&symbol_short!("owner"))
-            .unwrap();
-        owner
+    pub fn is_identity(env: Env, identity: Address) -> Result<bool, FactoryError> {
+        Ok(has_entry(&env, &FactoryDataKey::IdentityIndex(identity)))
     }
 
-    pub fn set_owner(env: Env, owner: Address) {
-        only_owner(&env);
+    pub fn identity_count(env: Env) -> Result<u32, FactoryError> {
+        Ok(identity_count(&env))
+    }
+
+    // Registered identities from position `start`, at most MAX_PAGE_SIZE at a time
+    pub fn get_identities(env: Env, start: u32, limit: u32) -> Result<Vec<Address>, FactoryError> {
+        let end = identity_count(&env).min(start.saturating_add(limit.min(MAX_PAGE_SIZE)));
+
+        let mut identities = Vec::new(&env);
+        for index in start..end {
+            let identity = get_entry::<Address>(&env, &FactoryDataKey::IdentityAt(index))
+                .ok_or(FactoryError::IdentityNotFound)?;
+            identities.push_back(identity);
+        }
+        Ok(identities)
+    }
+
+    // What an EVM address signs with `personal_sign` to link itself to
+    // `identity`. It covers this factory and the links the address made
+    // before, so a signature can't be replayed after an unlink.
+    pub fn get_evm_link_message(
+        env: Env,
+        evm_address: BytesN<20>,
+        identity: Address,
+    ) -> Result<BytesN<32>, FactoryError> {
+        Ok(evm_link_message(&env, &evm_address, &identity))
+    }
+
+    // Links an EVM address to `identity`. The EVM key signs
+    // `get_evm_link_message` and a management key of the identity authorizes. `recovery_id` is 0 or 1, or the EVM `v` of 27 or 28.
+    pub fn link_evm_address(
+        env: Env,
+        evm_address: BytesN<20>,
+        identity: Address,
+        manager: Address,
+        signature: BytesN<64>,
+        recovery_id: u32,
+    ) -> Result<(), FactoryError> {
+        require_management(&env, &identity, &manager)?;
 
+        let key = FactoryDataKey::EvmIdentity(evm_address.clone());
+        if has_entry(&env, &key) {
+            return Err(FactoryError::EvmAddressAlreadyLinked);
+        }
+        let message = evm_link_message(&env, &evm_address, &identity);
+        if recover_evm_address(&env, &message, &signature, recovery_id)? != evm_address {
+            return Err(FactoryError::InvalidEvmSignature);
+        }
+
+        let nonce = evm_nonce(&env, &evm_address);
+        set_entry(
+            &env,
+            &FactoryDataKey::EvmNonce(evm_address.clone()),
+            &(nonce + 1),
+        );
+        set_entry(&env, &key, &identity);
+
+        let mut addresses = Self::get_evm_addresses(env.clone(), identity.clone());
+        addresses.push_back(evm_address.clone());
+        set_entry(
+            &env,
+            &FactoryDataKey::EvmAddresses(identity.clone()),
+            &addresses,
+        );
+
+        publish_event(
+            &env,
+            "evm_address_linked",
+            &identity,
+            EvmLinkEvent { evm_address },
+        );
+
+        Ok(())
+    }
+
+    // Removes an EVM address from its identity, authorized by a management key
+    // of the identity
+    pub fn unlink_evm_address(
+        env: Env,
+        evm_address: BytesN<20>,
+        manager: Address,
+    ) -> Result<(), FactoryError> {
+        let key = FactoryDataKey::EvmIdentity(evm_address.clone());
+        let identity: Address = get_entry(&env, &key).ok_or(FactoryError::EvmAddressNotLinked)?;
+        require_management(&env, &identity, &manager)?;
+
+        let mut addresses = Self::get_evm_addresses(env.clone(), identity.clone());
+        if let Some(index) = addresses.first_index_of(&evm_address) {
+            addresses.remove(index);
+        }
+        set_entry(
+            &env,
+            &FactoryDataKey::EvmAddresses(identity.clone()),
+            &addresses,
+        );
+        remove_entry(&env, &key);
+
+        publish_event(
+            &env,
+            "evm_address_unlinked",
+            &identity,
+            EvmLinkEvent { evm_address },
+        );
+
+        Ok(())
+    }
+
+    // The identity a linked EVM address belongs to
+    pub fn get_identity_by_evm(env: Env, evm_address: BytesN<20>) -> Result<Address, FactoryError> {
+        get_entry(&env, &FactoryDataKey::EvmIdentity(evm_address))
+            .ok_or(FactoryError::IdentityNotFound)
+    }
+
+    pub fn get_evm_addresses(env: Env, identity: Address) -> Vec<BytesN<20>> {
+        get_entry::<Vec<BytesN<20>>>(&env, &FactoryDataKey::EvmAddresses(identity))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_owner(env: Env) -> Result<Address, FactoryError> {
         env.storage()
             .instance()
-            .set(&symbol_short!("owner"), &owner);
+            .get(&FactoryDataKey::Owner)
+            .ok_or(FactoryError::NotInitialized)
+    }
+
+    // `owner` takes over once it calls `accept_ownership`
+    pub fn transfer_ownership(env: Env, owner: Address) -> Result<(), FactoryError> {
+        let previous = only_owner(&env)?;
+        AccessControl::start_ownership_transfer(&env, previous, owner);
 
-        env.events().publish((Symbol::new(&env, "set_owner"),), owner);
+        Ok(())
+    }
+
+    pub fn accept_ownership(env: Env) -> Result<(), FactoryError> {
+        let previous = Self::get_owner(env.clone())?;
+        let owner = AccessControl::accept_ownership(&env)?;
+
+        env.storage().instance().set(&FactoryDataKey::Owner, &owner);
+
+        publish_event(
+            &env,
+            "owner_changed",
+            &env.current_contract_address(),
+            OwnerChangedEvent { previous, owner },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_pending_owner(env: Env) -> Result<Option<Address>, FactoryError> {
+        Ok(AccessControl::pending_owner(&env))
+    }
+
+    // The owner grants any role, admins every role but Admin
+    pub fn grant_role(
+        env: Env,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), FactoryError> {
+        let owner = Self::get_owner(env.clone())?;
+        AccessControl::grant_role(&env, &owner, sender, role, account)?;
+        Ok(())
+    }
+
+    pub fn revoke_role(
+        env: Env,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), FactoryError> {
+        let owner = Self::get_owner(env.clone())?;
+        AccessControl::revoke_role(&env, &owner, sender, role, account)?;
+        Ok(())
+    }
+
+    pub fn renounce_role(env: Env, account: Address, role: Role) -> Result<(), FactoryError> {
+        AccessControl::renounce_role(&env, account, role);
+        Ok(())
+    }
+
+    // The owner holds every role
+    pub fn has_role(env: Env, role: Role, account: Address) -> Result<bool, FactoryError> {
+        let owner = Self::get_owner(env.clone())?;
+        Ok(AccessControl::has_role(&env, &owner, role, &account))
     }
 }
 
-fn only_owner(env: &Env) -> Address {
+fn only_owner(env: &Env) -> Result<Address, FactoryError> {
     let owner: Address = env
         .storage()
         .instance()
-        .get(&symbol_short!("owner"))
-        .unwrap();
+        .get(&FactoryDataKey::Owner)
+        .ok_or(FactoryError::NotInitialized)?;
     owner.require_auth();
 
-    owner
+    Ok(owner)
+}
+
+// Checks that `sender` holds `role`, which the owner always does, and authorized the call
+fn require_role(env: &Env, role: Role, sender: &Address) -> Result<(), FactoryError> {
+    let owner = FactoryContract::get_owner(env.clone())?;
+    Ok(AccessControl::require_role(env, &owner, role, sender)?)
+}
+
+// The fixed initialization of every identity the factory deploys, which runs
+// the latest implementation
+fn initialize_identity(env: &Env, identity: &Address, management_key: &Address) {
+    env.invoke_contract::<()>(
+        identity,
+        &Symbol::new(env, "initialize"),
+        vec![env, management_key.into_val(env)],
+    );
+
+    set_entry(
+        env,
+        &FactoryDataKey::IdentityVersion(identity.clone()),
+        &identity_wasm_version(env),
+    );
+}
+
+// Deploys `wallet`'s `index`-th identity, unless one was already deployed there
+// and deploying again would trap
+fn deploy_identity(
+    env: &Env,
+    wasm_hash: BytesN<32>,
+    manager: &Address,
+    wallet: Address,
+    index: u32,
+) -> Result<Address, FactoryError> {
+    let deployer = env
+        .deployer()
+        .with_current_contract(identity_deploy_salt(env, &wallet, index));
+    if has_entry(
+        env,
+        &FactoryDataKey::IdentityIndex(deployer.deployed_address()),
+    ) {
+        return Err(FactoryError::IdentityAlreadyExists);
+    }
+
+    let identity = deployer.deploy(wasm_hash);
+    let salt = identity_salt(env, &wallet, index);
+    setup_identity(env, &identity, manager, wallet, salt);
+    Ok(identity)
+}
+
+// Initializes a freshly deployed identity with `manager` as management key,
+// which then adds `wallet` as another one, and links wallet and identity
+fn setup_identity(
+    env: &Env,
+    identity: &Address,
+    manager: &Address,
+    wallet: Address,
+    salt: BytesN<32>,
+) {
+    initialize_identity(env, identity, manager);
+    if *manager != wallet {
+        KeyHolderClient::new(env, identity).add_key(
+            manager,
+            &wallet,
+            &(KeyPurpose::Management as u32),
+            &(KeyType::ECDSA as u32),
+        );
+    }
+
+    register_identity(env, identity);
+    set_wallet(env, &wallet, identity);
+
+    publish_event(
+        env,
+        "identity_created",
+        identity,
+        IdentityCreatedEvent { wallet, salt },
+    );
+}
+
+// Checks that `manager` holds a Management key on `identity`, a registered
+// identity, and authorized the call. This is the identity's consent, no role
+// stands in for it.
+fn require_management(
+    env: &Env,
+    identity: &Address,
+    manager: &Address,
+) -> Result<(), FactoryError> {
+    if !has_entry(env, &FactoryDataKey::IdentityIndex(identity.clone())) {
+        return Err(FactoryError::IdentityNotFound);
+    }
+
+    match KeyHolderClient::new(env, identity).try_get_key(manager) {
+        Ok(Ok(key)) if key.purposes.contains(KeyPurpose::Management) => {}
+        _ => return Err(FactoryError::InsufficientPermissions),
+    }
+
+    manager.require_auth();
+    Ok(())
+}
+
+// Maps `wallet` to `identity` and appends it to the identity's wallets
+fn set_wallet(env: &Env, wallet: &Address, identity: &Address) {
+    set_entry(env, &FactoryDataKey::Identity(wallet.clone()), identity);
+
+    let mut wallets: Vec<Address> =
+        get_entry(env, &FactoryDataKey::Wallets(identity.clone())).unwrap_or(Vec::new(env));
+
+    wallets.push_back(wallet.clone());
+
+    set_entry(env, &FactoryDataKey::Wallets(identity.clone()), &wallets);
+}
+
+fn add_wallet(env: &Env, wallet: &Address, identity: &Address) {
+    set_wallet(env, wallet, identity);
+
+    publish_event(
+        env,
+        "wallet_linked",
+        identity,
+        WalletLinkEvent {
+            wallet: wallet.clone(),
+        },
+    );
+}
+
+fn remove_wallet(env: &Env, wallet: &Address, identity: &Address) -> Result<(), FactoryError> {
+    let mut wallets: Vec<Address> =
+        get_entry(env, &FactoryDataKey::Wallets(identity.clone())).unwrap_or(Vec::new(env));
+
+    let index = wallets
+        .iter()
+        .position(|x| x == *wallet)
+        .ok_or(FactoryError::WalletNotLinked)?;
+    wallets.remove(index as u32);
+
+    set_entry(env, &FactoryDataKey::Wallets(identity.clone()), &wallets);
+    remove_entry(env, &FactoryDataKey::Identity(wallet.clone()));
+
+    publish_event(
+        env,
+        "wallet_unlinked",
+        identity,
+        WalletLinkEvent {
+            wallet: wallet.clone(),
+        },
+    );
+
+    Ok(())
+}
+
+// Identities are deployed by the factory itself, so wallet and token identity
+// salts are hashed apart from each other
+fn domain_salt(env: &Env, domain: &[u8], salt: &BytesN<32>) -> BytesN<32> {
+    let mut bytes = Bytes::from_slice(env, domain);
+    bytes.append(&salt.clone().into());
+    env.crypto().keccak256(&bytes)
+}
+
+fn identity_deploy_salt(env: &Env, wallet: &Address, index: u32) -> BytesN<32> {
+    domain_salt(env, b"identity", &identity_salt(env, wallet, index))
+}
+
+fn self_service(env: &Env) -> SelfService {
+    env.storage()
+        .instance()
+        .get::<FactoryDataKey, SelfService>(&FactoryDataKey::SelfService)
+        .unwrap_or(SelfService {
+            enabled: false,
+            allowlist: false,
+        })
+}
+
+fn identity_wasm_version(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get::<FactoryDataKey, u32>(&FactoryDataKey::IdentityWasmVersion)
+        .unwrap_or(0)
+}
+
+fn identity_count(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get::<FactoryDataKey, u32>(&FactoryDataKey::IdentityCount)
+        .unwrap_or(0)
+}
+
+// Adds `identity` to the index unless it is already there
+fn register_identity(env: &Env, identity: &Address) {
+    let index_key = FactoryDataKey::IdentityIndex(identity.clone());
+    if has_entry(env, &index_key) {
+        return;
+    }
+
+    let count = identity_count(env);
+    set_entry(env, &index_key, &count);
+    set_entry(env, &FactoryDataKey::IdentityAt(count), identity);
+    env.storage()
+        .instance()
+        .set(&FactoryDataKey::IdentityCount, &(count + 1));
+}
+
+// Initialized with the unversioned layout: Symbol keys and bare Address mappings
+fn is_legacy(env: &Env) -> bool {
+    !env.storage().instance().has(&FactoryDataKey::SchemaVersion)
+        && env
+            .storage()
+            .instance()
+            .get::<Symbol, bool>(&Symbol::new(env, "initialized"))
+            .unwrap_or(false)
+}
+
+fn evm_nonce(env: &Env, evm_address: &BytesN<20>) -> u32 {
+    get_entry::<u32>(env, &FactoryDataKey::EvmNonce(evm_address.clone())).unwrap_or(0)
+}
+
+fn evm_link_message(env: &Env, evm_address: &BytesN<20>, identity: &Address) -> BytesN<32> {
+    let mut bytes = Bytes::from_slice(env, b"evm_link");
+    bytes.append(&env.current_contract_address().to_xdr(env));
+    bytes.append(&identity.clone().to_xdr(env));
+    bytes.append(&evm_address.clone().into());
+    bytes.extend_from_array(&evm_nonce(env, evm_address).to_be_bytes());
+    env.crypto().keccak256(&bytes)
+}
+
+// The EVM address that signed `message` with `personal_sign` (EIP-191): the
+// last 20 bytes of the keccak256 of the recovered uncompressed public key
+fn recover_evm_address(
+    env: &Env,
+    message: &BytesN<32>,
+    signature: &BytesN<64>,
+    recovery_id: u32,
+) -> Result<BytesN<20>, FactoryError> {
+    let recovery_id = match recovery_id {
+        0 | 1 => recovery_id,
+        27 | 28 => recovery_id - 27,
+        _ => return Err(FactoryError::InvalidEvmSignature),
+    };
+
+    let mut prefixed = Bytes::from_slice(env, b"\x19Ethereum Signed Message:\n32");
+    prefixed.append(&message.clone().into());
+    let digest = env.crypto().keccak256(&prefixed);
+
+    let public_key: Bytes = env
+        .crypto()
+        .secp256k1_recover(&digest, signature, recovery_id)
+        .into();
+    let hash: Bytes = env.crypto().keccak256(&public_key.slice(1..)).into();
+    BytesN::try_from(hash.slice(12..)).map_err(|_| FactoryError::InvalidEvmSignature)
+}
+
+// A schema 1 factory whose mappings are still being moved out of the instance
+fn is_migrating(env: &Env) -> bool {
+    FactoryContract::get_schema_version(env.clone()) == 1
+}
+
+// Moves the mappings of the next MIGRATION_BATCH registered identities to
+// persistent storage, and completes the migration after the last one
+fn migrate_batch(env: &Env) {
+    let start = env
+        .storage()
+        .instance()
+        .get::<FactoryDataKey, u32>(&FactoryDataKey::MigrationCursor)
+        .unwrap_or(0);
+    let count = identity_count(env);
+    let end = count.min(start.saturating_add(MIGRATION_BATCH));
+
+    for index in start..end {
+        let identity = match get_entry::<Address>(env, &FactoryDataKey::IdentityAt(index)) {
+            Some(identity) => identity,
+            None => continue,
+        };
+        move_entry::<Address>(env, &FactoryDataKey::IdentityAt(index));
+        move_entry::<u32>(env, &FactoryDataKey::IdentityIndex(identity.clone()));
+        move_entry::<u32>(env, &FactoryDataKey::IdentityVersion(identity.clone()));
+
+        // Wallets linked since the migration started are already persistent,
+        // the ones linked before may not be
+        let wallets_key = FactoryDataKey::Wallets(identity.clone());
+        if let Some(wallets) = get_entry::<Vec<Address>>(env, &wallets_key) {
+            move_entry::<Vec<Address>>(env, &wallets_key);
+            for wallet in wallets.iter() {
+                move_entry::<Address>(env, &FactoryDataKey::Identity(wallet));
+            }
+        }
+        let token_key = FactoryDataKey::IdentityToken(identity);
+        if let Some(token) = get_entry::<Address>(env, &token_key) {
+            move_entry::<Address>(env, &token_key);
+            move_entry::<Address>(env, &FactoryDataKey::TokenIdentity(token));
+        }
+    }
+
+    if end < count {
+        env.storage()
+            .instance()
+            .set(&FactoryDataKey::MigrationCursor, &end);
+        return;
+    }
+    env.storage()
+        .instance()
+        .remove(&FactoryDataKey::MigrationCursor);
+    env.storage()
+        .instance()
+        .set(&FactoryDataKey::SchemaVersion, &SCHEMA_VERSION);
+}
+
+// Reads a mapping and extends its TTL. A schema 1 factory still keeps the
+// mappings it hasn't moved in the instance.
+fn get_entry<V: TryFromVal<Env, Val>>(env: &Env, key: &FactoryDataKey) -> Option<V> {
+    if let Some(value) = env.storage().persistent().get::<FactoryDataKey, V>(key) {
+        extend_entry(env, key);
+        return Some(value);
+    }
+    if is_migrating(env) {
+        return env.storage().instance().get::<FactoryDataKey, V>(key);
+    }
+    None
+}
+
+fn has_entry(env: &Env, key: &FactoryDataKey) -> bool {
+    env.storage().persistent().has(key) || (is_migrating(env) && env.storage().instance().has(key))
+}
+
+fn set_entry<V: IntoVal<Env, Val>>(env: &Env, key: &FactoryDataKey, value: &V) {
+    env.storage().persistent().set(key, value);
+    extend_entry(env, key);
+    if is_migrating(env) {
+        env.storage().instance().remove(key);
+    }
+}
+
+fn remove_entry(env: &Env, key: &FactoryDataKey) {
+    env.storage().persistent().remove(key);
+    if is_migrating(env) {
+        env.storage().instance().remove(key);
+    }
+}
+
+// Extends a persistent mapping, and the instance the factory's config lives in
+fn extend_entry(env: &Env, key: &FactoryDataKey) {
+    env.storage()
+        .persistent()
+        .extend_ttl(key, MAPPING_TTL_THRESHOLD, MAPPING_TTL_EXTEND_TO);
+    env.storage()
+        .instance()
+        .extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+}
+
+// Moves a mapping from the instance to persistent storage, if it is still there
+fn move_entry<V: TryFromVal<Env, Val> + IntoVal<Env, Val>>(
+    env: &Env,
+    key: &FactoryDataKey,
+) -> Option<V> {
+    let value = env.storage().instance().get::<FactoryDataKey, V>(key)?;
+    env.storage().instance().remove(key);
+    env.storage().persistent().set(key, &value);
+    extend_entry(env, key);
+    Some(value)
 }
 
 mod test;
@@ -2,8 +2,62 @@
 extern crate std;
 
 use super::*;
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use onchainid_common::events::{RoleEvent, EVENT_NAMESPACE, EVENT_VERSION};
+use onchainid_common::{Error as KeyError, Key, KeyManager};
+use soroban_sdk::{
+    testutils::{Address as _, Events, Ledger},
+    vec, Address, Bytes, BytesN, Env, IntoVal,
+};
 
+mod factory {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/factory.wasm"
+    );
+}
+
+// Stand-in for an identity the factory deployed, with the real key management
+#[contract]
+struct TestIdentity;
+
+#[contractimpl]
+impl TestIdentity {
+    pub fn get_key(env: Env, key: Address) -> Result<Key, KeyError> {
+        KeyManager::get_key(&env, key)
+    }
+
+    pub fn set_upgrade_authority(env: Env, authority: Address) {
+        env.storage()
+            .instance()
+            .set(&symbol_short!("authority"), &authority);
+    }
+
+    // Records the hash instead of replacing the code
+    pub fn upgrade(env: Env, wasm_hash: BytesN<32>) -> Result<(), KeyError> {
+        let authority: Address = env
+            .storage()
+            .instance()
+            .get(&symbol_short!("authority"))
+            .ok_or(KeyError::UpgradeAuthorityNotSet)?;
+        authority.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&symbol_short!("wasm"), &wasm_hash);
+        Ok(())
+    }
+
+    pub fn get_wasm(env: Env) -> Option<BytesN<32>> {
+        env.storage().instance().get(&symbol_short!("wasm"))
+    }
+}
+
+fn test_identity(env: &Env, factory: &Address, manager: &Address) -> Address {
+    let identity = env.register_contract(None, TestIdentity);
+    env.as_contract(&identity, || KeyManager::initialize(env, manager.clone()))
+        .unwrap();
+    env.as_contract(factory, || register_identity(env, &identity));
+    identity
+}
 
 #[test]
 fn test_linking() {
@@ -14,17 +68,983 @@ fn test_linking() {
     let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
 
     let owner = Address::generate(&env);
+    let manager = Address::generate(&env);
     let wallet = Address::generate(&env);
-    let identity = Address::generate(&env);
 
     // Initialize the factory contract
     factory_client.initialize(&owner);
+    let identity = test_identity(&env, &factory_contract_id, &manager);
+    let other = test_identity(&env, &factory_contract_id, &manager);
+
+    // Only registered identities, and only with one of their management keys
+    let linker = Address::generate(&env);
+    assert_eq!(
+        factory_client.try_link_wallet(&wallet, &Address::generate(&env), &manager),
+        Err(Ok(FactoryError::IdentityNotFound))
+    );
+    assert_eq!(
+        factory_client.try_link_wallet(&wallet, &identity, &linker),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+
+    // Link the wallet to the identity, the wallet and the manager both sign
+    factory_client.link_wallet(&wallet, &identity, &manager);
+    let signers: std::vec::Vec<Address> = env.auths().into_iter().map(|(a, _)| a).collect();
+    assert_eq!(signers, [wallet.clone(), manager.clone()]);
+    assert_eq!(
+        factory_client.try_get_identity(&wallet),
+        Some(identity.clone())
+    );
+
+    // One identity per wallet
+    assert_eq!(
+        factory_client.try_link_wallet(&wallet, &other, &manager),
+        Err(Ok(FactoryError::WalletAlreadyLinked))
+    );
+
+    // Relinking moves the wallet between the lists
+    assert_eq!(
+        factory_client.try_relink_wallet(&wallet, &identity, &manager),
+        Err(Ok(FactoryError::WalletAlreadyLinked))
+    );
+    factory_client.relink_wallet(&wallet, &other, &manager);
+    assert_eq!(
+        factory_client.try_get_identity(&wallet),
+        Some(other.clone())
+    );
+    assert_eq!(factory_client.get_wallets(&identity).len(), 0);
+    assert_eq!(
+        factory_client.get_wallets(&other),
+        vec![&env, wallet.clone()]
+    );
+
+    let unlinked = Address::generate(&env);
+    assert_eq!(
+        factory_client.try_relink_wallet(&unlinked, &other, &manager),
+        Err(Ok(FactoryError::WalletNotLinked))
+    );
+
+    // A linker without a management key doesn't stand in for one
+    factory_client.grant_role(&owner, &Role::Linker, &linker);
+    assert_eq!(
+        factory_client.try_link_wallet(&unlinked, &identity, &linker),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+    assert_eq!(
+        factory_client.try_unlink_wallet(&wallet, &other, &linker),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+    let evm_address = BytesN::from_array(&env, &[1; 20]);
+    assert_eq!(
+        factory_client.try_link_evm_address(
+            &evm_address,
+            &identity,
+            &linker,
+            &BytesN::from_array(&env, &[0; 64]),
+            &0
+        ),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+
+    // A wallet may leave its identity on its own
+    factory_client.unlink_wallet(&wallet, &other, &wallet);
+    assert_eq!(env.auths()[0].0, wallet);
+    assert_eq!(factory_client.try_get_identity(&wallet), None);
+}
+
+#[test]
+fn test_roles() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // The owner holds every role, others only what they were granted
+    assert!(factory_client.has_role(&Role::IdentityCreator, &owner));
+    assert!(!factory_client.has_role(&Role::Admin, &admin));
+    let wasm_hash = BytesN::from_array(&env, &[1; 32]);
+    assert_eq!(
+        factory_client.try_set_identity_wasm(&admin, &wasm_hash),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+
+    // Only the owner makes admins, admins hand out the other roles
+    assert_eq!(
+        factory_client.try_grant_role(&admin, &Role::Admin, &admin),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+    factory_client.grant_role(&owner, &Role::Admin, &admin);
+    assert_eq!(
+        factory_client.try_grant_role(&admin, &Role::Admin, &creator),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+    factory_client.grant_role(&admin, &Role::IdentityCreator, &creator);
+    assert_eq!(factory_client.set_identity_wasm(&admin, &wasm_hash), 1);
+    assert_eq!(env.auths()[0].0, admin);
+
+    // Roles don't imply each other
+    assert_eq!(
+        factory_client.try_set_identity_wasm(&creator, &wasm_hash),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+    assert_eq!(
+        factory_client.try_create_token_identity(
+            &admin,
+            &Address::generate(&env),
+            &Address::generate(&env),
+            &wasm_hash
+        ),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+
+    factory_client.revoke_role(&admin, &Role::IdentityCreator, &creator);
+    assert!(!factory_client.has_role(&Role::IdentityCreator, &creator));
+    factory_client.renounce_role(&admin, &Role::Admin);
+    assert!(!factory_client.has_role(&Role::Admin, &admin));
+
+    let events = env.events().all();
+    assert_eq!(
+        events.slice(events.len() - 1..),
+        vec![
+            &env,
+            (
+                factory_contract_id.clone(),
+                (
+                    EVENT_NAMESPACE,
+                    EVENT_VERSION,
+                    Symbol::new(&env, "role_revoked"),
+                    factory_contract_id.clone(),
+                )
+                    .into_val(&env),
+                RoleEvent {
+                    role: Role::Admin,
+                    account: admin.clone(),
+                    sender: admin.clone(),
+                }
+                .into_val(&env)
+            )
+        ]
+    );
+
+    // Ownership moves only once the new owner accepts
+    let new_owner = Address::generate(&env);
+    assert_eq!(
+        factory_client.try_accept_ownership(),
+        Err(Ok(FactoryError::NoPendingOwner))
+    );
+    factory_client.transfer_ownership(&new_owner);
+    assert_eq!(factory_client.get_owner(), owner);
+    assert_eq!(factory_client.get_pending_owner(), Some(new_owner.clone()));
+    factory_client.accept_ownership();
+    assert_eq!(env.auths()[0].0, new_owner);
+    assert_eq!(factory_client.get_owner(), new_owner);
+    assert_eq!(factory_client.get_pending_owner(), None);
+    assert!(!factory_client.has_role(&Role::Admin, &owner));
+}
+
+#[test]
+fn test_registry_queries() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    // Owner-only calls fail cleanly before initialization
+    let wallet = Address::generate(&env);
+    assert_eq!(
+        factory_client.try_create_identity(&wallet, &wallet, &0),
+        Err(Ok(FactoryError::NotInitialized))
+    );
+    assert_eq!(
+        factory_client.try_get_owner(),
+        Err(Ok(FactoryError::NotInitialized))
+    );
+
+    let owner = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // Unknown wallets have no identity rather than trapping
+    assert_eq!(factory_client.try_get_identity(&wallet), None);
+
+    let manager = Address::generate(&env);
+    let identities: std::vec::Vec<Address> = (0..3)
+        .map(|_| test_identity(&env, &factory_contract_id, &manager))
+        .collect();
+    // Linking a wallet does not register the identity twice
+    factory_client.link_wallet(&wallet, &identities[0], &manager);
+
+    assert_eq!(factory_client.identity_count(), 3);
+    assert!(factory_client.is_identity(&identities[1]));
+    assert!(!factory_client.is_identity(&wallet));
+    assert_eq!(
+        factory_client.try_get_identity(&wallet),
+        Some(identities[0].clone())
+    );
+
+    let first = factory_client.get_identities(&0, &2);
+    assert_eq!(
+        first,
+        vec![&env, identities[0].clone(), identities[1].clone()]
+    );
+    assert_eq!(
+        factory_client.get_identities(&2, &2),
+        vec![&env, identities[2].clone()]
+    );
+    assert_eq!(factory_client.get_identities(&3, &2).len(), 0);
+    assert_eq!(factory_client.get_identities(&0, &u32::MAX).len(), 3);
+
+    // Unlinking keeps the identity registered
+    factory_client.unlink_wallet(&wallet, &identities[0], &manager);
+    assert_eq!(
+        factory_client.try_unlink_wallet(&wallet, &identities[0], &manager),
+        Err(Ok(FactoryError::WalletNotLinked))
+    );
+    assert!(factory_client.is_identity(&identities[0]));
+}
+
+#[test]
+fn test_predict_identity_address() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let wallet = Address::generate(&env);
+    let owner = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // One address per wallet and index, from the wallet's canonical salt
+    let predicted = factory_client.predict_identity_address(&wallet, &0);
+    let salt = identity_salt(&env, &wallet, 0);
+    assert_eq!(
+        predicted,
+        env.as_contract(&factory_contract_id, || {
+            env.deployer()
+                .with_current_contract(domain_salt(&env, b"identity", &salt))
+                .deployed_address()
+        })
+    );
+    assert_ne!(
+        factory_client.predict_identity_address(&wallet, &1),
+        predicted
+    );
+    assert_ne!(
+        factory_client.predict_identity_address(&Address::generate(&env), &0),
+        predicted
+    );
+
+    // Self-service creation deploys the wallet's first identity
+    assert_eq!(
+        factory_client.predict_my_identity_address(&wallet),
+        predicted
+    );
+
+    // The factory deploys, so the address holds whoever creates the identity
+    let new_owner = Address::generate(&env);
+    factory_client.transfer_ownership(&new_owner);
+    factory_client.accept_ownership();
+    assert_eq!(
+        factory_client.predict_identity_address(&wallet, &0),
+        predicted
+    );
+}
+
+#[test]
+fn test_identity_wasm_versions() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // Nothing to deploy until the owner approves an implementation
+    let wallet = Address::generate(&env);
+    assert_eq!(factory_client.get_identity_wasm_version(), 0);
+    assert_eq!(
+        factory_client.try_create_identity(&owner, &wallet, &0),
+        Err(Ok(FactoryError::IdentityWasmNotSet))
+    );
+
+    let first = BytesN::from_array(&env, &[1; 32]);
+    let second = BytesN::from_array(&env, &[2; 32]);
+    assert_eq!(factory_client.set_identity_wasm(&owner, &first), 1);
+    assert_eq!(factory_client.set_identity_wasm(&owner, &second), 2);
+
+    let events = env.events().all();
+    assert_eq!(
+        events.slice(events.len() - 1..),
+        vec![
+            &env,
+            (
+                factory_contract_id.clone(),
+                (
+                    EVENT_NAMESPACE,
+                    EVENT_VERSION,
+                    Symbol::new(&env, "identity_wasm_set"),
+                    factory_contract_id.clone(),
+                )
+                    .into_val(&env),
+                IdentityWasmSetEvent {
+                    version: 2,
+                    wasm_hash: second.clone()
+                }
+                .into_val(&env)
+            )
+        ]
+    );
+
+    // Earlier versions stay readable
+    assert_eq!(factory_client.get_identity_wasm_version(), 2);
+    assert_eq!(factory_client.get_identity_wasm(&1), first);
+    assert_eq!(factory_client.get_identity_wasm(&2), second);
+    assert_eq!(
+        factory_client.try_get_identity_wasm(&3),
+        Err(Ok(FactoryError::IdentityWasmNotSet))
+    );
+}
+
+#[test]
+fn test_upgrade_identities() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let manager = Address::generate(&env);
+    factory_client.initialize(&owner);
+    assert_eq!(
+        factory_client.try_upgrade_identities(&owner, &0, &10),
+        Err(Ok(FactoryError::IdentityWasmNotSet))
+    );
+
+    // Three identities, the second one never trusted the factory
+    let identities: std::vec::Vec<Address> = (0..3)
+        .map(|_| test_identity(&env, &factory_contract_id, &manager))
+        .collect();
+    for i in [0, 2] {
+        TestIdentityClient::new(&env, &identities[i]).set_upgrade_authority(&factory_contract_id);
+    }
+    let wasm_hash = BytesN::from_array(&env, &[1; 32]);
+    factory_client.set_identity_wasm(&owner, &wasm_hash);
+
+    // Batches walk the registry, failures don't stop them
+    let report = factory_client.upgrade_identities(&owner, &0, &2);
+    assert_eq!(
+        report,
+        UpgradeReport {
+            version: 1,
+            upgraded: vec![&env, identities[0].clone()],
+            failed: vec![&env, identities[1].clone()],
+        }
+    );
+    let events = env.events().all();
+    assert_eq!(
+        events.slice(events.len() - 1..),
+        vec![
+            &env,
+            (
+                factory_contract_id.clone(),
+                (
+                    EVENT_NAMESPACE,
+                    EVENT_VERSION,
+                    Symbol::new(&env, "identities_upgraded"),
+                    factory_contract_id.clone(),
+                )
+                    .into_val(&env),
+                IdentitiesUpgradedEvent {
+                    version: 1,
+                    upgraded: 1,
+                    failed: vec![&env, identities[1].clone()],
+                }
+                .into_val(&env)
+            )
+        ]
+    );
+
+    let report = factory_client.upgrade_identities(&owner, &2, &2);
+    assert_eq!(report.upgraded, vec![&env, identities[2].clone()]);
+    assert_eq!(report.failed.len(), 0);
+    for i in [0, 2] {
+        assert_eq!(factory_client.get_identity_version(&identities[i]), 1);
+        assert_eq!(
+            TestIdentityClient::new(&env, &identities[i]).get_wasm(),
+            Some(wasm_hash.clone())
+        );
+    }
+    assert_eq!(factory_client.get_identity_version(&identities[1]), 0);
+
+    // Identities on the current version are skipped, the rest retried
+    let report = factory_client.upgrade_identities(&owner, &0, &10);
+    assert_eq!(report.upgraded.len(), 0);
+    assert_eq!(report.failed, vec![&env, identities[1].clone()]);
+}
+
+#[test]
+fn test_self_service_settings() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // Off until the owner turns it on
+    let wallet = Address::generate(&env);
+    assert!(!factory_client.get_self_service().enabled);
+    assert_eq!(
+        factory_client.try_create_my_identity(&wallet),
+        Err(Ok(FactoryError::SelfServiceDisabled))
+    );
+
+    let fee_token = Address::generate(&env);
+    assert_eq!(
+        factory_client.try_set_creation_fee(&owner, &fee_token, &-1),
+        Err(Ok(FactoryError::InvalidFee))
+    );
+    factory_client.set_creation_fee(&owner, &fee_token, &100);
+    assert_eq!(
+        factory_client.get_creation_fee(),
+        Some(CreationFee {
+            token: fee_token.clone(),
+            amount: 100
+        })
+    );
+    factory_client.set_creation_fee(&owner, &fee_token, &0);
+    assert_eq!(factory_client.get_creation_fee(), None);
+
+    let config = SelfService {
+        enabled: true,
+        allowlist: true,
+    };
+    factory_client.set_self_service(&owner, &config);
+    assert_eq!(factory_client.get_self_service(), config);
+    assert_eq!(
+        factory_client.try_create_my_identity(&wallet),
+        Err(Ok(FactoryError::WalletNotAllowed))
+    );
+
+    // Allowed wallets get as far as the missing implementation
+    factory_client.set_allowed(&owner, &wallet, &true);
+    assert!(factory_client.is_allowed(&wallet));
+    assert_eq!(
+        factory_client.try_create_my_identity(&wallet),
+        Err(Ok(FactoryError::IdentityWasmNotSet))
+    );
+    factory_client.set_allowed(&owner, &wallet, &false);
+    assert!(!factory_client.is_allowed(&wallet));
+}
+
+#[test]
+fn test_token_factories() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    let token_factory = Address::generate(&env);
+    factory_client.grant_role(&owner, &Role::TokenFactory, &token_factory);
+    assert!(factory_client.has_role(&Role::TokenFactory, &token_factory));
+
+    // Identity creators and token factories may create token identities, nobody else
+    let token = Address::generate(&env);
+    let token_owner = Address::generate(&env);
+    let salt = BytesN::from_array(&env, &[7; 32]);
+    assert_eq!(
+        factory_client.try_create_token_identity(
+            &Address::generate(&env),
+            &token,
+            &token_owner,
+            &salt
+        ),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+    for sender in [&owner, &token_factory] {
+        assert_eq!(
+            factory_client.try_create_token_identity(sender, &token, &token_owner, &salt),
+            Err(Ok(FactoryError::IdentityWasmNotSet))
+        );
+    }
+    assert_eq!(
+        factory_client.try_get_token_identity(&token),
+        Err(Ok(FactoryError::IdentityNotFound))
+    );
+
+    // A token salt equal to a wallet's never deploys to that wallet's address
+    let wallet = Address::generate(&env);
+    assert_ne!(
+        factory_client.predict_token_identity_address(&identity_salt(&env, &wallet, 0)),
+        factory_client.predict_my_identity_address(&wallet)
+    );
+
+    factory_client.revoke_role(&owner, &Role::TokenFactory, &token_factory);
+    assert!(!factory_client.has_role(&Role::TokenFactory, &token_factory));
+    assert_eq!(
+        factory_client.try_create_token_identity(&token_factory, &token, &token_owner, &salt),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+}
+
+#[test]
+fn test_migrate_legacy_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let wallet = Address::generate(&env);
+    let identity = Address::generate(&env);
+
+    // Storage as written before schema versioning
+    env.as_contract(&factory_contract_id, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "initialized"), &true);
+        env.storage()
+            .instance()
+            .set(&symbol_short!("owner"), &owner);
+        env.storage().instance().set(&wallet, &identity);
+        env.storage()
+            .instance()
+            .set(&identity, &Vec::from_array(&env, [wallet.clone()]));
+    });
+
+    assert!(factory_client.get_initialized());
+    assert_eq!(factory_client.get_schema_version(), 0);
+    assert_eq!(
+        factory_client.try_initialize(&owner),
+        Err(Ok(FactoryError::AlreadyInitialized))
+    );
+
+    factory_client.migrate(&Vec::from_array(&env, [wallet.clone()]));
+
+    assert_eq!(factory_client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(factory_client.get_owner(), owner);
+    assert_eq!(
+        factory_client.try_get_identity(&wallet),
+        Some(identity.clone())
+    );
+    assert_eq!(factory_client.get_wallets(&identity).len(), 1);
+    assert!(factory_client.is_identity(&identity));
+    assert_eq!(factory_client.identity_count(), 1);
+
+    env.as_contract(&factory_contract_id, || {
+        assert!(!env.storage().instance().has(&wallet));
+        assert!(!env.storage().instance().has(&identity));
+    });
+}
+
+#[test]
+fn test_migrate_instance_mappings() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let allowed = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // Schema 1 kept every mapping in the instance
+    let count = MIGRATION_BATCH + 2;
+    let mut wallets = Vec::new(&env);
+    let mut identities = Vec::new(&env);
+    env.as_contract(&factory_contract_id, || {
+        let instance = env.storage().instance();
+        instance.set(&FactoryDataKey::SchemaVersion, &1u32);
+        for index in 0..count {
+            let wallet = Address::generate(&env);
+            let identity = Address::generate(&env);
+            instance.set(&FactoryDataKey::IdentityAt(index), &identity);
+            instance.set(&FactoryDataKey::IdentityIndex(identity.clone()), &index);
+            instance.set(&FactoryDataKey::Identity(wallet.clone()), &identity);
+            instance.set(
+                &FactoryDataKey::Wallets(identity.clone()),
+                &Vec::from_array(&env, [wallet.clone()]),
+            );
+            wallets.push_back(wallet);
+            identities.push_back(identity);
+        }
+        instance.set(&FactoryDataKey::IdentityCount, &count);
+        instance.set(&FactoryDataKey::Allowed(allowed.clone()), &true);
+    });
+
+    factory_client.migrate(&Vec::from_array(&env, [allowed.clone()]));
+    assert_eq!(factory_client.get_schema_version(), 1);
+
+    // Moved and unmoved mappings both read while the migration is under way
+    let last = identities.get(count - 1).unwrap();
+    assert_eq!(
+        factory_client.try_get_identity(&wallets.get(0).unwrap()),
+        identities.get(0)
+    );
+    assert_eq!(
+        factory_client.try_get_identity(&wallets.get(count - 1).unwrap()),
+        Some(last.clone())
+    );
+    assert!(factory_client.is_identity(&last));
+    assert!(factory_client.is_allowed(&allowed));
+
+    factory_client.migrate(&Vec::new(&env));
+    assert_eq!(factory_client.get_schema_version(), SCHEMA_VERSION);
+
+    for index in 0..count {
+        let identity = identities.get(index).unwrap();
+        assert_eq!(
+            factory_client.try_get_identity(&wallets.get(index).unwrap()),
+            Some(identity.clone())
+        );
+        assert_eq!(factory_client.get_wallets(&identity).len(), 1);
+        assert!(factory_client.is_identity(&identity));
+    }
+    assert_eq!(factory_client.get_identities(&0, &count), identities);
+    assert!(factory_client.is_allowed(&allowed));
+
+    env.as_contract(&factory_contract_id, || {
+        assert!(!env.storage().instance().has(&FactoryDataKey::IdentityAt(0)));
+        assert!(!env
+            .storage()
+            .instance()
+            .has(&FactoryDataKey::Identity(wallets.get(0).unwrap())));
+        assert!(!env
+            .storage()
+            .instance()
+            .has(&FactoryDataKey::Allowed(allowed.clone())));
+        assert!(!env
+            .storage()
+            .instance()
+            .has(&FactoryDataKey::MigrationCursor));
+    });
+}
+
+#[test]
+fn test_upgrade_then_migrate() {
+    let env = Env::default();
+    env.mock_all_auths();
+    // Running the factory as wasm exceeds a transaction's budget
+    env.budget().reset_unlimited();
+
+    let factory_contract_id = env.register_contract_wasm(None, factory::WASM);
+    let factory_client = factory::Client::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    factory_client.initialize(&owner);
+
+    // A schema 1 factory with a mapping still in its instance
+    let wallet = Address::generate(&env);
+    let identity = Address::generate(&env);
+    env.as_contract(&factory_contract_id, || {
+        let instance = env.storage().instance();
+        instance.set(&FactoryDataKey::SchemaVersion, &1u32);
+        instance.set(&FactoryDataKey::IdentityAt(0), &identity);
+        instance.set(&FactoryDataKey::IdentityIndex(identity.clone()), &0u32);
+        instance.set(&FactoryDataKey::Identity(wallet.clone()), &identity);
+        instance.set(
+            &FactoryDataKey::Wallets(identity.clone()),
+            &Vec::from_array(&env, [wallet.clone()]),
+        );
+        instance.set(&FactoryDataKey::IdentityCount, &1u32);
+    });
+
+    // Only admins replace the code
+    let wasm_hash = env.deployer().upload_contract_wasm(factory::WASM);
+    let admin = Address::generate(&env);
+    assert_eq!(
+        factory_client.try_upgrade(&admin, &wasm_hash),
+        Err(Ok(factory::FactoryError::InsufficientPermissions))
+    );
+    factory_client.grant_role(&owner, &factory::Role::Admin, &admin);
+    factory_client.upgrade(&admin, &wasm_hash);
+    assert_eq!(env.auths()[0].0, admin);
+
+    factory_client.migrate(&Vec::new(&env));
+    assert_eq!(factory_client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(factory_client.try_get_identity(&wallet), Some(identity));
+}
 
-    // Link the wallet to the identity
-    factory_client.link_wallet(&wallet, &identity);
+#[test]
+fn test_mapping_ttl() {
+    let env = Env::default();
+    env.mock_all_auths();
 
-    // Get the identity
-    let linked_identity = factory_client.get_identity(&wallet);
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let manager = Address::generate(&env);
+    let wallet = Address::generate(&env);
+    let other = Address::generate(&env);
+    factory_client.initialize(&owner);
+    let identity = test_identity(&env, &factory_contract_id, &manager);
+    factory_client.link_wallet(&wallet, &identity, &manager);
+
+    env.as_contract(&factory_contract_id, || {
+        assert!(env
+            .storage()
+            .persistent()
+            .has(&FactoryDataKey::Identity(wallet.clone())));
+        assert!(!env
+            .storage()
+            .instance()
+            .has(&FactoryDataKey::Identity(wallet.clone())));
+    });
+
+    // Well past the default TTL of entries, and then past the TTL the link
+    // was written with
+    env.ledger()
+        .with_mut(|ledger| ledger.sequence_number += 400_000);
+    assert_eq!(
+        factory_client.try_get_identity(&wallet),
+        Some(identity.clone())
+    );
+    factory_client.extend_wallet_ttl(&wallet);
+
+    env.ledger()
+        .with_mut(|ledger| ledger.sequence_number += 400_000);
+    assert_eq!(
+        factory_client.try_get_identity(&wallet),
+        Some(identity.clone())
+    );
+    assert_eq!(factory_client.get_wallets(&identity).len(), 1);
+    assert_eq!(factory_client.get_identities(&0, &1).len(), 1);
+    assert_eq!(
+        factory_client.try_extend_wallet_ttl(&other),
+        Err(Ok(FactoryError::IdentityNotFound))
+    );
+}
+
+// An EVM account: its 20-byte address and a personal_sign of `message`
+struct EvmKey(k256::ecdsa::SigningKey);
+
+impl EvmKey {
+    fn generate(seed: u8) -> Self {
+        EvmKey(k256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    fn address(&self, env: &Env) -> BytesN<20> {
+        let point = self.0.verifying_key().to_encoded_point(false);
+        let hash: Bytes = env
+            .crypto()
+            .keccak256(&Bytes::from_slice(env, &point.as_bytes()[1..]))
+            .into();
+        hash.slice(12..).try_into().unwrap()
+    }
+
+    fn sign(&self, env: &Env, message: &BytesN<32>) -> (BytesN<64>, u32) {
+        let mut prefixed = Bytes::from_slice(env, b"\x19Ethereum Signed Message:\n32");
+        prefixed.append(&message.clone().into());
+        let digest = env.crypto().keccak256(&prefixed).to_array();
+
+        let (signature, recovery_id) = self.0.sign_prehash_recoverable(&digest).unwrap();
+        (
+            BytesN::from_array(env, &signature.to_bytes().into()),
+            recovery_id.to_byte() as u32,
+        )
+    }
+}
+
+#[test]
+fn test_evm_linking() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let manager = Address::generate(&env);
+    factory_client.initialize(&owner);
+    let identity = test_identity(&env, &factory_contract_id, &manager);
+    let other = test_identity(&env, &factory_contract_id, &manager);
+
+    let evm_key = EvmKey::generate(1);
+    let evm_address = evm_key.address(&env);
+    let message = factory_client.get_evm_link_message(&evm_address, &identity);
+    let (signature, recovery_id) = evm_key.sign(&env, &message);
+
+    // Signed by another EVM key, or for another identity
+    let (forged, forged_id) = EvmKey::generate(2).sign(&env, &message);
+    assert_eq!(
+        factory_client.try_link_evm_address(&evm_address, &identity, &manager, &forged, &forged_id),
+        Err(Ok(FactoryError::InvalidEvmSignature))
+    );
+    assert_eq!(
+        factory_client.try_link_evm_address(
+            &evm_address,
+            &other,
+            &manager,
+            &signature,
+            &recovery_id
+        ),
+        Err(Ok(FactoryError::InvalidEvmSignature))
+    );
+    assert_eq!(
+        factory_client.try_link_evm_address(
+            &evm_address,
+            &identity,
+            &Address::generate(&env),
+            &signature,
+            &recovery_id
+        ),
+        Err(Ok(FactoryError::InsufficientPermissions))
+    );
+
+    // EVM wallets report the recovery id as `v`
+    factory_client.link_evm_address(
+        &evm_address,
+        &identity,
+        &manager,
+        &signature,
+        &(recovery_id + 27),
+    );
+    assert_eq!(factory_client.get_identity_by_evm(&evm_address), identity);
+    assert_eq!(
+        factory_client.get_evm_addresses(&identity),
+        vec![&env, evm_address.clone()]
+    );
+
+    // One identity per EVM address
+    let message = factory_client.get_evm_link_message(&evm_address, &other);
+    let (to_other, to_other_id) = evm_key.sign(&env, &message);
+    assert_eq!(
+        factory_client.try_link_evm_address(
+            &evm_address,
+            &other,
+            &manager,
+            &to_other,
+            &to_other_id
+        ),
+        Err(Ok(FactoryError::EvmAddressAlreadyLinked))
+    );
+
+    factory_client.unlink_evm_address(&evm_address, &manager);
+    assert_eq!(
+        factory_client.try_get_identity_by_evm(&evm_address),
+        Err(Ok(FactoryError::IdentityNotFound))
+    );
+    assert_eq!(factory_client.get_evm_addresses(&identity).len(), 0);
+    assert_eq!(
+        factory_client.try_unlink_evm_address(&evm_address, &manager),
+        Err(Ok(FactoryError::EvmAddressNotLinked))
+    );
+
+    // The first signature doesn't link it again, the one made after the unlink does
+    assert_eq!(
+        factory_client.try_link_evm_address(
+            &evm_address,
+            &identity,
+            &manager,
+            &signature,
+            &recovery_id
+        ),
+        Err(Ok(FactoryError::InvalidEvmSignature))
+    );
+    factory_client.link_evm_address(&evm_address, &other, &manager, &to_other, &to_other_id);
+    assert_eq!(factory_client.get_identity_by_evm(&evm_address), other);
+}
+
+#[test]
+fn test_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_contract_id = env.register_contract(None, FactoryContract);
+    let factory_client = FactoryContractClient::new(&env, &factory_contract_id);
+
+    let owner = Address::generate(&env);
+    let new_owner = Address::generate(&env);
+    let wallet = Address::generate(&env);
+    let identity = test_identity(&env, &factory_contract_id, &owner);
+
+    let topics = |name: &str, subject: &Address| {
+        (
+            EVENT_NAMESPACE,
+            EVENT_VERSION,
+            Symbol::new(&env, name),
+            subject.clone(),
+        )
+            .into_val(&env)
+    };
+
+    factory_client.initialize(&owner);
+    factory_client.link_wallet(&wallet, &identity, &owner);
+    factory_client.unlink_wallet(&wallet, &identity, &owner);
+    factory_client.transfer_ownership(&new_owner);
+    factory_client.accept_ownership();
 
-    assert!(linked_identity == identity, "Identity should be linked to wallet");
-}
\ No newline at end of file
+    let mut events = Vec::new(&env);
+    for event in env.events().all().iter() {
+        if event.0 == factory_contract_id {
+            events.push_back(event);
+        }
+    }
+    assert_eq!(
+        events,
+        vec![
+            &env,
+            (
+                factory_contract_id.clone(),
+                topics("initialized", &factory_contract_id),
+                InitializedEvent {
+                    owner: owner.clone()
+                }
+                .into_val(&env)
+            ),
+            (
+                factory_contract_id.clone(),
+                topics("wallet_linked", &identity),
+                WalletLinkEvent {
+                    wallet: wallet.clone()
+                }
+                .into_val(&env)
+            ),
+            (
+                factory_contract_id.clone(),
+                topics("wallet_unlinked", &identity),
+                WalletLinkEvent {
+                    wallet: wallet.clone()
+                }
+                .into_val(&env)
+            ),
+            (
+                factory_contract_id.clone(),
+                topics("ownership_transfer_started", &factory_contract_id),
+                OwnerChangedEvent {
+                    previous: owner.clone(),
+                    owner: new_owner.clone()
+                }
+                .into_val(&env)
+            ),
+            (
+                factory_contract_id.clone(),
+                topics("owner_changed", &factory_contract_id),
+                OwnerChangedEvent {
+                    previous: owner.clone(),
+                    owner: new_owner.clone()
+                }
+                .into_val(&env)
+            ),
+        ]
+    );
+}
@@ -1,12 +1,17 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contracterror, contractimpl, symbol_short, Address, BytesN, Bytes, Env, Symbol, U256,FromVal, xdr::ToXdr
+    contract, contracterror, contractimpl, contracttype, symbol_short, Address, BytesN, Env,
+    String, Symbol, U256,
 };
 
+use onchainid_common::events::{
+    publish_event, ClaimValidatedEvent, ContractSetEvent, InitializedEvent, MigratedEvent,
+    OwnerChangedEvent, UpgradedEvent,
+};
+use onchainid_common::{hash_claim, AccessControl, AccessError, Role};
+
 mod factory {
-    soroban_sdk::contractimport!(
-        file = "../../target/wasm32-unknown-unknown/release/factory.wasm"
-    );
+    soroban_sdk::contractimport!(file = "../../target/wasm32-unknown-unknown/release/factory.wasm");
 }
 mod claim_issuer {
     soroban_sdk::contractimport!(
@@ -22,10 +27,45 @@ mod identity {
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
-pub enum Error {
+pub enum GatedError {
     AlreadyInitialized = 1,
     InsufficientPermissions = 2,
     IdentityNotFound = 3,
+    AlreadyMigrated = 4,
+    NotInitialized = 5,
+    NoPendingOwner = 6,
+}
+
+impl From<AccessError> for GatedError {
+    fn from(error: AccessError) -> Self {
+        match error {
+            AccessError::Unauthorized => GatedError::InsufficientPermissions,
+            AccessError::NoPendingOwner => GatedError::NoPendingOwner,
+        }
+    }
+}
+
+// Version of the storage layout, contracts from before versioning report 0
+const SCHEMA_VERSION: u32 = 1;
+
+#[contracttype]
+#[derive(Clone)]
+pub enum GatedDataKey {
+    Initialized,
+    SchemaVersion,
+    Owner,
+    Factory,
+    Issuer,
+}
+
+// Mirrors the claim issuer's metadata so it is part of this contract's spec
+#[contracttype]
+#[derive(Clone)]
+pub struct IssuerMetadata {
+    pub name: String,
+    pub jurisdiction: String,
+    pub uri: String,
+    pub contact: String,
 }
 
 #[contract]
@@ -33,12 +73,13 @@ pub struct GatedContract;
 
 #[contractimpl]
 impl GatedContract {
-    pub fn get_initialized(env: Env) -> Result<bool, Error> {
+    pub fn get_initialized(env: Env) -> Result<bool, GatedError> {
         Ok(env
             .storage()
             .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false))
+            .get::<GatedDataKey, bool>(&GatedDataKey::Initialized)
+            .unwrap_or(false)
+            || is_legacy(&env))
     }
 
     pub fn initialize(
@@ -46,44 +87,176 @@ impl GatedContract {
         identity_factory: Address,
         claim_issuer: Address,
         owner: Address,
-    ) -> Result<(), Error> {
-        let initialized = env
-            .storage()
+    ) -> Result<(), GatedError> {
+        if Self::get_initialized(env.clone())? {
+            return Err(GatedError::AlreadyInitialized);
+        }
+
+        env.storage()
             .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false);
+            .set(&GatedDataKey::Initialized, &true);
+        env.storage()
+            .instance()
+            .set(&GatedDataKey::SchemaVersion, &SCHEMA_VERSION);
+        env.storage()
+            .persistent()
+            .set(&GatedDataKey::Factory, &identity_factory);
+        env.storage()
+            .persistent()
+            .set(&GatedDataKey::Issuer, &claim_issuer);
+        env.storage().instance().set(&GatedDataKey::Owner, &owner);
 
-        if initialized {
-            return Err(Error::AlreadyInitialized);
+        publish_event(
+            &env,
+            "initialized",
+            &env.current_contract_address(),
+            InitializedEvent { owner },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_schema_version(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get::<GatedDataKey, u32>(&GatedDataKey::SchemaVersion)
+            .unwrap_or(0)
+    }
+
+    // Moves a contract deployed before schema versioning to the typed layout.
+    // Must be authorized by the legacy owner.
+    pub fn migrate(env: Env) -> Result<(), GatedError> {
+        if !is_legacy(&env) {
+            return Err(if Self::get_schema_version(env.clone()) >= SCHEMA_VERSION {
+                GatedError::AlreadyMigrated
+            } else {
+                GatedError::NotInitialized
+            });
         }
 
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&symbol_short!("owner"))
+            .ok_or(GatedError::NotInitialized)?;
+        owner.require_auth();
+
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "initialized"), &true);
+            .remove(&Symbol::new(&env, "initialized"));
+        env.storage().instance().remove(&symbol_short!("owner"));
         env.storage()
+            .instance()
+            .set(&GatedDataKey::Initialized, &true);
+        env.storage().instance().set(&GatedDataKey::Owner, &owner);
+
+        if let Some(factory) = env
+            .storage()
             .persistent()
-            .set(&symbol_short!("factory"), &identity_factory);
-        env.storage()
+            .get::<Symbol, Address>(&symbol_short!("factory"))
+        {
+            env.storage().persistent().remove(&symbol_short!("factory"));
+            env.storage()
+                .persistent()
+                .set(&GatedDataKey::Factory, &factory);
+
+            publish_event(
+                &env,
+                "identity_factory_set",
+                &env.current_contract_address(),
+                ContractSetEvent { contract: factory },
+            );
+        }
+        if let Some(issuer) = env
+            .storage()
             .persistent()
-            .set(&symbol_short!("issuer"), &claim_issuer);
+            .get::<Symbol, Address>(&symbol_short!("issuer"))
+        {
+            env.storage().persistent().remove(&symbol_short!("issuer"));
+            env.storage()
+                .persistent()
+                .set(&GatedDataKey::Issuer, &issuer);
+
+            publish_event(
+                &env,
+                "claim_issuer_set",
+                &env.current_contract_address(),
+                ContractSetEvent { contract: issuer },
+            );
+        }
+
         env.storage()
             .instance()
-            .set(&symbol_short!("owner"), &owner);
+            .set(&GatedDataKey::SchemaVersion, &SCHEMA_VERSION);
+
+        publish_event(
+            &env,
+            "migrated",
+            &env.current_contract_address(),
+            MigratedEvent {
+                by: owner,
+                version: SCHEMA_VERSION,
+            },
+        );
+        Ok(())
+    }
+
+    // Replaces the contract code, signed by the owner. The new code takes over
+    // once this invocation has finished.
+    pub fn upgrade(env: Env, wasm_hash: BytesN<32>) -> Result<(), GatedError> {
+        let owner = only_owner(&env)?;
+
+        env.deployer()
+            .update_current_contract_wasm(wasm_hash.clone());
 
+        publish_event(
+            &env,
+            "upgraded",
+            &env.current_contract_address(),
+            UpgradedEvent {
+                authority: owner,
+                wasm_hash,
+            },
+        );
         Ok(())
     }
 
+    pub fn set_claim_issuer(env: Env, sender: Address, issuer: Address) -> Result<(), GatedError> {
+        require_role(&env, Role::PolicyAdmin, &sender)?;
 
-    pub fn set_claim_issuer(env: Env, issuer: Address) {
-        only_owner(&env);
+        env.storage()
+            .persistent()
+            .set(&GatedDataKey::Issuer, &issuer);
 
-        env.storage().persistent().set(&symbol_short!("issuer"), &issuer);
+        publish_event(
+            &env,
+            "claim_issuer_set",
+            &env.current_contract_address(),
+            ContractSetEvent { contract: issuer },
+        );
+
+        Ok(())
     }
 
-    pub fn set_identity_factory(env: Env, factory: Address) {
-        only_owner(&env);
+    pub fn set_identity_factory(
+        env: Env,
+        sender: Address,
+        factory: Address,
+    ) -> Result<(), GatedError> {
+        require_role(&env, Role::PolicyAdmin, &sender)?;
 
-        env.storage().persistent().set(&symbol_short!("factory"), &factory);
+        env.storage()
+            .persistent()
+            .set(&GatedDataKey::Factory, &factory);
+
+        publish_event(
+            &env,
+            "identity_factory_set",
+            &env.current_contract_address(),
+            ContractSetEvent { contract: factory },
+        );
+
+        Ok(())
     }
 
     // Validate a claim
@@ -92,74 +265,165 @@ impl GatedContract {
     // 3. Get the claim from the identity
     // 4. Extract topic, signature, data from the claim
     // 4. Call is_claim_valid on the claim issuer
-    pub fn validate_claim(env: Env, sender: Address,  issuer: Address, required_topic: U256) -> bool {
-
+    pub fn validate_claim(
+        env: Env,
+        sender: Address,
+        issuer: Address,
+        required_topic: U256,
+    ) -> bool {
         // Get the factory address
-        let factory_address = env.storage()
-        .persistent()
-            .get(&symbol_short!("factory"))
+        let factory_address = env
+            .storage()
+            .persistent()
+            .get(&GatedDataKey::Factory)
             .unwrap();
 
         let factory_client = factory::Client::new(&env, &factory_address);
-        // Get the identity of the sender
-        let user_identity = factory_client.get_identity(&sender);
-
-        // Generate the claim id
-        let claim_id = hash_claim(&env, &issuer, &required_topic);
+        // Get the identity of the sender, a wallet without one has no valid claim
+        let valid = match factory_client.try_get_identity(&sender) {
+            Some(user_identity) => {
+                // Generate the claim id
+                let claim_id = hash_claim(&env, &issuer, &required_topic);
 
-        // Get the identity client
-        let identity_client = identity::Client::new(&env, &user_identity);
+                // Get the identity client
+                let identity_client = identity::Client::new(&env, &user_identity);
 
-        // Get Claim
-        let claim = match identity_client.get_claim(&claim_id) {
-            Some(claim) => claim,
-            None => return false,
+                // Get Claim, then ask the claim issuer whether it still holds
+                match identity_client.get_claim(&claim_id) {
+                    Some(claim) => {
+                        let issuer_client = claim_issuer::Client::new(&env, &issuer);
+                        issuer_client.is_claim_valid(
+                            &claim.issuer_wallet,
+                            &user_identity,
+                            &claim.topic,
+                            &claim.scheme,
+                            &claim.signature,
+                            &claim.data,
+                        )
+                    }
+                    None => false,
+                }
+            }
+            None => false,
         };
 
-        // Get the claim issuer client
+        publish_event(
+            &env,
+            "claim_validated",
+            &sender,
+            ClaimValidatedEvent {
+                issuer,
+                topic: required_topic,
+                valid,
+            },
+        );
+
+        valid
+    }
+
+    // Lets UIs show who stands behind the claims this contract accepts
+    pub fn get_issuer_metadata(env: Env, issuer: Address) -> Option<IssuerMetadata> {
         let issuer_client = claim_issuer::Client::new(&env, &issuer);
-        return  issuer_client.is_claim_valid(&claim.issuer_wallet, &user_identity, &claim.topic, &claim.signature, &claim.data);
 
+        issuer_client.get_metadata().map(|metadata| IssuerMetadata {
+            name: metadata.name,
+            jurisdiction: metadata.jurisdiction,
+            uri: metadata.uri,
+            contact: metadata.contact,
+        })
     }
 
-    pub fn get_owner(env: Env) -> Address {
-        let owner: Address = env
-            .storage()
+    pub fn get_owner(env: Env) -> Result<Address, GatedError> {
+        env.storage()
             .instance()
-            .get(&symbol_short!("owner"))
-            .unwrap();
-        owner
+            .get(&GatedDataKey::Owner)
+            .ok_or(GatedError::NotInitialized)
     }
 
-    pub fn set_owner(env: Env, owner: Address) {
-        only_owner(&env);
+    // `owner` takes over once it calls `accept_ownership`
+    pub fn transfer_ownership(env: Env, owner: Address) -> Result<(), GatedError> {
+        let previous = only_owner(&env)?;
+        AccessControl::start_ownership_transfer(&env, previous, owner);
 
-        env.storage()
-            .instance()
-            .set(&symbol_short!("owner"), &owner);
+        Ok(())
     }
-}
 
-fn hash_claim(env: &Env, issuer: &Address, topic: &U256) -> BytesN<32> {
-    let address_bytes = Bytes::from_val(env, &issuer.to_xdr(&env));
-    let topic_bytes = Bytes::from_val(env, &topic.to_xdr(env));
+    pub fn accept_ownership(env: Env) -> Result<(), GatedError> {
+        let previous = Self::get_owner(env.clone())?;
+        let owner = AccessControl::accept_ownership(&env)?;
+
+        env.storage().instance().set(&GatedDataKey::Owner, &owner);
 
-    let mut concatenated_bytes = Bytes::new(env);
-    concatenated_bytes.append(&address_bytes);
-    concatenated_bytes.append(&topic_bytes);
-    env.crypto().keccak256(&concatenated_bytes)
+        publish_event(
+            &env,
+            "owner_changed",
+            &env.current_contract_address(),
+            OwnerChangedEvent { previous, owner },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_pending_owner(env: Env) -> Result<Option<Address>, GatedError> {
+        Ok(AccessControl::pending_owner(&env))
+    }
+
+    // The owner grants any role, admins every role but Admin
+    pub fn grant_role(
+        env: Env,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), GatedError> {
+        let owner = Self::get_owner(env.clone())?;
+        AccessControl::grant_role(&env, &owner, sender, role, account)?;
+        Ok(())
+    }
+
+    pub fn revoke_role(
+        env: Env,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), GatedError> {
+        let owner = Self::get_owner(env.clone())?;
+        AccessControl::revoke_role(&env, &owner, sender, role, account)?;
+        Ok(())
+    }
+
+    pub fn renounce_role(env: Env, account: Address, role: Role) -> Result<(), GatedError> {
+        AccessControl::renounce_role(&env, account, role);
+        Ok(())
+    }
+
+    // The owner holds every role
+    pub fn has_role(env: Env, role: Role, account: Address) -> Result<bool, GatedError> {
+        let owner = Self::get_owner(env.clone())?;
+        Ok(AccessControl::has_role(&env, &owner, role, &account))
+    }
 }
 
-fn only_owner(env: &Env) -> Address {
-    let owner: Address = env
-        .storage()
-        .instance()
-        .get(&symbol_short!("owner"))
-        .unwrap();
+fn only_owner(env: &Env) -> Result<Address, GatedError> {
+    let owner = GatedContract::get_owner(env.clone())?;
     owner.require_auth();
 
-    owner
+    Ok(owner)
+}
+
+// Checks that `sender` holds `role`, which the owner always does, and authorized the call
+fn require_role(env: &Env, role: Role, sender: &Address) -> Result<(), GatedError> {
+    let owner = GatedContract::get_owner(env.clone())?;
+    Ok(AccessControl::require_role(env, &owner, role, sender)?)
 }
 
+// Initialized with the unversioned Symbol key layout
+fn is_legacy(env: &Env) -> bool {
+    !env.storage().instance().has(&GatedDataKey::SchemaVersion)
+        && env
+            .storage()
+            .instance()
+            .get::<Symbol, bool>(&Symbol::new(env, "initialized"))
+            .unwrap_or(false)
+}
 
-mod test;
\ No newline at end of file
+mod test;
@@ -2,15 +2,23 @@
 extern crate std;
 
 use super::*;
-use ed25519_dalek::SigningKey;
-use soroban_sdk::testutils::ed25519::Sign;
-use soroban_sdk::xdr::ScVal;
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use onchainid_testutils::IssuerKey;
+use soroban_sdk::{testutils::Address as _, xdr::ToXdr, Address, Bytes, Env, FromVal};
 
 use crate::claim_issuer;
 use crate::factory;
 use crate::identity;
 
+mod gated {
+    soroban_sdk::contractimport!(file = "../../target/wasm32-unknown-unknown/release/gated.wasm");
+}
+
+// I made this Secret Key for testing, do not use it anywhere else
+const ISSUER_SECRET: [u8; 32] = [
+    0xb5, 0x1a, 0x48, 0x2a, 0x45, 0x9d, 0x1b, 0x2f, 0x8f, 0x1f, 0xf5, 0xb7, 0x15, 0x9c, 0xdb, 0xf0,
+    0xab, 0x23, 0xee, 0x46, 0x42, 0x2e, 0xd0, 0x72, 0x4f, 0x28, 0x22, 0xcd, 0x55, 0x0e, 0xcf, 0x71,
+];
+
 #[test]
 fn test_initialize() {
     let env = Env::default();
@@ -35,19 +43,11 @@ fn test_initialize() {
 fn test_validate_claim() {
     let env = Env::default();
     env.mock_all_auths();
+    // Every wasm call instantiates a VM, together they exceed a transaction's budget
+    env.budget().reset_unlimited();
 
-    let sk = SigningKey::from_bytes(
-        &hex::decode("b51a482a459d1b2f8f1ff5b7159cdbf0ab23ee46422ed0724f2822cd550ecf71")
-            .unwrap()
-            .try_into()
-            .unwrap(),
-    );
-
-    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(
-        &env,
-        "GARPZXSZVTI7WG3ADZQB5QGH67WS6XW6ISHIO2N4AOAC32PLNYBH7QCY",
-    ));
-
+    let issuer_key = IssuerKey::from_secret(&env, &ISSUER_SECRET);
+    let issuer_wallet = issuer_key.wallet.clone();
 
     let gated_contract_id = env.register_contract(None, GatedContract);
     let gated_client = GatedContractClient::new(&env, &gated_contract_id);
@@ -56,16 +56,19 @@ fn test_validate_claim() {
     let factory_client = factory::Client::new(&env, &factory_contract_id);
     let claim_issuer_contract_id = env.register_contract_wasm(None, claim_issuer::WASM);
     let claim_issuer_client = claim_issuer::Client::new(&env, &claim_issuer_contract_id);
-    let identity_contract_id = env.register_contract_wasm(None, identity::WASM);
-    let identity_client = identity::Client::new(&env, &identity_contract_id);
 
-    
     let management_key = Address::generate(&env);
     factory_client.initialize(&management_key);
     claim_issuer_client.initialize(&management_key);
-    identity_client.initialize(&management_key);
     gated_client.initialize(&factory_contract_id, &claim_issuer_contract_id, &management_key);
 
+    // Identities come from the factory, managed by the wallet they are created for
+    let identity_wasm_hash = env.deployer().upload_contract_wasm(identity::WASM);
+    factory_client.set_identity_wasm(&management_key, &identity_wasm_hash);
+    let identity_contract_id =
+        factory_client.create_identity(&management_key, &management_key, &0);
+    let identity_client = identity::Client::new(&env, &identity_contract_id);
+
     // Add Claim Key 
     let claim_key = Address::generate(&env);
     identity_client.add_key(&management_key, &claim_key, &3, &1);
@@ -73,12 +76,9 @@ fn test_validate_claim() {
 
     // Link Identity to User Wallet
     let user_wallet = Address::generate(&env);
-    factory_client.link_wallet(&user_wallet, &identity_contract_id);
-
-    std::println!("User Wallet: {:?}", user_wallet);
-    std::println!("Identity Contract: {:?}", identity_contract_id);
+    factory_client.link_wallet(&user_wallet, &identity_contract_id, &management_key);
 
-    let linked_identity = factory_client.get_identity(&user_wallet);
+    let linked_identity = factory_client.try_get_identity(&user_wallet).unwrap();
 
     assert!(
         linked_identity == identity_contract_id,
@@ -90,21 +90,7 @@ fn test_validate_claim() {
     let issuer = claim_issuer_contract_id;
     let data = "data".to_xdr(&env);
     let uri = Bytes::from_val(&env, &"uri".to_xdr(&env));
-
-    let mut concatenated_bytes = Bytes::new(&env);
-    concatenated_bytes.append(&identity_contract_id.clone().to_xdr(&env));
-    concatenated_bytes.append(&topic.clone().to_xdr(&env));
-    concatenated_bytes.append(&data);
-
-    let hashed_bytes: ScVal = env
-        .crypto()
-        .keccak256(&concatenated_bytes)
-        .to_array()
-        .try_into()
-        .unwrap();
-
-    let sigtest_val: [u8; 64] = sk.sign(hashed_bytes).unwrap();
-    let signature = Bytes::from_slice(&env, &sigtest_val);
+    let signature = issuer_key.sign_claim(&env, &identity_contract_id, &topic, &data);
 
     let claim_id = identity_client.add_claim(
         &claim_key,
@@ -117,10 +103,6 @@ fn test_validate_claim() {
         &uri,
     );
 
-    let claim_id_hex = hex::encode(claim_id.to_array());
-
-    std::println!("Claim ID: {:?}", claim_id_hex);
-
     // Verify that the claim has been added
     match identity_client.get_claim(&claim_id) {
         Some(claim) => claim,
@@ -133,5 +115,126 @@ fn test_validate_claim() {
         "Claim should be valid"
     );
 
-    
+    // A wallet the factory doesn't know is simply not verified
+    let stranger = Address::generate(&env);
+    assert!(!gated_client.validate_claim(&stranger, &issuer, &topic));
+}
+
+#[test]
+fn test_get_issuer_metadata() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let gated_contract_id = env.register_contract(None, GatedContract);
+    let gated_client = GatedContractClient::new(&env, &gated_contract_id);
+
+    let factory_contract_id = env.register_contract_wasm(None, factory::WASM);
+    let claim_issuer_contract_id = env.register_contract_wasm(None, claim_issuer::WASM);
+    let claim_issuer_client = claim_issuer::Client::new(&env, &claim_issuer_contract_id);
+
+    let management_key = Address::generate(&env);
+    claim_issuer_client.initialize(&management_key);
+    gated_client.initialize(
+        &factory_contract_id,
+        &claim_issuer_contract_id,
+        &management_key,
+    );
+
+    assert!(gated_client
+        .get_issuer_metadata(&claim_issuer_contract_id)
+        .is_none());
+
+    claim_issuer_client.set_metadata(
+        &management_key,
+        &claim_issuer::IssuerMetadata {
+            name: soroban_sdk::String::from_str(&env, "0xAuth KYC"),
+            jurisdiction: soroban_sdk::String::from_str(&env, "CH"),
+            uri: soroban_sdk::String::from_str(&env, "https://0xauth.co"),
+            contact: soroban_sdk::String::from_str(&env, "compliance@0xauth.co"),
+        },
+    );
+
+    let metadata = gated_client
+        .get_issuer_metadata(&claim_issuer_contract_id)
+        .unwrap();
+    assert_eq!(
+        metadata.name,
+        soroban_sdk::String::from_str(&env, "0xAuth KYC")
+    );
+}
+
+#[test]
+fn test_policy_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let gated_contract_id = env.register_contract(None, GatedContract);
+    let gated_client = GatedContractClient::new(&env, &gated_contract_id);
+
+    let owner = Address::generate(&env);
+    gated_client.initialize(&Address::generate(&env), &Address::generate(&env), &owner);
+
+    // The issuer is set by a policy admin, not by anyone holding the owner key
+    let policy_admin = Address::generate(&env);
+    let issuer = Address::generate(&env);
+    assert_eq!(
+        gated_client.try_set_claim_issuer(&policy_admin, &issuer),
+        Err(Ok(GatedError::InsufficientPermissions))
+    );
+    gated_client.grant_role(&owner, &Role::PolicyAdmin, &policy_admin);
+    gated_client.set_claim_issuer(&policy_admin, &issuer);
+    assert_eq!(env.auths()[0].0, policy_admin);
+
+    // Two-step ownership transfer
+    let new_owner = Address::generate(&env);
+    gated_client.transfer_ownership(&new_owner);
+    assert_eq!(gated_client.get_owner(), owner);
+    gated_client.accept_ownership();
+    assert_eq!(gated_client.get_owner(), new_owner);
+    assert!(gated_client.has_role(&Role::PolicyAdmin, &policy_admin));
+}
+
+#[test]
+fn test_migrate_without_owner() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let gated_contract_id = env.register_contract(None, GatedContract);
+    let gated_client = GatedContractClient::new(&env, &gated_contract_id);
+
+    // Legacy storage that lost its owner fails cleanly instead of trapping
+    env.as_contract(&gated_contract_id, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "initialized"), &true);
+    });
+    assert_eq!(gated_client.get_schema_version(), 0);
+    assert_eq!(
+        gated_client.try_migrate(),
+        Err(Ok(GatedError::NotInitialized))
+    );
+}
+
+#[test]
+fn test_upgrade() {
+    let env = Env::default();
+    env.mock_all_auths();
+    // Running the contract as wasm exceeds a transaction's budget
+    env.budget().reset_unlimited();
+
+    let gated_contract_id = env.register_contract_wasm(None, gated::WASM);
+    let gated_client = gated::Client::new(&env, &gated_contract_id);
+
+    let owner = Address::generate(&env);
+    gated_client.initialize(&Address::generate(&env), &Address::generate(&env), &owner);
+
+    // Only the owner replaces the code, the storage stays as it is
+    let wasm_hash = env.deployer().upload_contract_wasm(gated::WASM);
+    gated_client.upgrade(&wasm_hash);
+    assert_eq!(env.auths()[0].0, owner);
+    assert_eq!(gated_client.get_owner(), owner);
+    assert_eq!(
+        gated_client.try_migrate(),
+        Err(Ok(gated::GatedError::AlreadyMigrated))
+    );
 }
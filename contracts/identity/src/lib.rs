@@ -1,17 +1,23 @@
 #![no_std]
 
-use soroban_sdk::{
-    contract, contractimpl, log, symbol_short, vec, xdr::ToXdr, Address, Bytes, BytesN, Env,
-    FromVal, Symbol, Vec, U256,
-};
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Bytes, BytesN, Env, Vec, U256};
 
-mod state;
-use state::{Claim, Error, Key, KeyPurpose, KeyType};
+use onchainid_common::events::{
+    publish_event, IssuerApprovedEvent, IssuerRevokedEvent, UpgradeAuthorityEvent, UpgradedEvent,
+};
+use onchainid_common::{
+    get_schema_version, hash_key, identity_require_auth, key_has_purpose, migrate_legacy,
+    verify_claim_signature, Claim, ClaimHolder, Error, Key, KeyManager, KeyPurpose,
+    CONTRACT_SCHEME,
+};
 
-mod claim_issuer {
-    soroban_sdk::contractimport!(
-        file = "../../target/wasm32-unknown-unknown/release/claim_issuer.wasm"
-    );
+// Identity specific storage, shared entries live in onchainid_common::DataKey
+#[contracttype]
+#[derive(Clone)]
+pub enum IdentityDataKey {
+    Allowance(Address),
+    // Trusted to replace the contract code, usually the factory
+    UpgradeAuthority,
 }
 
 #[contract]
@@ -20,71 +26,28 @@ pub struct IdentityContract;
 #[contractimpl]
 impl IdentityContract {
     pub fn get_initialized(env: Env) -> Result<bool, Error> {
-        Ok(env
-            .storage()
-            .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false))
+        KeyManager::get_initialized(&env)
     }
 
     pub fn initialize(env: Env, initial_management_key: Address) -> Result<(), Error> {
-        let init_symbol = Symbol::new(&env, "initialized");
-
-        let initialized = env
-            .storage()
-            .instance()
-            .get::<Symbol, bool>(&init_symbol)
-            .unwrap_or(false);
-
-        if initialized {
-            return Err(Error::AlreadyInitialized);
-        }
-        env.storage()
-            .instance()
-            .set(&init_symbol, &true);
-
-        let key_hash = hash_key(&env, &initial_management_key);
-        let key = Key {
-            purposes: vec![&env, KeyPurpose::Management],
-            key_type: KeyType::ECDSA,
-            key: key_hash.clone(),
-        };
-
-        let keys = vec![&env, key];
-        env.storage()
-            .persistent()
-            .set(&symbol_short!("keys"), &keys);
+        KeyManager::initialize(&env, initial_management_key)
+    }
 
-        log!(
-            &env,
-            "Identity contract initialized with management key: {:?}",
-            initial_management_key
-        );
+    pub fn get_schema_version(env: Env) -> u32 {
+        get_schema_version(&env)
+    }
 
-        env.events().publish((init_symbol,), initial_management_key);
-        Ok(())
+    // Moves an identity deployed before schema versioning to the typed layout
+    pub fn migrate(env: Env, manager: Address) -> Result<(), Error> {
+        migrate_legacy(&env, &manager)
     }
 
     pub fn get_key(env: Env, key: Address) -> Result<Key, Error> {
-        let key_hash = hash_key(&env, &key);
-        let keys = env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
-            .unwrap_or(Vec::new(&env));
-
-        keys.iter()
-            .find(|k| k.key == key_hash)
-            .clone()
-            .ok_or(Error::KeyNotFound)
+        KeyManager::get_key(&env, key)
     }
 
     pub fn get_keys(env: Env) -> Result<Vec<Key>, Error> {
-        Ok(env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
-            .unwrap_or(Vec::new(&env)))
+        KeyManager::get_keys(&env)
     }
 
     pub fn add_key(
@@ -94,119 +57,19 @@ impl IdentityContract {
         purpose: u32,
         key_type: u32,
     ) -> Result<(), Error> {
-        // Only the manager can add keys
-        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
-
-        // Make a Kecak256 hash of the key
-        let key_hash = hash_key(&env, &key);
-        let key_purpose = KeyPurpose::try_from(purpose).map_err(|_| Error::InvalidKeyPurpose)?;
-
-        // Retrieve and mutate the list of keys
-        let mut keys: Vec<Key> = env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
-            .unwrap_or(Vec::new(&env));
-
-        let mut key_found = false;
-
-        for i in 0..keys.len() {
-            let mut k = keys.get(i).ok_or(Error::IndexOutOfBounds)?;
-            if k.key == key_hash {
-                if k.purposes.contains(&key_purpose) {
-                    return Err(Error::KeyConflict);
-                } else {
-                    k.purposes.push_back(key_purpose);
-                    keys.set(i, k);
-                    key_found = true;
-                    break;
-                }
-            }
-        }
-
-        if !key_found {
-            let key = Key {
-                purposes: vec![&env, key_purpose],
-                key_type: KeyType::try_from(key_type).map_err(|_| Error::InvalidKeyType)?,
-                key: key_hash.clone(),
-            };
-            keys.push_back(key);
-        }
-        env.storage()
-            .persistent()
-            .set(&symbol_short!("keys"), &keys);
-
-        env.events().publish(
-            (symbol_short!("add_key"),),
-            (manager, key, purpose, key_type)
-        );
-        Ok(())
+        KeyManager::add_key(&env, manager, key, purpose, key_type)
     }
 
     pub fn remove_key(env: Env, manager: Address, key: Address, purpose: u32) -> Result<(), Error> {
-        // Only the manager can remove keys
-        identity_require_auth(&env, &manager, KeyPurpose::Management)?;
-
-        // Make a Kecak256 hash of the key
-        let key_hash = hash_key(&env, &key);
-        let key_purpose = KeyPurpose::try_from(purpose).map_err(|_| Error::InvalidKeyPurpose)?;
-
-        // Retrieve and mutate the list of keys
-        let mut keys: Vec<Key> = env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
-            .unwrap_or(Vec::new(&env));
-
-        if !keys.iter().any(|k| k.key == key_hash) {
-            return Err(Error::KeyNotFound);
-        }
-
-        for i in 0..keys.len() {
-            if let Some(mut k) = keys.get(i) {
-                if k.key == key_hash {
-                    if let Some(pos) = k.purposes.iter().position(|p| p == key_purpose) {
-                        k.purposes.remove(pos as u32);
-
-                        if k.purposes.is_empty() {
-                            keys.remove(i as u32);
-                        } else {
-                            keys.set(i, k);
-                        }
-                    } else {
-                        return Err(Error::KeyDoesNotHavePurpose);
-                    }
-                    break;
-                }
-            } else {
-                return Err(Error::IndexOutOfBounds);
-            }
-        }
-
-        env.storage()
-            .persistent()
-            .set(&symbol_short!("keys"), &keys);
-
-        env.events().publish(
-            (Symbol::new(&env, "remove_key"),),
-            (manager, key, purpose)
-        );
-        Ok(())
+        KeyManager::remove_key(&env, manager, key, purpose)
     }
 
     pub fn get_claim(env: Env, claim_id: BytesN<32>) -> Result<Option<Claim>, Error> {
-        Ok(env
-            .storage()
-            .persistent()
-            .get::<BytesN<32>, Claim>(&claim_id))
+        ClaimHolder::get_claim(&env, claim_id)
     }
 
     pub fn get_claim_ids(env: Env) -> Result<Vec<BytesN<32>>, Error> {
-        Ok(env
-            .storage()
-            .persistent()
-            .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
-            .unwrap_or(Vec::new(&env)))
+        ClaimHolder::get_claim_ids(&env)
     }
 
     pub fn add_claim(
@@ -220,83 +83,173 @@ impl IdentityContract {
         data: Bytes,
         uri: Bytes,
     ) -> Result<BytesN<32>, Error> {
-        identity_require_auth(&env, &sender, KeyPurpose::Claim)?;
-
-        let current_contact = env.current_contract_address();
-
-        if current_contact != issuer {
-            let client = claim_issuer::Client::new(&env, &issuer);
-            if !client.is_claim_valid(&issuer_wallet, &current_contact, &topic, &signature, &data) {
-                return Err(Error::InvalidClaim);
-            }
-        }
-
-        let claim_id = hash_claim(&env, &issuer, &topic);
-        let claim: Claim = Claim {
+        ClaimHolder::add_claim(
+            &env,
+            sender,
             topic,
             scheme,
-            issuer,
             issuer_wallet,
+            issuer,
             signature,
             data,
             uri,
-        };
-        env.storage().persistent().set(&claim_id, &claim);
+        )
+    }
 
-        let mut claims = env
+    pub fn approve_issuer(
+        env: Env,
+        sender: Address,
+        issuer: Address,
+        topics: Vec<U256>,
+    ) -> Result<(), Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Management)?;
+
+        env.storage()
+            .persistent()
+            .set(&IdentityDataKey::Allowance(issuer.clone()), &topics);
+
+        publish_event(
+            &env,
+            "issuer_approved",
+            &env.current_contract_address(),
+            IssuerApprovedEvent {
+                sender,
+                issuer,
+                topics,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn revoke_issuer(env: Env, sender: Address, issuer: Address) -> Result<(), Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Management)?;
+
+        let allowance_key = IdentityDataKey::Allowance(issuer.clone());
+        if !env.storage().persistent().has(&allowance_key) {
+            return Err(Error::IssuerNotAllowed);
+        }
+        env.storage().persistent().remove(&allowance_key);
+
+        publish_event(
+            &env,
+            "issuer_revoked",
+            &env.current_contract_address(),
+            IssuerRevokedEvent { sender, issuer },
+        );
+        Ok(())
+    }
+
+    pub fn get_issuer_allowance(env: Env, issuer: Address) -> Result<Option<Vec<U256>>, Error> {
+        Ok(env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
-            .unwrap_or(Vec::new(&env));
+            .get::<IdentityDataKey, Vec<U256>>(&IdentityDataKey::Allowance(issuer)))
+    }
 
-        claims.push_back(claim_id.clone());
+    // Opts in to upgrades pushed by `authority`, e.g. the factory's upgrade_identities
+    pub fn set_upgrade_authority(
+        env: Env,
+        sender: Address,
+        authority: Address,
+    ) -> Result<(), Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Management)?;
 
         env.storage()
-            .persistent()
-            .set(&symbol_short!("claims"), &claims);
+            .instance()
+            .set(&IdentityDataKey::UpgradeAuthority, &authority);
+
+        publish_event(
+            &env,
+            "upgrade_authority_set",
+            &env.current_contract_address(),
+            UpgradeAuthorityEvent { sender, authority },
+        );
+        Ok(())
+    }
 
-        log!(&env, "Claim added: {:?}", claim);
+    pub fn remove_upgrade_authority(env: Env, sender: Address) -> Result<(), Error> {
+        identity_require_auth(&env, &sender, KeyPurpose::Management)?;
 
-        env.events().publish(
-            (symbol_short!("add_claim"),),
-            (sender, claim_id.clone(), claim.topic, claim.scheme, claim.issuer, claim.issuer_wallet,claim.signature, claim.data, claim.uri)
+        let authority =
+            Self::get_upgrade_authority(env.clone())?.ok_or(Error::UpgradeAuthorityNotSet)?;
+        env.storage()
+            .instance()
+            .remove(&IdentityDataKey::UpgradeAuthority);
+
+        publish_event(
+            &env,
+            "upgrade_authority_removed",
+            &env.current_contract_address(),
+            UpgradeAuthorityEvent { sender, authority },
         );
+        Ok(())
+    }
 
-        Ok(claim_id)
+    pub fn get_upgrade_authority(env: Env) -> Result<Option<Address>, Error> {
+        Ok(env
+            .storage()
+            .instance()
+            .get::<IdentityDataKey, Address>(&IdentityDataKey::UpgradeAuthority))
     }
 
-    pub fn remove_claim(env: Env, sender: Address, claim_id: BytesN<32>) -> Result<(), Error> {
-        identity_require_auth(&env, &sender, KeyPurpose::Claim)?;
+    // Replaces the contract code, only on behalf of the upgrade authority. The
+    // new code takes over once this invocation has finished.
+    pub fn upgrade(env: Env, wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let authority =
+            Self::get_upgrade_authority(env.clone())?.ok_or(Error::UpgradeAuthorityNotSet)?;
+        authority.require_auth();
 
-        let claim = env
-            .storage()
-            .persistent()
-            .get::<BytesN<32>, Claim>(&claim_id)
-            .ok_or(Error::ClaimNotFound)?;
+        env.deployer()
+            .update_current_contract_wasm(wasm_hash.clone());
 
-        env.storage().persistent().remove(&claim_id);
+        publish_event(
+            &env,
+            "upgraded",
+            &env.current_contract_address(),
+            UpgradedEvent {
+                authority,
+                wasm_hash,
+            },
+        );
+        Ok(())
+    }
 
-        let mut claims = env
+    // Called by an approved issuer contract to write a claim directly.
+    // The claim uses the contract scheme and is validated by the issuer's registry.
+    pub fn add_claim_by_issuer(
+        env: Env,
+        issuer: Address,
+        topic: U256,
+        data: Bytes,
+        uri: Bytes,
+    ) -> Result<BytesN<32>, Error> {
+        let topics = env
             .storage()
             .persistent()
-            .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
-            .unwrap_or(Vec::new(&env));
+            .get::<IdentityDataKey, Vec<U256>>(&IdentityDataKey::Allowance(issuer.clone()))
+            .ok_or(Error::IssuerNotAllowed)?;
 
-        if let Some(pos) = claims.iter().position(|c| c == claim_id) {
-            claims.remove(pos as u32);
+        if !topics.is_empty() && !topics.contains(&topic) {
+            return Err(Error::IssuerNotAllowed);
         }
 
-        env.storage()
-            .persistent()
-            .set(&symbol_short!("claims"), &claims);
+        issuer.require_auth();
 
-        log!(&env, "Claim removed: {:?}", claim);
+        let claim: Claim = Claim {
+            topic,
+            scheme: U256::from_u32(&env, CONTRACT_SCHEME),
+            issuer: issuer.clone(),
+            issuer_wallet: issuer.clone(),
+            signature: Bytes::new(&env),
+            data,
+            uri,
+        };
 
-        env.events().publish(
-            (Symbol::new(&env, "remove_claim"),),
-            (sender, claim_id)
-        );
-        Ok(())
+        Ok(ClaimHolder::store_claim(&env, issuer, claim))
+    }
+
+    pub fn remove_claim(env: Env, sender: Address, claim_id: BytesN<32>) -> Result<(), Error> {
+        ClaimHolder::remove_claim(&env, sender, claim_id)
     }
 
     pub fn is_claim_valid(
@@ -304,32 +257,12 @@ impl IdentityContract {
         issuer_wallet: Address,
         identity: Address,
         topic: U256,
+        _scheme: U256,
         signature: Bytes,
         data: Bytes,
     ) -> Result<bool, Error> {
-        let mut concatenated_bytes = Bytes::new(env);
-        concatenated_bytes.append(&identity.clone().to_xdr(&env));
-        concatenated_bytes.append(&topic.to_xdr(&env));
-        concatenated_bytes.append(&data);
-
-        // Make sure the Signature was also signed in the XDR format
-        // Otherwise, the signature will be invalid
-        let data_digest = env.crypto().keccak256(&concatenated_bytes).to_xdr(&env);
-
-        let signature_slice: BytesN<64> = match signature.slice(..64).try_into() {
-            Ok(slice) => slice,
-            Err(_) => return Err(Error::InvalidSignature),
-        };
-
-        let issuer_xdr = issuer_wallet.clone().to_xdr(env);
-
-        let issuer_bytes: BytesN<32> = match issuer_xdr.slice(12..44).try_into() {
-            Ok(slice) => slice,
-            Err(_) => return Err(Error::InvalidAddressBytes),
-        };
-
-        env.crypto()
-            .ed25519_verify(&issuer_bytes, &data_digest, &signature_slice);
+        // Identities don't scope their Claim keys, any scheme is accepted
+        verify_claim_signature(env, &issuer_wallet, &identity, &topic, &signature, &data)?;
 
         let hashed_addr = hash_key(env, &issuer_wallet);
 
@@ -337,42 +270,4 @@ impl IdentityContract {
     }
 }
 
-fn hash_key(env: &Env, key: &Address) -> BytesN<32> {
-    let address_bytes = Bytes::from_val(env, &key.to_xdr(&env));
-    env.crypto().keccak256(&address_bytes)
-}
-
-fn hash_claim(env: &Env, issuer: &Address, topic: &U256) -> BytesN<32> {
-    let address_bytes = Bytes::from_val(env, &issuer.to_xdr(&env));
-    let topic_bytes = Bytes::from_val(env, &topic.to_xdr(env));
-
-    let mut concatenated_bytes = Bytes::new(env);
-    concatenated_bytes.append(&address_bytes);
-    concatenated_bytes.append(&topic_bytes);
-    env.crypto().keccak256(&concatenated_bytes)
-}
-
-fn key_has_purpose(env: &Env, key_hash: &BytesN<32>, purpose: KeyPurpose) -> bool {
-    if let Some(keys) = env
-        .storage()
-        .persistent()
-        .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
-    {
-        keys.iter()
-            .any(|k| k.key == *key_hash && k.purposes.contains(&purpose))
-    } else {
-        false
-    }
-}
-fn identity_require_auth(env: &Env, sender: &Address, key_type: KeyPurpose) -> Result<(), Error> {
-    let key_hash = hash_key(env, sender);
-
-    if !key_has_purpose(env, &key_hash, key_type) {
-        return Err(Error::InsufficientPermissions);
-    }
-
-    sender.require_auth();
-    Ok(())
-}
-
 mod test;
@@ -2,15 +2,25 @@
 extern crate std;
 
 use super::*;
-use base32::{decode as base32_decode, encode as base32_encode, Alphabet};
-use crc16::{State, XMODEM};
 use ed25519_dalek::SigningKey;
+use onchainid_common::events::{EVENT_NAMESPACE, EVENT_VERSION};
+use onchainid_common::hash_claim;
+use onchainid_testutils::strkey;
+use proptest::prelude::*;
 use soroban_sdk::testutils::ed25519::Sign;
-use soroban_sdk::xdr::ScVal;
-use soroban_sdk::{testutils::Address as _, Address, Env};
-use std::string::String;
-
-use crate::claim_issuer;
+use soroban_sdk::testutils::EnvTestConfig;
+use soroban_sdk::xdr::{ScVal, ToXdr};
+use soroban_sdk::{
+    testutils::{Address as _, Events},
+    vec, Address, Env, FromVal, IntoVal, Symbol,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+mod claim_issuer {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/claim_issuer.wasm"
+    );
+}
 
 #[test]
 fn test_initialize() {
@@ -292,8 +302,10 @@ fn test_revoke_claim() {
     client.add_key(&management_key, &claim_key, &3, &1);
     issuer_client.add_key(&management_key, &issuer_wallet, &3, &1);
 
+    // The holder picks the scheme, a signed claim is revoked by its signature
+    // even when it claims the contract scheme
     let topic = U256::from_u32(&env, 6);
-    let scheme = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, CONTRACT_SCHEME);
     let issuer = issuer_contract_id;
     let data = Bytes::from_val(&env, &"data".to_xdr(&env));
     let uri = Bytes::from_val(&env, &"uri".to_xdr(&env));
@@ -330,7 +342,15 @@ fn test_revoke_claim() {
 
     // Verify that the claim has been revoked
     let claim = issuer_client.is_claim_revoked(&signature);
-    assert!(claim, "Claim should be revoked")
+    assert!(claim, "Claim should be revoked");
+    assert!(!issuer_client.is_claim_valid(
+        &issuer_wallet,
+        &contract_id,
+        &topic,
+        &scheme,
+        &signature,
+        &data
+    ));
 }
 
 #[test]
@@ -357,6 +377,7 @@ fn test_is_claim_valid() {
         "CD32UHM2YHGENPHQBD4UQC4TOS3G6MUGLY65DVCJHK4YJF2HAEQIARNT",
     ));
     let topic = U256::from_u32(&env, 6);
+    let scheme = U256::from_u32(&env, 1);
     let data = Bytes::from_slice(&env, "data".as_bytes());
 
     // Convert the Data to Bytes
@@ -381,21 +402,24 @@ fn test_is_claim_valid() {
     let pk = sk.verifying_key();
     let pk_bytes: [u8; 32] = pk.to_bytes();
 
-    if let Some(stellar_pub_key) = encode_stellar_pub_key(&pk_bytes) {
-        std::println!("Stellar Public Key: {}", stellar_pub_key);
-        let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(
-            &env,
-            stellar_pub_key.as_str(),
-        ));
+    let stellar_pub_key = strkey::encode_account(&pk_bytes);
+    let issuer_wallet = Address::from_string(&soroban_sdk::String::from_str(
+        &env,
+        stellar_pub_key.as_str(),
+    ));
 
-        client.add_key(&management_key, &issuer_wallet, &3, &1);
+    client.add_key(&management_key, &issuer_wallet, &3, &1);
 
-        let valid = client.is_claim_valid(&issuer_wallet, &identity, &topic, &signature, &data);
+    let valid = client.is_claim_valid(
+        &issuer_wallet,
+        &identity,
+        &topic,
+        &scheme,
+        &signature,
+        &data,
+    );
 
-        assert_eq!(valid, true, "Claim should be valid");
-    } else {
-        std::println!("Failed to encode the Stellar public key.");
-    }
+    assert_eq!(valid, true, "Claim should be valid");
 }
 
 #[test]
@@ -420,50 +444,467 @@ fn test_ed25519_verify() {
         .ed25519_verify(&pk, &sc_val_data.to_xdr(&env), &signature_bytes);
 }
 
-fn decode_stellar_pub_key(pub_key: &str) -> Option<[u8; 32]> {
-    let decoded = base32_decode(Alphabet::Rfc4648 { padding: false }, pub_key)?;
-    if decoded.len() != 35 {
-        return None;
-    }
+#[test]
+fn test_issue_claim_directly() {
+    let env = Env::default();
+    env.mock_all_auths();
+    // Re-issuing and revoking through the wasm issuer exceed a transaction's budget
+    env.budget().reset_unlimited();
+
+    let issuer_contract_id = env.register_contract_wasm(None, claim_issuer::WASM);
+    let issuer_client = claim_issuer::Client::new(&env, &issuer_contract_id);
+
+    let contract_id = env.register_contract(None, IdentityContract);
+    let client = IdentityContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+    issuer_client.initialize(&management_key);
+
+    let issuer_key = Address::generate(&env);
+    issuer_client.add_key(&management_key, &issuer_key, &3, &1);
+
+    let topic = U256::from_u32(&env, 6);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+    let uri = Bytes::from_slice(&env, "uri".as_bytes());
 
-    // std::println!("Decoded: {:?}", decoded);
+    // The identity has to approve the issuer first
+    let result = issuer_client.try_issue_claim(&issuer_key, &contract_id, &topic, &data, &uri);
+    assert!(result.is_err(), "Unapproved issuer should not write claims");
+
+    client.approve_issuer(
+        &management_key,
+        &issuer_contract_id,
+        &vec![&env, topic.clone()],
+    );
 
-    let checksum = &decoded[33..];
-    let payload = &decoded[..33];
+    // Keys scoped to other schemes can't write contract scheme claims
+    issuer_client.set_key_scope(
+        &management_key,
+        &issuer_key,
+        &vec![&env],
+        &vec![&env, U256::from_u32(&env, 1)],
+    );
+    let result = issuer_client.try_issue_claim(&issuer_key, &contract_id, &topic, &data, &uri);
+    assert!(
+        result.is_err(),
+        "Key scoped to another scheme should not write claims"
+    );
+    issuer_client.remove_key_scope(&management_key, &issuer_key);
 
-    // std::println!("Payload: {:?}", payload);
-    // std::println!("Checksum: {:?}", checksum);
+    let first_index = issuer_client.get_next_status_index();
+    issuer_client.issue_claim(&issuer_key, &contract_id, &topic, &data, &uri);
 
-    // Verify checksum
-    let mut state = State::<XMODEM>::new();
-    state.update(payload);
-    let calculated_checksum = state.get();
+    // Re-issuing replaces the claim and revokes the status index of the old one
+    let data = Bytes::from_slice(&env, "new data".as_bytes());
+    let claim_id = issuer_client.issue_claim(&issuer_key, &contract_id, &topic, &data, &uri);
+    assert_eq!(claim_id, hash_claim(&env, &issuer_contract_id, &topic));
+    assert!(issuer_client.is_status_revoked(&first_index));
+    assert!(!issuer_client.is_status_revoked(&(first_index + 1)));
 
-    // std::println!("Calculated Checksum: {:?}", calculated_checksum.to_le_bytes());
+    let claim = client.get_claim(&claim_id).unwrap();
+    assert_eq!(claim.scheme, U256::from_u32(&env, CONTRACT_SCHEME));
+    assert!(
+        claim.signature.is_empty(),
+        "Contract scheme claims carry no signature"
+    );
 
-    if calculated_checksum.to_le_bytes() != [checksum[0], checksum[1]] {
-        return None;
-    }
+    assert!(issuer_client.is_claim_valid(
+        &claim.issuer_wallet,
+        &contract_id,
+        &topic,
+        &claim.scheme,
+        &claim.signature,
+        &claim.data
+    ));
+
+    // Revocation drops the claim from the issuer registry
+    issuer_client.revoke_claim(&management_key, &contract_id, &claim_id);
+    assert!(!issuer_client.is_claim_valid(
+        &claim.issuer_wallet,
+        &contract_id,
+        &topic,
+        &claim.scheme,
+        &claim.signature,
+        &claim.data
+    ));
+}
+
+#[test]
+fn test_issuer_allowance_topics() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, IdentityContract);
+    let client = IdentityContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let issuer = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+    let uri = Bytes::from_slice(&env, "uri".as_bytes());
+
+    client.approve_issuer(
+        &management_key,
+        &issuer,
+        &vec![&env, U256::from_u32(&env, 1)],
+    );
+    assert_eq!(
+        client.try_add_claim_by_issuer(&issuer, &topic, &data, &uri),
+        Err(Ok(Error::IssuerNotAllowed))
+    );
+
+    client.approve_issuer(&management_key, &issuer, &vec![&env]);
+    client.add_claim_by_issuer(&issuer, &topic, &data, &uri);
+    assert_eq!(client.get_claim_ids().len(), 1);
+
+    // Re-issuing replaces the claim instead of listing it twice
+    client.add_claim_by_issuer(&issuer, &topic, &data, &uri);
+    assert_eq!(client.get_claim_ids().len(), 1);
+
+    client.revoke_issuer(&management_key, &issuer);
+    assert!(client.get_issuer_allowance(&issuer).is_none());
+    assert_eq!(
+        client.try_add_claim_by_issuer(&issuer, &topic, &data, &uri),
+        Err(Ok(Error::IssuerNotAllowed))
+    );
+}
+
+#[test]
+fn test_upgrade_authority() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, IdentityContract);
+    let client = IdentityContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    // Nobody may upgrade an identity that didn't opt in
+    let wasm_hash = BytesN::from_array(&env, &[1; 32]);
+    assert_eq!(client.get_upgrade_authority(), None);
+    assert_eq!(
+        client.try_upgrade(&wasm_hash),
+        Err(Ok(Error::UpgradeAuthorityNotSet))
+    );
+
+    let factory = Address::generate(&env);
+    assert!(client
+        .try_set_upgrade_authority(&Address::generate(&env), &factory)
+        .is_err());
+    client.set_upgrade_authority(&management_key, &factory);
+    assert_eq!(client.get_upgrade_authority(), Some(factory.clone()));
+
+    client.remove_upgrade_authority(&management_key);
+    assert_eq!(client.get_upgrade_authority(), None);
+    assert_eq!(
+        client.try_remove_upgrade_authority(&management_key),
+        Err(Ok(Error::UpgradeAuthorityNotSet))
+    );
+}
+
+#[test]
+fn test_issuer_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, IdentityContract);
+    let client = IdentityContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+
+    let issuer = Address::generate(&env);
+    let topics = vec![&env, U256::from_u32(&env, 1)];
+    client.approve_issuer(&management_key, &issuer, &topics);
+    client.revoke_issuer(&management_key, &issuer);
+
+    let event_topics = |name: &str| {
+        (
+            EVENT_NAMESPACE,
+            EVENT_VERSION,
+            Symbol::new(&env, name),
+            contract_id.clone(),
+        )
+            .into_val(&env)
+    };
+
+    let events = env.events().all();
+    assert_eq!(
+        events.slice(events.len() - 2..),
+        vec![
+            &env,
+            (
+                contract_id.clone(),
+                event_topics("issuer_approved"),
+                IssuerApprovedEvent {
+                    sender: management_key.clone(),
+                    issuer: issuer.clone(),
+                    topics,
+                }
+                .into_val(&env)
+            ),
+            (
+                contract_id.clone(),
+                event_topics("issuer_revoked"),
+                IssuerRevokedEvent {
+                    sender: management_key.clone(),
+                    issuer: issuer.clone(),
+                }
+                .into_val(&env)
+            ),
+        ]
+    );
+}
 
-    let mut key = [0u8; 32];
-    key.copy_from_slice(&payload[1..33]);
-    Some(key)
+// Model-based property test: random key and claim operations are applied to
+// the contract and to a plain model, results and state must agree throughout.
+
+const PROP_KEYS: usize = 4;
+const PROP_TOPICS: u32 = 3;
+
+#[derive(Clone, Debug)]
+enum IdentityOp {
+    AddKey {
+        manager: usize,
+        key: usize,
+        purpose: u32,
+    },
+    RemoveKey {
+        manager: usize,
+        key: usize,
+        purpose: u32,
+    },
+    AddClaim {
+        sender: usize,
+        topic: u32,
+        data: u8,
+    },
+    RemoveClaim {
+        sender: usize,
+        topic: u32,
+    },
 }
 
-fn encode_stellar_pub_key(pub_key_bytes: &[u8; 32]) -> Option<String> {
-    let mut payload = std::vec![0x30];
-    payload.extend_from_slice(pub_key_bytes);
+fn identity_op() -> impl Strategy<Value = IdentityOp> {
+    prop_oneof![
+        (0..PROP_KEYS, 0..PROP_KEYS, 1..=4u32).prop_map(|(manager, key, purpose)| {
+            IdentityOp::AddKey {
+                manager,
+                key,
+                purpose,
+            }
+        }),
+        (0..PROP_KEYS, 0..PROP_KEYS, 1..=4u32).prop_map(|(manager, key, purpose)| {
+            IdentityOp::RemoveKey {
+                manager,
+                key,
+                purpose,
+            }
+        }),
+        (0..PROP_KEYS, 0..PROP_TOPICS, any::<u8>()).prop_map(|(sender, topic, data)| {
+            IdentityOp::AddClaim {
+                sender,
+                topic,
+                data,
+            }
+        }),
+        (0..PROP_KEYS, 0..PROP_TOPICS)
+            .prop_map(|(sender, topic)| IdentityOp::RemoveClaim { sender, topic }),
+    ]
+}
+
+// Keys by index into the generated addresses, claims by topic
+#[derive(Default)]
+struct IdentityModel {
+    keys: BTreeMap<usize, BTreeSet<u32>>,
+    claims: BTreeMap<u32, u8>,
+}
+
+impl IdentityModel {
+    fn has(&self, key: usize, purpose: u32) -> bool {
+        self.keys.get(&key).is_some_and(|p| p.contains(&purpose))
+    }
 
-    let mut state = State::<XMODEM>::new();
-    state.update(&payload);
-    let checksum = state.get().to_le_bytes();
+    // The outcome the contract should report, applied to the model on success
+    fn apply(&mut self, op: &IdentityOp) -> Result<(), Error> {
+        match *op {
+            IdentityOp::AddKey {
+                manager,
+                key,
+                purpose,
+            } => {
+                if !self.has(manager, 1) {
+                    return Err(Error::InsufficientPermissions);
+                }
+                if !self.keys.entry(key).or_default().insert(purpose) {
+                    return Err(Error::KeyConflict);
+                }
+            }
+            IdentityOp::RemoveKey {
+                manager,
+                key,
+                purpose,
+            } => {
+                if !self.has(manager, 1) {
+                    return Err(Error::InsufficientPermissions);
+                }
+                if !self.keys.contains_key(&key) {
+                    return Err(Error::KeyNotFound);
+                }
+                if !self.has(key, purpose) {
+                    return Err(Error::KeyDoesNotHavePurpose);
+                }
+                if purpose == 1 && self.keys.values().filter(|p| p.contains(&1)).count() == 1 {
+                    return Err(Error::LastManagementKey);
+                }
+                let purposes = self.keys.get_mut(&key).unwrap();
+                purposes.remove(&purpose);
+                if purposes.is_empty() {
+                    self.keys.remove(&key);
+                }
+            }
+            IdentityOp::AddClaim {
+                sender,
+                topic,
+                data,
+            } => {
+                if !self.has(sender, 3) {
+                    return Err(Error::InsufficientPermissions);
+                }
+                self.claims.insert(topic, data);
+            }
+            IdentityOp::RemoveClaim { sender, topic } => {
+                if !self.has(sender, 3) {
+                    return Err(Error::InsufficientPermissions);
+                }
+                self.claims.remove(&topic).ok_or(Error::ClaimNotFound)?;
+            }
+        }
+        Ok(())
+    }
+}
 
-    // std::println!("Payload: {:?}", payload);
-    // std::println!("Checksum: {:?}", checksum);
+fn outcome<T, C: core::fmt::Debug, I: core::fmt::Debug>(
+    result: Result<Result<T, C>, Result<Error, I>>,
+) -> Result<(), Error> {
+    match result {
+        Ok(Ok(_)) => Ok(()),
+        Err(Ok(error)) => Err(error),
+        other => panic!(
+            "unexpected host failure: {:?}",
+            other.map(|r| r.map(|_| ()))
+        ),
+    }
+}
 
-    payload.extend_from_slice(&checksum);
+fn check_identity_invariants(
+    env: &Env,
+    client: &IdentityContractClient,
+    contract_id: &Address,
+    keys: &[Address],
+    model: &IdentityModel,
+) {
+    let stored = client.get_keys();
+    let mut actual = BTreeMap::new();
+    for key in stored.iter() {
+        let purposes: BTreeSet<u32> = key.purposes.iter().map(|p| p as u32).collect();
+        assert_eq!(
+            purposes.len() as u32,
+            key.purposes.len(),
+            "purposes must be a set"
+        );
+        assert!(
+            actual.insert(key.key.to_array(), purposes).is_none(),
+            "duplicate key hash"
+        );
+    }
+    let expected: BTreeMap<[u8; 32], BTreeSet<u32>> = model
+        .keys
+        .iter()
+        .map(|(i, purposes)| (hash_key(env, &keys[*i]).to_array(), purposes.clone()))
+        .collect();
+    assert_eq!(actual, expected);
+    assert!(
+        stored
+            .iter()
+            .any(|k| k.purposes.contains(KeyPurpose::Management)),
+        "a management key must remain"
+    );
 
-    let encoded = base32_encode(Alphabet::Rfc4648 { padding: false }, &payload);
+    let ids = client.get_claim_ids();
+    let id_set: BTreeSet<[u8; 32]> = ids.iter().map(|id| id.to_array()).collect();
+    assert_eq!(id_set.len() as u32, ids.len(), "claim ids must not repeat");
+    assert_eq!(id_set.len(), model.claims.len());
+    for topic in 0..PROP_TOPICS {
+        let claim_id = hash_claim(env, contract_id, &U256::from_u32(env, topic));
+        let claim = client.get_claim(&claim_id);
+        match model.claims.get(&topic) {
+            Some(data) => {
+                assert_eq!(claim.unwrap().data, Bytes::from_slice(env, &[*data]));
+                assert!(id_set.contains(&claim_id.to_array()));
+            }
+            None => {
+                assert!(claim.is_none());
+                assert!(!id_set.contains(&claim_id.to_array()));
+            }
+        }
+    }
+}
 
-    Some(encoded)
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn test_key_and_claim_invariants(ops in prop::collection::vec(identity_op(), 1..32)) {
+        // Cases are random, a snapshot of one would change on every run
+        let env = Env::new_with_config(EnvTestConfig {
+            capture_snapshot_at_drop: false,
+        });
+        env.mock_all_auths();
+        // The invariant checks alone exceed a transaction's budget
+        env.budget().reset_unlimited();
+
+        let contract_id = env.register_contract(None, IdentityContract);
+        let client = IdentityContractClient::new(&env, &contract_id);
+
+        let keys: std::vec::Vec<Address> = (0..PROP_KEYS).map(|_| Address::generate(&env)).collect();
+        client.initialize(&keys[0]);
+        let mut model = IdentityModel::default();
+        model.keys.insert(0, BTreeSet::from([1]));
+
+        for op in &ops {
+            let expected = model.apply(op);
+            let actual = match *op {
+                IdentityOp::AddKey { manager, key, purpose } => {
+                    outcome(client.try_add_key(&keys[manager], &keys[key], &purpose, &1))
+                }
+                IdentityOp::RemoveKey { manager, key, purpose } => {
+                    outcome(client.try_remove_key(&keys[manager], &keys[key], &purpose))
+                }
+                IdentityOp::AddClaim { sender, topic, data } => {
+                    // Self-issued, so no issuer contract is involved
+                    outcome(client.try_add_claim(
+                        &keys[sender],
+                        &U256::from_u32(&env, topic),
+                        &U256::from_u32(&env, 1),
+                        &contract_id,
+                        &contract_id,
+                        &Bytes::new(&env),
+                        &Bytes::from_slice(&env, &[data]),
+                        &Bytes::new(&env),
+                    ))
+                }
+                IdentityOp::RemoveClaim { sender, topic } => {
+                    let claim_id = hash_claim(&env, &contract_id, &U256::from_u32(&env, topic));
+                    outcome(client.try_remove_claim(&keys[sender], &claim_id))
+                }
+            };
+            prop_assert_eq!(actual, expected, "{:?}", op);
+            check_identity_invariants(&env, &client, &contract_id, &keys, &model);
+        }
+    }
 }
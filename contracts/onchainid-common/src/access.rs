@@ -0,0 +1,184 @@
+use soroban_sdk::{contracttype, Address, Env};
+
+use crate::events::{publish_event, OwnerChangedEvent, RoleEvent};
+
+// What the owner of the factory, gated and trex contracts can delegate instead
+// of sharing its key. The owner holds every role.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    // Configures the contract and grants every role but Admin
+    Admin = 1,
+    IdentityCreator = 2,
+    // Relays wallet links. The wallet and a management key of the identity
+    // still authorize each one, the role doesn't replace their consent.
+    Linker = 3,
+    // Chooses the factory and claim issuer compliance checks rely on
+    PolicyAdmin = 4,
+    // Token agent, pauses and resumes transfers
+    Agent = 5,
+    // Creates the onchainIDs of tokens, e.g. a token deployment contract
+    TokenFactory = 6,
+}
+
+// Kept in instance storage on purpose: a contract grants a handful of roles, so
+// they add little to the instance entry and live exactly as long as the contract
+#[contracttype]
+#[derive(Clone)]
+pub enum AccessKey {
+    Role(Role, Address),
+    PendingOwner,
+}
+
+// Contracts map these onto their own error enum
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccessError {
+    Unauthorized,
+    NoPendingOwner,
+}
+
+// Role grants and two-step ownership transfer for owner-administered contracts.
+// The owner stays in each contract's storage and is passed in.
+pub struct AccessControl;
+
+impl AccessControl {
+    pub fn has_role(env: &Env, owner: &Address, role: Role, account: &Address) -> bool {
+        account == owner
+            || env
+                .storage()
+                .instance()
+                .has(&AccessKey::Role(role, account.clone()))
+    }
+
+    // Checks that `account` holds `role` and authorized the call
+    pub fn require_role(
+        env: &Env,
+        owner: &Address,
+        role: Role,
+        account: &Address,
+    ) -> Result<(), AccessError> {
+        if !Self::has_role(env, owner, role, account) {
+            return Err(AccessError::Unauthorized);
+        }
+        account.require_auth();
+        Ok(())
+    }
+
+    // Granting a role the account already holds does nothing
+    pub fn grant_role(
+        env: &Env,
+        owner: &Address,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), AccessError> {
+        require_role_admin(env, owner, &sender, role)?;
+
+        let key = AccessKey::Role(role, account.clone());
+        if env.storage().instance().has(&key) {
+            return Ok(());
+        }
+        env.storage().instance().set(&key, &true);
+
+        publish_event(
+            env,
+            "role_granted",
+            &env.current_contract_address(),
+            RoleEvent {
+                role,
+                account,
+                sender,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn revoke_role(
+        env: &Env,
+        owner: &Address,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), AccessError> {
+        require_role_admin(env, owner, &sender, role)?;
+        remove_role(env, sender, role, account);
+        Ok(())
+    }
+
+    // Lets a compromised or departing account drop its own role
+    pub fn renounce_role(env: &Env, account: Address, role: Role) {
+        account.require_auth();
+        remove_role(env, account.clone(), role, account);
+    }
+
+    // First step of an ownership transfer, authorized by the current owner. A
+    // later call replaces the pending owner.
+    pub fn start_ownership_transfer(env: &Env, owner: Address, pending: Address) {
+        env.storage()
+            .instance()
+            .set(&AccessKey::PendingOwner, &pending);
+
+        publish_event(
+            env,
+            "ownership_transfer_started",
+            &env.current_contract_address(),
+            OwnerChangedEvent {
+                previous: owner,
+                owner: pending,
+            },
+        );
+    }
+
+    // Second step, authorized by the pending owner. Returns the new owner for
+    // the contract to store.
+    pub fn accept_ownership(env: &Env) -> Result<Address, AccessError> {
+        let pending = Self::pending_owner(env).ok_or(AccessError::NoPendingOwner)?;
+        pending.require_auth();
+
+        env.storage().instance().remove(&AccessKey::PendingOwner);
+        Ok(pending)
+    }
+
+    pub fn pending_owner(env: &Env) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get::<AccessKey, Address>(&AccessKey::PendingOwner)
+    }
+}
+
+// The owner manages every role, admins every role but their own
+fn require_role_admin(
+    env: &Env,
+    owner: &Address,
+    sender: &Address,
+    role: Role,
+) -> Result<(), AccessError> {
+    let allowed = match role {
+        Role::Admin => sender == owner,
+        _ => AccessControl::has_role(env, owner, Role::Admin, sender),
+    };
+    if !allowed {
+        return Err(AccessError::Unauthorized);
+    }
+    sender.require_auth();
+    Ok(())
+}
+
+fn remove_role(env: &Env, sender: Address, role: Role, account: Address) {
+    let key = AccessKey::Role(role, account.clone());
+    if !env.storage().instance().has(&key) {
+        return;
+    }
+    env.storage().instance().remove(&key);
+
+    publish_event(
+        env,
+        "role_revoked",
+        &env.current_contract_address(),
+        RoleEvent {
+            role,
+            account,
+            sender,
+        },
+    );
+}
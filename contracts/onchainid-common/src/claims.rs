@@ -0,0 +1,140 @@
+use soroban_sdk::{contractclient, log, Address, Bytes, BytesN, Env, Vec, U256};
+
+use crate::events::{publish_event, ClaimAddedEvent, ClaimRemovedEvent};
+use crate::{hash_claim, identity_require_auth, Claim, DataKey, Error, KeyPurpose};
+
+// The part of the claim issuer interface a claim holder relies on
+#[contractclient(name = "ClaimIssuerClient")]
+pub trait ClaimIssuer {
+    fn is_claim_valid(
+        env: Env,
+        issuer_wallet: Address,
+        identity: Address,
+        topic: U256,
+        scheme: U256,
+        signature: Bytes,
+        data: Bytes,
+    ) -> Result<bool, Error>;
+}
+
+// ERC-735 claim storage shared by identities and claim issuers
+pub struct ClaimHolder;
+
+impl ClaimHolder {
+    pub fn get_claim(env: &Env, claim_id: BytesN<32>) -> Result<Option<Claim>, Error> {
+        Ok(env
+            .storage()
+            .persistent()
+            .get::<DataKey, Claim>(&DataKey::Claim(claim_id)))
+    }
+
+    pub fn get_claim_ids(env: &Env) -> Result<Vec<BytesN<32>>, Error> {
+        Ok(env
+            .storage()
+            .persistent()
+            .get::<DataKey, Vec<BytesN<32>>>(&DataKey::ClaimIds)
+            .unwrap_or(Vec::new(env)))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_claim(
+        env: &Env,
+        sender: Address,
+        topic: U256,
+        scheme: U256,
+        issuer_wallet: Address,
+        issuer: Address,
+        signature: Bytes,
+        data: Bytes,
+        uri: Bytes,
+    ) -> Result<BytesN<32>, Error> {
+        identity_require_auth(env, &sender, KeyPurpose::Claim)?;
+
+        let current_contact = env.current_contract_address();
+
+        if current_contact != issuer {
+            let client = ClaimIssuerClient::new(env, &issuer);
+            if !client.is_claim_valid(
+                &issuer_wallet,
+                &current_contact,
+                &topic,
+                &scheme,
+                &signature,
+                &data,
+            ) {
+                return Err(Error::InvalidClaim);
+            }
+        }
+
+        let claim: Claim = Claim {
+            topic,
+            scheme,
+            issuer,
+            issuer_wallet,
+            signature,
+            data,
+            uri,
+        };
+
+        Ok(Self::store_claim(env, sender, claim))
+    }
+
+    // Stores an already validated claim, re-issuing a claim replaces it in place
+    pub fn store_claim(env: &Env, sender: Address, claim: Claim) -> BytesN<32> {
+        let claim_id = hash_claim(env, &claim.issuer, &claim.topic);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Claim(claim_id.clone()), &claim);
+
+        let mut claims = Self::get_claim_ids(env).unwrap_or(Vec::new(env));
+
+        if !claims.contains(&claim_id) {
+            claims.push_back(claim_id.clone());
+        }
+
+        env.storage().persistent().set(&DataKey::ClaimIds, &claims);
+
+        log!(env, "Claim added: {:?}", claim);
+
+        publish_event(
+            env,
+            "claim_added",
+            &env.current_contract_address(),
+            ClaimAddedEvent {
+                sender,
+                claim_id: claim_id.clone(),
+                claim,
+            },
+        );
+
+        claim_id
+    }
+
+    pub fn remove_claim(env: &Env, sender: Address, claim_id: BytesN<32>) -> Result<(), Error> {
+        identity_require_auth(env, &sender, KeyPurpose::Claim)?;
+
+        let claim = Self::get_claim(env, claim_id.clone())?.ok_or(Error::ClaimNotFound)?;
+
+        env.storage()
+            .persistent()
+            .remove(&DataKey::Claim(claim_id.clone()));
+
+        let mut claims = Self::get_claim_ids(env)?;
+
+        if let Some(pos) = claims.iter().position(|c| c == claim_id) {
+            claims.remove(pos as u32);
+        }
+
+        env.storage().persistent().set(&DataKey::ClaimIds, &claims);
+
+        log!(env, "Claim removed: {:?}", claim);
+
+        publish_event(
+            env,
+            "claim_removed",
+            &env.current_contract_address(),
+            ClaimRemovedEvent { sender, claim_id },
+        );
+        Ok(())
+    }
+}
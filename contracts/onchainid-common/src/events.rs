@@ -0,0 +1,360 @@
+use soroban_sdk::{
+    contracttype, symbol_short, Address, BytesN, Env, IntoVal, String, Symbol, Val, Vec, U256,
+};
+
+use crate::{Claim, Role};
+
+// Every 0xAuth event is published with the topics
+// ("0xauth", EVENT_VERSION, <event name>, <subject>)
+// and one of the structs below as data. The subject is the address the event is
+// about: the identity for claim events, the emitting contract otherwise.
+// Changing a struct or a name means bumping EVENT_VERSION.
+pub const EVENT_NAMESPACE: Symbol = symbol_short!("0xauth");
+pub const EVENT_VERSION: Symbol = symbol_short!("v1");
+
+pub fn publish_event<D>(env: &Env, name: &str, subject: &Address, data: D)
+where
+    D: IntoVal<Env, Val>,
+{
+    env.events().publish(
+        (
+            EVENT_NAMESPACE,
+            EVENT_VERSION,
+            Symbol::new(env, name),
+            subject.clone(),
+        ),
+        data,
+    );
+}
+
+// "initialized"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InitializedEvent {
+    pub owner: Address,
+}
+
+// "migrated"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigratedEvent {
+    pub by: Address,
+    pub version: u32,
+}
+
+// "owner_changed" and "ownership_transfer_started", where `owner` is the
+// pending owner until it accepts
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnerChangedEvent {
+    pub previous: Address,
+    pub owner: Address,
+}
+
+// "role_granted" and "role_revoked"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RoleEvent {
+    pub role: Role,
+    pub account: Address,
+    pub sender: Address,
+}
+
+// "key_added"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyAddedEvent {
+    pub manager: Address,
+    pub key: Address,
+    pub purpose: u32,
+    pub key_type: u32,
+}
+
+// "key_removed"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyRemovedEvent {
+    pub manager: Address,
+    pub key: Address,
+    pub purpose: u32,
+}
+
+// "claim_added"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimAddedEvent {
+    pub sender: Address,
+    pub claim_id: BytesN<32>,
+    pub claim: Claim,
+}
+
+// "claim_removed"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimRemovedEvent {
+    pub sender: Address,
+    pub claim_id: BytesN<32>,
+}
+
+// "issuer_approved", subject is the identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IssuerApprovedEvent {
+    pub sender: Address,
+    pub issuer: Address,
+    pub topics: Vec<U256>,
+}
+
+// "issuer_revoked", subject is the identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IssuerRevokedEvent {
+    pub sender: Address,
+    pub issuer: Address,
+}
+
+// "upgrade_authority_set" and "upgrade_authority_removed", subject is the identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UpgradeAuthorityEvent {
+    pub sender: Address,
+    pub authority: Address,
+}
+
+// "upgraded", subject is the upgraded contract
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UpgradedEvent {
+    pub authority: Address,
+    pub wasm_hash: BytesN<32>,
+}
+
+// "key_scope_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyScopeSetEvent {
+    pub manager: Address,
+    pub key: Address,
+    pub topics: Vec<U256>,
+    pub schemes: Vec<U256>,
+}
+
+// "key_scope_removed"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyScopeRemovedEvent {
+    pub manager: Address,
+    pub key: Address,
+}
+
+// "metadata_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MetadataSetEvent {
+    pub manager: Address,
+    pub name: String,
+    pub jurisdiction: String,
+    pub uri: String,
+    pub contact: String,
+}
+
+// "topic_supported"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TopicSupportedEvent {
+    pub manager: Address,
+    pub topic: U256,
+    pub schema: String,
+}
+
+// "topic_unsupported"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TopicUnsupportedEvent {
+    pub manager: Address,
+    pub topic: U256,
+}
+
+// "delegation_added"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelegationAddedEvent {
+    pub manager: Address,
+    pub sub_issuer: Address,
+    pub topics: Vec<U256>,
+    pub expiry: u64,
+}
+
+// "delegation_revoked"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelegationRevokedEvent {
+    pub manager: Address,
+    pub sub_issuer: Address,
+}
+
+// "claim_issued", subject is the identity the claim was written to
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimIssuedEvent {
+    pub sender: Address,
+    pub claim_id: BytesN<32>,
+    pub topic: U256,
+    pub status_index: u32,
+}
+
+// "claim_revoked", subject is the identity holding the claim
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimRevokedEvent {
+    pub sender: Address,
+    pub claim_id: BytesN<32>,
+}
+
+// "status_registered"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatusRegisteredEvent {
+    pub sender: Address,
+    pub index: u32,
+}
+
+// "status_revoked"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatusRevokedEvent {
+    pub sender: Address,
+    pub list_id: u32,
+    pub index: u32,
+}
+
+// "validator_added" and "validator_removed"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidatorEvent {
+    pub manager: Address,
+    pub validator: BytesN<32>,
+}
+
+// "threshold_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ThresholdSetEvent {
+    pub manager: Address,
+    pub threshold: u32,
+}
+
+// "claim_bridged", subject is the identity the claim was written to
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimBridgedEvent {
+    pub chain_id: u64,
+    pub tx_hash: BytesN<32>,
+    pub log_index: u64,
+    pub claim_id: BytesN<32>,
+    pub topic: U256,
+}
+
+// "identity_created", subject is the new identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IdentityCreatedEvent {
+    pub wallet: Address,
+    pub salt: BytesN<32>,
+}
+
+// "identities_upgraded", one per upgrade_identities batch
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IdentitiesUpgradedEvent {
+    pub version: u32,
+    pub upgraded: u32,
+    pub failed: Vec<Address>,
+}
+
+// "token_identity_created", subject is the new identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenIdentityCreatedEvent {
+    pub token: Address,
+    pub token_owner: Address,
+    pub salt: BytesN<32>,
+}
+
+// "identity_wasm_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IdentityWasmSetEvent {
+    pub version: u32,
+    pub wasm_hash: BytesN<32>,
+}
+
+// "self_service_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SelfServiceSetEvent {
+    pub enabled: bool,
+    pub allowlist: bool,
+}
+
+// "creation_fee_set", an amount of 0 removed the fee
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CreationFeeSetEvent {
+    pub token: Address,
+    pub amount: i128,
+}
+
+// "allowlist_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AllowlistSetEvent {
+    pub wallet: Address,
+    pub allowed: bool,
+}
+
+// "wallet_linked" and "wallet_unlinked", subject is the identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WalletLinkEvent {
+    pub wallet: Address,
+}
+
+// "evm_address_linked" and "evm_address_unlinked", subject is the identity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EvmLinkEvent {
+    pub evm_address: BytesN<20>,
+}
+
+// "claim_issuer_set" and "identity_factory_set"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ContractSetEvent {
+    pub contract: Address,
+}
+
+// "claim_validated", subject is the wallet that was checked
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimValidatedEvent {
+    pub issuer: Address,
+    pub topic: U256,
+    pub valid: bool,
+}
+
+// "paused" and "unpaused"
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PauseEvent {
+    pub sender: Address,
+}
+
+// "transfer", subject is the sender
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransferEvent {
+    pub token: Address,
+    pub to: Address,
+    pub amount: i128,
+}
@@ -0,0 +1,241 @@
+use soroban_sdk::{contractclient, log, vec, Address, BytesN, Env, Vec};
+
+use crate::events::{publish_event, InitializedEvent, KeyAddedEvent, KeyRemovedEvent};
+use crate::{hash_key, is_legacy, set_schema_version, DataKey, Error, Key, KeyPurpose, KeyType};
+
+// The part of the identity interface other contracts check and add keys with
+#[contractclient(name = "KeyHolderClient")]
+pub trait KeyHolder {
+    fn get_key(env: Env, key: Address) -> Result<Key, Error>;
+    fn add_key(
+        env: Env,
+        manager: Address,
+        key: Address,
+        purpose: u32,
+        key_type: u32,
+    ) -> Result<(), Error>;
+}
+
+// Implemented by identities that let a trusted authority replace their code
+#[contractclient(name = "UpgradeableClient")]
+pub trait Upgradeable {
+    fn upgrade(env: Env, wasm_hash: BytesN<32>) -> Result<(), Error>;
+}
+
+// ERC-734 key management shared by every contract that holds keys.
+// Contracts expose these through their own #[contractimpl] functions.
+pub struct KeyManager;
+
+impl KeyManager {
+    pub fn get_initialized(env: &Env) -> Result<bool, Error> {
+        Ok(env
+            .storage()
+            .instance()
+            .get::<DataKey, bool>(&DataKey::Initialized)
+            .unwrap_or(false)
+            || is_legacy(env))
+    }
+
+    pub fn initialize(env: &Env, initial_management_key: Address) -> Result<(), Error> {
+        if Self::get_initialized(env)? {
+            return Err(Error::AlreadyInitialized);
+        }
+        env.storage().instance().set(&DataKey::Initialized, &true);
+        set_schema_version(env);
+
+        let key_hash = hash_key(env, &initial_management_key);
+        let key = Key {
+            purposes: vec![env, KeyPurpose::Management],
+            key_type: KeyType::ECDSA,
+            key: key_hash.clone(),
+        };
+
+        let keys = vec![env, key];
+        env.storage().persistent().set(&DataKey::Keys, &keys);
+
+        log!(
+            env,
+            "Identity contract initialized with management key: {:?}",
+            initial_management_key
+        );
+
+        publish_event(
+            env,
+            "initialized",
+            &env.current_contract_address(),
+            InitializedEvent {
+                owner: initial_management_key,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get_key(env: &Env, key: Address) -> Result<Key, Error> {
+        let key_hash = hash_key(env, &key);
+
+        Self::get_keys(env)?
+            .iter()
+            .find(|k| k.key == key_hash)
+            .ok_or(Error::KeyNotFound)
+    }
+
+    pub fn get_keys(env: &Env) -> Result<Vec<Key>, Error> {
+        Ok(env
+            .storage()
+            .persistent()
+            .get::<DataKey, Vec<Key>>(&DataKey::Keys)
+            .unwrap_or(Vec::new(env)))
+    }
+
+    pub fn add_key(
+        env: &Env,
+        manager: Address,
+        key: Address,
+        purpose: u32,
+        key_type: u32,
+    ) -> Result<(), Error> {
+        // Only the manager can add keys
+        identity_require_auth(env, &manager, KeyPurpose::Management)?;
+
+        // Make a Kecak256 hash of the key
+        let key_hash = hash_key(env, &key);
+        let key_purpose = KeyPurpose::try_from(purpose).map_err(|_| Error::InvalidKeyPurpose)?;
+
+        // Retrieve and mutate the list of keys
+        let mut keys = Self::get_keys(env)?;
+
+        let mut key_found = false;
+
+        for i in 0..keys.len() {
+            let mut k = keys.get(i).ok_or(Error::IndexOutOfBounds)?;
+            if k.key == key_hash {
+                if k.purposes.contains(key_purpose) {
+                    return Err(Error::KeyConflict);
+                } else {
+                    k.purposes.push_back(key_purpose);
+                    keys.set(i, k);
+                    key_found = true;
+                    break;
+                }
+            }
+        }
+
+        if !key_found {
+            let key = Key {
+                purposes: vec![env, key_purpose],
+                key_type: KeyType::try_from(key_type).map_err(|_| Error::InvalidKeyType)?,
+                key: key_hash.clone(),
+            };
+            keys.push_back(key);
+        }
+        env.storage().persistent().set(&DataKey::Keys, &keys);
+
+        publish_event(
+            env,
+            "key_added",
+            &env.current_contract_address(),
+            KeyAddedEvent {
+                manager,
+                key,
+                purpose,
+                key_type,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove_key(
+        env: &Env,
+        manager: Address,
+        key: Address,
+        purpose: u32,
+    ) -> Result<(), Error> {
+        // Only the manager can remove keys
+        identity_require_auth(env, &manager, KeyPurpose::Management)?;
+
+        // Make a Kecak256 hash of the key
+        let key_hash = hash_key(env, &key);
+        let key_purpose = KeyPurpose::try_from(purpose).map_err(|_| Error::InvalidKeyPurpose)?;
+
+        // Retrieve and mutate the list of keys
+        let mut keys = Self::get_keys(env)?;
+
+        if !keys.iter().any(|k| k.key == key_hash) {
+            return Err(Error::KeyNotFound);
+        }
+
+        // A contract without a Management key could never be managed again
+        if key_purpose == KeyPurpose::Management
+            && !keys
+                .iter()
+                .any(|k| k.key != key_hash && k.purposes.contains(KeyPurpose::Management))
+            && key_has_purpose(env, &key_hash, KeyPurpose::Management)
+        {
+            return Err(Error::LastManagementKey);
+        }
+
+        for i in 0..keys.len() {
+            if let Some(mut k) = keys.get(i) {
+                if k.key == key_hash {
+                    if let Some(pos) = k.purposes.iter().position(|p| p == key_purpose) {
+                        k.purposes.remove(pos as u32);
+
+                        if k.purposes.is_empty() {
+                            keys.remove(i);
+                        } else {
+                            keys.set(i, k);
+                        }
+                    } else {
+                        return Err(Error::KeyDoesNotHavePurpose);
+                    }
+                    break;
+                }
+            } else {
+                return Err(Error::IndexOutOfBounds);
+            }
+        }
+
+        env.storage().persistent().set(&DataKey::Keys, &keys);
+
+        publish_event(
+            env,
+            "key_removed",
+            &env.current_contract_address(),
+            KeyRemovedEvent {
+                manager,
+                key,
+                purpose,
+            },
+        );
+        Ok(())
+    }
+}
+
+pub fn key_has_purpose(env: &Env, key_hash: &BytesN<32>, purpose: KeyPurpose) -> bool {
+    if let Some(keys) = env
+        .storage()
+        .persistent()
+        .get::<DataKey, Vec<Key>>(&DataKey::Keys)
+    {
+        keys.iter()
+            .any(|k| k.key == *key_hash && k.purposes.contains(purpose))
+    } else {
+        false
+    }
+}
+
+// Checks that `sender` holds a key with `key_type` purpose and authorized the call
+pub fn identity_require_auth(
+    env: &Env,
+    sender: &Address,
+    key_type: KeyPurpose,
+) -> Result<(), Error> {
+    let key_hash = hash_key(env, sender);
+
+    if !key_has_purpose(env, &key_hash, key_type) {
+        return Err(Error::InsufficientPermissions);
+    }
+
+    sender.require_auth();
+    Ok(())
+}
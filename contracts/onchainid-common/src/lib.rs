@@ -0,0 +1,95 @@
+#![no_std]
+
+// Types and key/claim management shared by the identity and claim issuer contracts
+
+use soroban_sdk::{xdr::ToXdr, Address, Bytes, BytesN, Env, FromVal, U256};
+
+mod state;
+pub use state::{Claim, Error, Key, KeyPurpose, KeyType, CONTRACT_SCHEME};
+
+pub mod events;
+
+mod access;
+pub use access::{AccessControl, AccessError, AccessKey, Role};
+
+mod storage;
+pub use storage::{
+    get_schema_version, is_legacy, migrate_legacy, set_schema_version, DataKey, SCHEMA_VERSION,
+};
+
+mod keys;
+pub use keys::{
+    identity_require_auth, key_has_purpose, KeyHolder, KeyHolderClient, KeyManager, Upgradeable,
+    UpgradeableClient,
+};
+
+mod claims;
+pub use claims::{ClaimHolder, ClaimIssuer, ClaimIssuerClient};
+
+pub fn hash_key(env: &Env, key: &Address) -> BytesN<32> {
+    let address_bytes = Bytes::from_val(env, &key.to_xdr(env));
+    env.crypto().keccak256(&address_bytes)
+}
+
+// Claim ids are keccak256(issuer XDR ‖ topic XDR), as in ERC-735
+pub fn hash_claim(env: &Env, issuer: &Address, topic: &U256) -> BytesN<32> {
+    let address_bytes = Bytes::from_val(env, &issuer.to_xdr(env));
+    let topic_bytes = Bytes::from_val(env, &topic.to_xdr(env));
+
+    let mut concatenated_bytes = Bytes::new(env);
+    concatenated_bytes.append(&address_bytes);
+    concatenated_bytes.append(&topic_bytes);
+    env.crypto().keccak256(&concatenated_bytes)
+}
+
+// Canonical factory salt for a wallet's n-th identity: keccak256(wallet XDR ‖ nonce),
+// the nonce big-endian, so its address can be predicted from the wallet alone
+pub fn identity_salt(env: &Env, wallet: &Address, nonce: u32) -> BytesN<32> {
+    let mut concatenated_bytes = Bytes::new(env);
+    concatenated_bytes.append(&wallet.clone().to_xdr(env));
+    concatenated_bytes.extend_from_array(&nonce.to_be_bytes());
+    env.crypto().keccak256(&concatenated_bytes)
+}
+
+// The message issuers sign for a claim: keccak256(identity XDR ‖ topic XDR ‖ data),
+// XDR-encoded again as signers sign the ScVal of the digest
+pub fn claim_digest(env: &Env, identity: &Address, topic: &U256, data: &Bytes) -> Bytes {
+    let mut concatenated_bytes = Bytes::new(env);
+    concatenated_bytes.append(&identity.clone().to_xdr(env));
+    concatenated_bytes.append(&topic.clone().to_xdr(env));
+    concatenated_bytes.append(data);
+
+    env.crypto().keccak256(&concatenated_bytes).to_xdr(env)
+}
+
+// Checks an ed25519 claim signature against the key of the issuer wallet account.
+// Like the host's ed25519_verify, a signature that doesn't verify traps.
+pub fn verify_claim_signature(
+    env: &Env,
+    issuer_wallet: &Address,
+    identity: &Address,
+    topic: &U256,
+    signature: &Bytes,
+    data: &Bytes,
+) -> Result<(), Error> {
+    let data_digest = claim_digest(env, identity, topic, data);
+
+    let signature_slice: BytesN<64> = match signature.slice(..64).try_into() {
+        Ok(slice) => slice,
+        Err(_) => return Err(Error::InvalidSignature),
+    };
+
+    // Account address XDR is type tags followed by the raw ed25519 key at 12..44
+    let issuer_xdr = issuer_wallet.clone().to_xdr(env);
+
+    let issuer_bytes: BytesN<32> = match issuer_xdr.slice(12..44).try_into() {
+        Ok(slice) => slice,
+        Err(_) => return Err(Error::InvalidAddressBytes),
+    };
+
+    env.crypto()
+        .ed25519_verify(&issuer_bytes, &data_digest, &signature_slice);
+    Ok(())
+}
+
+mod test;
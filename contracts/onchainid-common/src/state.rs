@@ -1,7 +1,11 @@
-use soroban_sdk::{contracttype,contracterror, Address, Bytes, BytesN, Vec, U256};
+use soroban_sdk::{contracterror, contracttype, Address, Bytes, BytesN, Vec, U256};
+
+// ERC-735 scheme for claims written by the issuer contract itself. They carry no
+// signature, the issuer's own registry is the proof.
+pub const CONTRACT_SCHEME: u32 = 3;
 
 #[contracttype]
-#[derive(Clone,Copy)]
+#[derive(Clone, Copy)]
 pub enum KeyType {
     ECDSA = 1,
     RSA = 2,
@@ -19,7 +23,6 @@ impl TryFrom<u32> for KeyType {
     }
 }
 
-
 #[contracttype]
 #[derive(Clone, Copy, PartialEq)]
 pub enum KeyPurpose {
@@ -52,7 +55,7 @@ pub struct Key {
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Claim {
     pub topic: U256,
     pub scheme: U256,
@@ -81,4 +84,24 @@ pub enum Error {
     InvalidClaim = 12,
     InvalidIssuer = 13,
     InvalidAddressBytes = 14,
-}
\ No newline at end of file
+    KeyScopeNotFound = 15,
+    DelegationNotFound = 16,
+    DelegationConflict = 17,
+    DelegationAlreadyRevoked = 18,
+    InvalidDelegation = 19,
+    IssuerNotAllowed = 20,
+    StatusAlreadyRevoked = 21,
+    StatusAlreadyAssigned = 22,
+    StatusIndexOutOfRange = 23,
+    TopicNotSupported = 24,
+    AlreadyMigrated = 25,
+    NotInitialized = 26,
+    ValidatorConflict = 27,
+    ValidatorNotFound = 28,
+    InvalidThreshold = 29,
+    InsufficientSignatures = 30,
+    SourceEventAlreadyBridged = 31,
+    BridgedClaimNotFound = 32,
+    LastManagementKey = 33,
+    UpgradeAuthorityNotSet = 34,
+}
@@ -0,0 +1,114 @@
+use soroban_sdk::{contracttype, symbol_short, Address, BytesN, Env, Symbol, Vec};
+
+use crate::events::{publish_event, MigratedEvent};
+use crate::{hash_key, Claim, Error, Key, KeyPurpose};
+
+// Version of the storage layout written by this code. Contracts deployed before
+// versioning used bare Symbol keys and are reported as version 0.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Storage shared by every key and claim holder. Contracts keep their own
+// entries in a separate enum so the two can never collide.
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Initialized,
+    SchemaVersion,
+    Keys,
+    ClaimIds,
+    Claim(BytesN<32>),
+}
+
+pub fn get_schema_version(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get::<DataKey, u32>(&DataKey::SchemaVersion)
+        .unwrap_or(0)
+}
+
+pub fn set_schema_version(env: &Env) {
+    env.storage()
+        .instance()
+        .set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+}
+
+// Whether the contract was initialized with the unversioned layout
+pub fn is_legacy(env: &Env) -> bool {
+    !env.storage().instance().has(&DataKey::SchemaVersion)
+        && env
+            .storage()
+            .instance()
+            .get::<Symbol, bool>(&Symbol::new(env, "initialized"))
+            .unwrap_or(false)
+}
+
+// Moves keys, claim ids and claims from the unversioned layout.
+// `manager` must hold a Management key in the legacy key list.
+pub fn migrate_legacy(env: &Env, manager: &Address) -> Result<(), Error> {
+    if !is_legacy(env) {
+        return Err(if get_schema_version(env) >= SCHEMA_VERSION {
+            Error::AlreadyMigrated
+        } else {
+            Error::NotInitialized
+        });
+    }
+
+    let legacy_keys = env
+        .storage()
+        .persistent()
+        .get::<Symbol, Vec<Key>>(&symbol_short!("keys"))
+        .unwrap_or(Vec::new(env));
+
+    let manager_hash = hash_key(env, manager);
+    if !legacy_keys
+        .iter()
+        .any(|k| k.key == manager_hash && k.purposes.contains(KeyPurpose::Management))
+    {
+        return Err(Error::InsufficientPermissions);
+    }
+    manager.require_auth();
+
+    let init_symbol = Symbol::new(env, "initialized");
+    env.storage().instance().remove(&init_symbol);
+    env.storage().instance().set(&DataKey::Initialized, &true);
+
+    env.storage().persistent().remove(&symbol_short!("keys"));
+    env.storage().persistent().set(&DataKey::Keys, &legacy_keys);
+
+    let claim_ids = env
+        .storage()
+        .persistent()
+        .get::<Symbol, Vec<BytesN<32>>>(&symbol_short!("claims"))
+        .unwrap_or(Vec::new(env));
+
+    for claim_id in claim_ids.iter() {
+        if let Some(claim) = env
+            .storage()
+            .persistent()
+            .get::<BytesN<32>, Claim>(&claim_id)
+        {
+            env.storage().persistent().remove(&claim_id);
+            env.storage()
+                .persistent()
+                .set(&DataKey::Claim(claim_id.clone()), &claim);
+        }
+    }
+
+    env.storage().persistent().remove(&symbol_short!("claims"));
+    env.storage()
+        .persistent()
+        .set(&DataKey::ClaimIds, &claim_ids);
+
+    set_schema_version(env);
+
+    publish_event(
+        env,
+        "migrated",
+        &env.current_contract_address(),
+        MigratedEvent {
+            by: manager.clone(),
+            version: SCHEMA_VERSION,
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,322 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use events::{
+    ClaimAddedEvent, ClaimRemovedEvent, InitializedEvent, KeyAddedEvent, KeyRemovedEvent,
+    EVENT_NAMESPACE, EVENT_VERSION,
+};
+use soroban_sdk::{
+    contract, contractimpl, symbol_short,
+    testutils::{Address as _, Events},
+    vec, IntoVal, Symbol, Vec,
+};
+
+// Minimal holder composing the shared implementations, like identity and claim issuer do
+#[contract]
+pub struct HolderContract;
+
+#[contractimpl]
+impl HolderContract {
+    pub fn initialize(env: Env, initial_management_key: Address) -> Result<(), Error> {
+        KeyManager::initialize(&env, initial_management_key)
+    }
+
+    pub fn get_keys(env: Env) -> Result<Vec<Key>, Error> {
+        KeyManager::get_keys(&env)
+    }
+
+    pub fn get_schema_version(env: Env) -> u32 {
+        get_schema_version(&env)
+    }
+
+    pub fn migrate(env: Env, manager: Address) -> Result<(), Error> {
+        migrate_legacy(&env, &manager)
+    }
+
+    pub fn add_key(
+        env: Env,
+        manager: Address,
+        key: Address,
+        purpose: u32,
+        key_type: u32,
+    ) -> Result<(), Error> {
+        KeyManager::add_key(&env, manager, key, purpose, key_type)
+    }
+
+    pub fn remove_key(env: Env, manager: Address, key: Address, purpose: u32) -> Result<(), Error> {
+        KeyManager::remove_key(&env, manager, key, purpose)
+    }
+
+    pub fn add_claim(
+        env: Env,
+        sender: Address,
+        topic: U256,
+        data: Bytes,
+    ) -> Result<BytesN<32>, Error> {
+        let issuer = env.current_contract_address();
+        ClaimHolder::add_claim(
+            &env,
+            sender,
+            topic,
+            U256::from_u32(&env, 1),
+            issuer.clone(),
+            issuer,
+            Bytes::new(&env),
+            data,
+            Bytes::new(&env),
+        )
+    }
+
+    pub fn get_claim_ids(env: Env) -> Result<Vec<BytesN<32>>, Error> {
+        ClaimHolder::get_claim_ids(&env)
+    }
+
+    pub fn remove_claim(env: Env, sender: Address, claim_id: BytesN<32>) -> Result<(), Error> {
+        ClaimHolder::remove_claim(&env, sender, claim_id)
+    }
+}
+
+#[test]
+fn test_key_manager() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, HolderContract);
+    let client = HolderContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+    assert_eq!(
+        client.try_initialize(&management_key),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+
+    let new_key = Address::generate(&env);
+    client.add_key(&management_key, &new_key, &3, &1);
+    client.add_key(&management_key, &new_key, &2, &1);
+    assert_eq!(
+        client.try_add_key(&management_key, &new_key, &3, &1),
+        Err(Ok(Error::KeyConflict))
+    );
+
+    let keys = client.get_keys();
+    assert_eq!(keys.len(), 2, "Purposes should be merged into one key");
+
+    client.remove_key(&management_key, &new_key, &3);
+    client.remove_key(&management_key, &new_key, &2);
+    assert_eq!(
+        client.get_keys().len(),
+        1,
+        "Key without purposes should be removed"
+    );
+
+    // Keys without the Management purpose cannot manage
+    assert_eq!(
+        client.try_add_key(&new_key, &new_key, &1, &1),
+        Err(Ok(Error::InsufficientPermissions))
+    );
+
+    // The last Management key stays
+    assert_eq!(
+        client.try_remove_key(&management_key, &management_key, &1),
+        Err(Ok(Error::LastManagementKey))
+    );
+    client.add_key(&management_key, &new_key, &1, &1);
+    client.remove_key(&new_key, &management_key, &1);
+}
+
+#[test]
+fn test_claim_holder() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, HolderContract);
+    let client = HolderContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    client.initialize(&management_key);
+    client.add_key(&management_key, &management_key, &3, &1);
+
+    let topic = U256::from_u32(&env, 6);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+
+    // Self-issued claims skip the issuer check
+    let claim_id = client.add_claim(&management_key, &topic, &data);
+    assert_eq!(claim_id, hash_claim(&env, &contract_id, &topic));
+
+    client.add_claim(&management_key, &topic, &data);
+    assert_eq!(
+        client.get_claim_ids().len(),
+        1,
+        "Claim ids should not repeat"
+    );
+
+    client.remove_claim(&management_key, &claim_id);
+    assert_eq!(client.get_claim_ids().len(), 0);
+    assert_eq!(
+        client.try_remove_claim(&management_key, &claim_id),
+        Err(Ok(Error::ClaimNotFound))
+    );
+}
+
+#[test]
+fn test_migrate_legacy() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, HolderContract);
+    let client = HolderContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let claim_id = hash_claim(&env, &contract_id, &topic);
+
+    // Storage as written before schema versioning
+    env.as_contract(&contract_id, || {
+        let key = Key {
+            purposes: vec![&env, KeyPurpose::Management, KeyPurpose::Claim],
+            key_type: KeyType::ECDSA,
+            key: hash_key(&env, &management_key),
+        };
+        let claim = Claim {
+            topic: topic.clone(),
+            scheme: U256::from_u32(&env, 1),
+            issuer: contract_id.clone(),
+            issuer_wallet: contract_id.clone(),
+            signature: Bytes::new(&env),
+            data: Bytes::new(&env),
+            uri: Bytes::new(&env),
+        };
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "initialized"), &true);
+        env.storage()
+            .persistent()
+            .set(&symbol_short!("keys"), &vec![&env, key]);
+        env.storage()
+            .persistent()
+            .set(&symbol_short!("claims"), &vec![&env, claim_id.clone()]);
+        env.storage().persistent().set(&claim_id, &claim);
+    });
+
+    assert_eq!(client.get_schema_version(), 0);
+    assert_eq!(
+        client.try_initialize(&management_key),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+    assert_eq!(
+        client.try_migrate(&Address::generate(&env)),
+        Err(Ok(Error::InsufficientPermissions))
+    );
+
+    client.migrate(&management_key);
+
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(client.get_keys().len(), 1);
+    assert_eq!(client.get_claim_ids(), vec![&env, claim_id.clone()]);
+    assert_eq!(
+        client.try_migrate(&management_key),
+        Err(Ok(Error::AlreadyMigrated))
+    );
+
+    // Migrated claims are reachable through the typed keys
+    client.remove_claim(&management_key, &claim_id);
+    env.as_contract(&contract_id, || {
+        assert!(!env.storage().persistent().has(&claim_id));
+        assert!(!env.storage().persistent().has(&symbol_short!("keys")));
+    });
+}
+
+#[test]
+fn test_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, HolderContract);
+    let client = HolderContractClient::new(&env, &contract_id);
+
+    let management_key = Address::generate(&env);
+    let topic = U256::from_u32(&env, 6);
+    let data = Bytes::from_slice(&env, "data".as_bytes());
+
+    client.initialize(&management_key);
+    client.add_key(&management_key, &management_key, &3, &1);
+    let claim_id = client.add_claim(&management_key, &topic, &data);
+    client.remove_claim(&management_key, &claim_id);
+    client.remove_key(&management_key, &management_key, &3);
+
+    let topics = |name: &str| {
+        (
+            EVENT_NAMESPACE,
+            EVENT_VERSION,
+            Symbol::new(&env, name),
+            contract_id.clone(),
+        )
+            .into_val(&env)
+    };
+    let claim = Claim {
+        topic: topic.clone(),
+        scheme: U256::from_u32(&env, 1),
+        issuer: contract_id.clone(),
+        issuer_wallet: contract_id.clone(),
+        signature: Bytes::new(&env),
+        data: data.clone(),
+        uri: Bytes::new(&env),
+    };
+
+    assert_eq!(
+        env.events().all(),
+        vec![
+            &env,
+            (
+                contract_id.clone(),
+                topics("initialized"),
+                InitializedEvent {
+                    owner: management_key.clone()
+                }
+                .into_val(&env)
+            ),
+            (
+                contract_id.clone(),
+                topics("key_added"),
+                KeyAddedEvent {
+                    manager: management_key.clone(),
+                    key: management_key.clone(),
+                    purpose: 3,
+                    key_type: 1,
+                }
+                .into_val(&env)
+            ),
+            (
+                contract_id.clone(),
+                topics("claim_added"),
+                ClaimAddedEvent {
+                    sender: management_key.clone(),
+                    claim_id: claim_id.clone(),
+                    claim,
+                }
+                .into_val(&env)
+            ),
+            (
+                contract_id.clone(),
+                topics("claim_removed"),
+                ClaimRemovedEvent {
+                    sender: management_key.clone(),
+                    claim_id: claim_id.clone(),
+                }
+                .into_val(&env)
+            ),
+            (
+                contract_id.clone(),
+                topics("key_removed"),
+                KeyRemovedEvent {
+                    manager: management_key.clone(),
+                    key: management_key.clone(),
+                    purpose: 3,
+                }
+                .into_val(&env)
+            ),
+        ]
+    );
+}
@@ -1,8 +1,14 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contracterror, contractimpl, symbol_short, Address, Env, Symbol, U256, token
+    contract, contracterror, contractimpl, contracttype, symbol_short, Address, BytesN, Env, Symbol, U256, token
 };
 
+use onchainid_common::events::{
+    publish_event, ContractSetEvent, InitializedEvent, MigratedEvent, OwnerChangedEvent,
+    PauseEvent, TransferEvent, UpgradedEvent,
+};
+use onchainid_common::{AccessControl, AccessError, Role};
+
 mod gated {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/gated.wasm"
@@ -12,10 +18,37 @@ mod gated {
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
-pub enum Error {
+pub enum TrexError {
     AlreadyInitialized = 1,
     InsufficientPermissions = 2,
     IdentityNotFound = 3,
+    AlreadyMigrated = 4,
+    NotInitialized = 5,
+    NoPendingOwner = 6,
+    Paused = 7,
+}
+
+impl From<AccessError> for TrexError {
+    fn from(error: AccessError) -> Self {
+        match error {
+            AccessError::Unauthorized => TrexError::InsufficientPermissions,
+            AccessError::NoPendingOwner => TrexError::NoPendingOwner,
+        }
+    }
+}
+
+// Version of the storage layout, contracts from before versioning report 0
+const SCHEMA_VERSION: u32 = 1;
+
+#[contracttype]
+#[derive(Clone)]
+pub enum TrexDataKey {
+    Initialized,
+    SchemaVersion,
+    Owner,
+    Gated,
+    Issuer,
+    Paused,
 }
 
 #[contract]
@@ -23,92 +56,308 @@ pub struct TrexContract;
 
 #[contractimpl]
 impl TrexContract {
-    pub fn get_initialized(env: Env) -> Result<bool, Error> {
+    pub fn get_initialized(env: Env) -> Result<bool, TrexError> {
         Ok(env
             .storage()
             .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false))
+            .get::<TrexDataKey, bool>(&TrexDataKey::Initialized)
+            .unwrap_or(false)
+            || is_legacy(&env))
     }
 
     pub fn initialize(
         env: Env,
         gated_address: Address,
         owner: Address,
-    ) -> Result<(), Error> {
-        let initialized = env
-            .storage()
-            .instance()
-            .get::<Symbol, bool>(&Symbol::new(&env, "initialized"))
-            .unwrap_or(false);
-
-        if initialized {
-            return Err(Error::AlreadyInitialized);
+    ) -> Result<(), TrexError> {
+        if Self::get_initialized(env.clone())? {
+            return Err(TrexError::AlreadyInitialized);
         }
 
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "initialized"), &true);
+            .set(&TrexDataKey::Initialized, &true);
+        env.storage()
+            .instance()
+            .set(&TrexDataKey::SchemaVersion, &SCHEMA_VERSION);
+        env.storage()
+            .instance()
+            .set(&TrexDataKey::Owner, &owner);
         env.storage()
             .instance()
-            .set(&symbol_short!("owner"), &owner);
+            .set(&TrexDataKey::Gated, &gated_address);
+
+        publish_event(
+            &env,
+            "initialized",
+            &env.current_contract_address(),
+            InitializedEvent { owner },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_schema_version(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get::<TrexDataKey, u32>(&TrexDataKey::SchemaVersion)
+            .unwrap_or(0)
+    }
+
+    // Moves a contract deployed before schema versioning to the typed layout.
+    // Must be authorized by the legacy owner.
+    pub fn migrate(env: Env) -> Result<(), TrexError> {
+        if !is_legacy(&env) {
+            return Err(if Self::get_schema_version(env.clone()) >= SCHEMA_VERSION {
+                TrexError::AlreadyMigrated
+            } else {
+                TrexError::NotInitialized
+            });
+        }
+
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&symbol_short!("owner"))
+            .ok_or(TrexError::NotInitialized)?;
+        owner.require_auth();
+
+        let gated: Address = env
+            .storage()
+            .instance()
+            .get(&symbol_short!("gated"))
+            .ok_or(TrexError::NotInitialized)?;
+
+        env.storage().instance().remove(&Symbol::new(&env, "initialized"));
+        env.storage().instance().remove(&symbol_short!("owner"));
+        env.storage().instance().remove(&symbol_short!("gated"));
+        env.storage().instance().set(&TrexDataKey::Initialized, &true);
+        env.storage().instance().set(&TrexDataKey::Owner, &owner);
+        env.storage().instance().set(&TrexDataKey::Gated, &gated);
+
+        if let Some(issuer) = env.storage().persistent().get::<Symbol, Address>(&symbol_short!("issuer")) {
+            env.storage().persistent().remove(&symbol_short!("issuer"));
+            env.storage().persistent().set(&TrexDataKey::Issuer, &issuer);
+        }
+
         env.storage()
             .instance()
-            .set(&symbol_short!("gated"), &gated_address);
+            .set(&TrexDataKey::SchemaVersion, &SCHEMA_VERSION);
 
+        publish_event(
+            &env,
+            "migrated",
+            &env.current_contract_address(),
+            MigratedEvent {
+                by: owner,
+                version: SCHEMA_VERSION,
+            },
+        );
         Ok(())
     }
-    pub fn transfer(env: Env, id: Address, from: Address, to: Address, amount: i128) -> Result<bool, Error> {
+
+    // Replaces the contract code, signed by the owner. The new code takes over
+    // once this invocation has finished.
+    pub fn upgrade(env: Env, wasm_hash: BytesN<32>) -> Result<(), TrexError> {
+        let owner = only_owner(&env)?;
+
+        env.deployer()
+            .update_current_contract_wasm(wasm_hash.clone());
+
+        publish_event(
+            &env,
+            "upgraded",
+            &env.current_contract_address(),
+            UpgradedEvent {
+                authority: owner,
+                wasm_hash,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn set_claim_issuer(env: Env, sender: Address, issuer: Address) -> Result<(), TrexError> {
+        require_role(&env, Role::PolicyAdmin, &sender)?;
+
+        env.storage().persistent().set(&TrexDataKey::Issuer, &issuer);
+
+        publish_event(
+            &env,
+            "claim_issuer_set",
+            &env.current_contract_address(),
+            ContractSetEvent { contract: issuer },
+        );
+
+        Ok(())
+    }
+
+    // Stops all transfers until an agent unpauses
+    pub fn pause(env: Env, sender: Address) -> Result<(), TrexError> {
+        require_role(&env, Role::Agent, &sender)?;
+
+        env.storage().instance().set(&TrexDataKey::Paused, &true);
+
+        publish_event(
+            &env,
+            "paused",
+            &env.current_contract_address(),
+            PauseEvent { sender },
+        );
+
+        Ok(())
+    }
+
+    pub fn unpause(env: Env, sender: Address) -> Result<(), TrexError> {
+        require_role(&env, Role::Agent, &sender)?;
+
+        env.storage().instance().remove(&TrexDataKey::Paused);
+
+        publish_event(
+            &env,
+            "unpaused",
+            &env.current_contract_address(),
+            PauseEvent { sender },
+        );
+
+        Ok(())
+    }
+
+    pub fn is_paused(env: Env) -> bool {
+        env.storage().instance().has(&TrexDataKey::Paused)
+    }
+
+    pub fn transfer(env: Env, id: Address, from: Address, to: Address, amount: i128) -> Result<bool, TrexError> {
+        if Self::is_paused(env.clone()) {
+            return Err(TrexError::Paused);
+        }
         let token_client = token::Client::new(&env, &id);
 
         let gated_address: Address = env
             .storage()
             .instance()
-            .get(&symbol_short!("gated"))
+            .get(&TrexDataKey::Gated)
             .unwrap();
 
         let issuer: Address = env
             .storage()
             .persistent()
-            .get(&symbol_short!("issuer"))
+            .get(&TrexDataKey::Issuer)
             .unwrap();
 
         let gated_client = gated::Client::new(&env, &gated_address);
         if !gated_client.validate_claim(&from, &issuer, &U256::from_u128(&env, 1)) {
-            return Err(Error::InsufficientPermissions);
+            return Err(TrexError::InsufficientPermissions);
         }
 
         token_client.transfer(&from, &to, &amount);
 
+        publish_event(
+            &env,
+            "transfer",
+            &from,
+            TransferEvent {
+                token: id,
+                to,
+                amount,
+            },
+        );
+
         return Ok(true);
       }
 
-    pub fn get_owner(env: Env) -> Address {
-        let owner: Address = env
-            .storage()
+    pub fn get_owner(env: Env) -> Result<Address, TrexError> {
+        env.storage()
             .instance()
-            .get(&symbol_short!("owner"))
-            .unwrap();
-        owner
+            .get(&TrexDataKey::Owner)
+            .ok_or(TrexError::NotInitialized)
+    }
+
+    // `owner` takes over once it calls `accept_ownership`
+    pub fn transfer_ownership(env: Env, owner: Address) -> Result<(), TrexError> {
+        let previous = only_owner(&env)?;
+        AccessControl::start_ownership_transfer(&env, previous, owner);
+
+        Ok(())
     }
 
-    pub fn set_owner(env: Env, owner: Address) {
-        only_owner(&env);
+    pub fn accept_ownership(env: Env) -> Result<(), TrexError> {
+        let previous = Self::get_owner(env.clone())?;
+        let owner = AccessControl::accept_ownership(&env)?;
 
         env.storage()
             .instance()
-            .set(&symbol_short!("owner"), &owner);
+            .set(&TrexDataKey::Owner, &owner);
+
+        publish_event(
+            &env,
+            "owner_changed",
+            &env.current_contract_address(),
+            OwnerChangedEvent { previous, owner },
+        );
+
+        Ok(())
     }
-}
 
+    pub fn get_pending_owner(env: Env) -> Result<Option<Address>, TrexError> {
+        Ok(AccessControl::pending_owner(&env))
+    }
 
-fn only_owner(env: &Env) -> Address {
-    let owner: Address = env
-        .storage()
-        .instance()
-        .get(&symbol_short!("owner"))
-        .unwrap();
+    // The owner grants any role, admins every role but Admin
+    pub fn grant_role(
+        env: Env,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), TrexError> {
+        let owner = Self::get_owner(env.clone())?;
+        AccessControl::grant_role(&env, &owner, sender, role, account)?;
+        Ok(())
+    }
+
+    pub fn revoke_role(
+        env: Env,
+        sender: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), TrexError> {
+        let owner = Self::get_owner(env.clone())?;
+        AccessControl::revoke_role(&env, &owner, sender, role, account)?;
+        Ok(())
+    }
+
+    pub fn renounce_role(env: Env, account: Address, role: Role) -> Result<(), TrexError> {
+        AccessControl::renounce_role(&env, account, role);
+        Ok(())
+    }
+
+    // The owner holds every role
+    pub fn has_role(env: Env, role: Role, account: Address) -> Result<bool, TrexError> {
+        let owner = Self::get_owner(env.clone())?;
+        Ok(AccessControl::has_role(&env, &owner, role, &account))
+    }
+}
+
+fn only_owner(env: &Env) -> Result<Address, TrexError> {
+    let owner = TrexContract::get_owner(env.clone())?;
     owner.require_auth();
 
-    owner
+    Ok(owner)
+}
+
+// Checks that `sender` holds `role`, which the owner always does, and authorized the call
+fn require_role(env: &Env, role: Role, sender: &Address) -> Result<(), TrexError> {
+    let owner = TrexContract::get_owner(env.clone())?;
+    Ok(AccessControl::require_role(env, &owner, role, sender)?)
+}
+
+// Initialized with the unversioned Symbol key layout
+fn is_legacy(env: &Env) -> bool {
+    !env.storage().instance().has(&TrexDataKey::SchemaVersion)
+        && env
+            .storage()
+            .instance()
+            .get::<Symbol, bool>(&Symbol::new(env, "initialized"))
+            .unwrap_or(false)
 }
+
+mod test;
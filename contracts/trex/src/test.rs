@@ -0,0 +1,228 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use soroban_sdk::{testutils::Address as _, Address, Env};
+
+mod trex {
+    soroban_sdk::contractimport!(file = "../../target/wasm32-unknown-unknown/release/trex.wasm");
+}
+
+fn setup(env: &Env) -> (TrexContractClient<'_>, Address) {
+    let trex_contract_id = env.register_contract(None, TrexContract);
+    let trex_client = TrexContractClient::new(env, &trex_contract_id);
+
+    let owner = Address::generate(env);
+    trex_client.initialize(&Address::generate(env), &owner);
+
+    (trex_client, owner)
+}
+
+#[test]
+fn test_initialize() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (trex_client, owner) = setup(&env);
+
+    assert!(trex_client.get_initialized());
+    assert_eq!(trex_client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(trex_client.get_owner(), owner);
+    assert_eq!(
+        trex_client.try_initialize(&Address::generate(&env), &owner),
+        Err(Ok(TrexError::AlreadyInitialized))
+    );
+}
+
+#[test]
+fn test_pause() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (trex_client, owner) = setup(&env);
+
+    // Only agents pause, and the owner holds every role
+    let agent = Address::generate(&env);
+    assert_eq!(
+        trex_client.try_pause(&agent),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+    trex_client.grant_role(&owner, &Role::Agent, &agent);
+    trex_client.pause(&agent);
+    assert_eq!(env.auths()[0].0, agent);
+    assert!(trex_client.is_paused());
+
+    // No transfer gets as far as the claim check while paused
+    let token = Address::generate(&env);
+    let from = Address::generate(&env);
+    let to = Address::generate(&env);
+    assert_eq!(
+        trex_client.try_transfer(&token, &from, &to, &1),
+        Err(Ok(TrexError::Paused))
+    );
+
+    assert_eq!(
+        trex_client.try_unpause(&Address::generate(&env)),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+    trex_client.unpause(&owner);
+    assert!(!trex_client.is_paused());
+}
+
+#[test]
+fn test_roles() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (trex_client, owner) = setup(&env);
+
+    // Only the owner grants Admin, admins grant the other roles
+    let admin = Address::generate(&env);
+    assert_eq!(
+        trex_client.try_grant_role(&admin, &Role::Admin, &admin),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+    trex_client.grant_role(&owner, &Role::Admin, &admin);
+    let policy_admin = Address::generate(&env);
+    assert_eq!(
+        trex_client.try_grant_role(&admin, &Role::Admin, &policy_admin),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+    trex_client.grant_role(&admin, &Role::PolicyAdmin, &policy_admin);
+    assert!(trex_client.has_role(&Role::PolicyAdmin, &policy_admin));
+    assert!(trex_client.has_role(&Role::PolicyAdmin, &owner));
+
+    // The issuer is set by a policy admin, not by an admin
+    let issuer = Address::generate(&env);
+    assert_eq!(
+        trex_client.try_set_claim_issuer(&admin, &issuer),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+    trex_client.set_claim_issuer(&policy_admin, &issuer);
+    assert_eq!(env.auths()[0].0, policy_admin);
+
+    trex_client.revoke_role(&admin, &Role::PolicyAdmin, &policy_admin);
+    assert!(!trex_client.has_role(&Role::PolicyAdmin, &policy_admin));
+    assert_eq!(
+        trex_client.try_set_claim_issuer(&policy_admin, &issuer),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+
+    trex_client.renounce_role(&admin, &Role::Admin);
+    assert!(!trex_client.has_role(&Role::Admin, &admin));
+}
+
+#[test]
+fn test_ownership_transfer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (trex_client, owner) = setup(&env);
+
+    assert_eq!(
+        trex_client.try_accept_ownership(),
+        Err(Ok(TrexError::NoPendingOwner))
+    );
+
+    // Nothing changes until the new owner accepts
+    let new_owner = Address::generate(&env);
+    trex_client.transfer_ownership(&new_owner);
+    assert_eq!(env.auths()[0].0, owner);
+    assert_eq!(trex_client.get_pending_owner(), Some(new_owner.clone()));
+    assert_eq!(trex_client.get_owner(), owner);
+
+    trex_client.accept_ownership();
+    assert_eq!(env.auths()[0].0, new_owner);
+    assert_eq!(trex_client.get_owner(), new_owner);
+    assert_eq!(trex_client.get_pending_owner(), None);
+
+    // The roles the owner held move with ownership
+    assert!(trex_client.has_role(&Role::Agent, &new_owner));
+    assert!(!trex_client.has_role(&Role::Agent, &owner));
+    assert_eq!(
+        trex_client.try_pause(&owner),
+        Err(Ok(TrexError::InsufficientPermissions))
+    );
+}
+
+#[test]
+fn test_migrate_legacy_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let trex_contract_id = env.register_contract(None, TrexContract);
+    let trex_client = TrexContractClient::new(&env, &trex_contract_id);
+
+    let owner = Address::generate(&env);
+    let gated = Address::generate(&env);
+    let issuer = Address::generate(&env);
+
+    // Storage as written before schema versioning, the gated address missing
+    env.as_contract(&trex_contract_id, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "initialized"), &true);
+        env.storage()
+            .instance()
+            .set(&symbol_short!("owner"), &owner);
+        env.storage()
+            .persistent()
+            .set(&symbol_short!("issuer"), &issuer);
+    });
+
+    assert!(trex_client.get_initialized());
+    assert_eq!(trex_client.get_schema_version(), 0);
+    assert_eq!(
+        trex_client.try_migrate(),
+        Err(Ok(TrexError::NotInitialized))
+    );
+
+    env.as_contract(&trex_contract_id, || {
+        env.storage()
+            .instance()
+            .set(&symbol_short!("gated"), &gated);
+    });
+    trex_client.migrate();
+    assert_eq!(env.auths()[0].0, owner);
+
+    assert_eq!(trex_client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(trex_client.get_owner(), owner);
+    env.as_contract(&trex_contract_id, || {
+        assert_eq!(
+            env.storage().instance().get(&TrexDataKey::Gated),
+            Some(gated.clone())
+        );
+        assert_eq!(
+            env.storage().persistent().get(&TrexDataKey::Issuer),
+            Some(issuer.clone())
+        );
+    });
+    assert_eq!(
+        trex_client.try_migrate(),
+        Err(Ok(TrexError::AlreadyMigrated))
+    );
+}
+
+#[test]
+fn test_upgrade() {
+    let env = Env::default();
+    env.mock_all_auths();
+    // Running the contract as wasm exceeds a transaction's budget
+    env.budget().reset_unlimited();
+
+    let trex_contract_id = env.register_contract_wasm(None, trex::WASM);
+    let trex_client = trex::Client::new(&env, &trex_contract_id);
+
+    let owner = Address::generate(&env);
+    trex_client.initialize(&Address::generate(&env), &owner);
+
+    // Only the owner replaces the code, the storage stays as it is
+    let wasm_hash = env.deployer().upload_contract_wasm(trex::WASM);
+    trex_client.upgrade(&wasm_hash);
+    assert_eq!(env.auths()[0].0, owner);
+    assert_eq!(trex_client.get_owner(), owner);
+    assert_eq!(
+        trex_client.try_migrate(),
+        Err(Ok(trex::TrexError::AlreadyMigrated))
+    );
+}
@@ -0,0 +1,291 @@
+// Building blocks of the `onchainid` command. Everything here works offline:
+// hashes and signatures come from onchainid-signer, and contract calls are
+// rendered as `soroban contract invoke` command lines for an operator to run.
+
+use std::path::Path;
+
+use onchainid_signer::strkey;
+use serde::Deserialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Signer(#[from] onchainid_signer::Error),
+    #[error("invalid hex for {0}: expected {1} bytes")]
+    InvalidHex(&'static str, usize),
+    #[error("no `{0}` contract in the config file")]
+    MissingContract(&'static str),
+    #[error("cannot read config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid config: {0}")]
+    Config(#[from] toml::de::Error),
+}
+
+// ERC-735 scheme for claims carrying an issuer signature
+pub const SIGNED_SCHEME: u32 = 1;
+
+// Where and as whom invocations run. Loaded from a TOML file:
+//
+//   network = "testnet"
+//   source = "issuer"
+//
+//   [contracts]
+//   factory = "C..."
+//   claim_issuer = "C..."
+//   gated = "C..."
+//   identity_wasm_hash = "<hex>"
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct Config {
+    pub network: String,
+    pub source: String,
+    #[serde(default)]
+    pub contracts: Contracts,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct Contracts {
+    pub factory: Option<String>,
+    pub claim_issuer: Option<String>,
+    pub gated: Option<String>,
+    pub identity_wasm_hash: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let config: Config = toml::from_str(text)?;
+        for contract in [
+            &config.contracts.factory,
+            &config.contracts.claim_issuer,
+            &config.contracts.gated,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            strkey::parse_address(contract)?;
+        }
+        if let Some(hash) = &config.contracts.identity_wasm_hash {
+            parse_hex32("identity_wasm_hash", hash)?;
+        }
+        Ok(config)
+    }
+}
+
+fn required(value: &Option<String>, name: &'static str) -> Result<String, Error> {
+    value.clone().ok_or(Error::MissingContract(name))
+}
+
+pub fn parse_hex(name: &'static str, value: &str, len: usize) -> Result<Vec<u8>, Error> {
+    let bytes =
+        hex::decode(value.trim_start_matches("0x")).map_err(|_| Error::InvalidHex(name, len))?;
+    if bytes.len() != len {
+        return Err(Error::InvalidHex(name, len));
+    }
+    Ok(bytes)
+}
+
+pub fn parse_hex32(name: &'static str, value: &str) -> Result<[u8; 32], Error> {
+    Ok(parse_hex(name, value, 32)?.try_into().unwrap())
+}
+
+fn address(value: &str) -> Result<String, Error> {
+    strkey::parse_address(value)?;
+    Ok(value.to_string())
+}
+
+// One `soroban contract invoke` call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub contract_id: String,
+    pub source: String,
+    pub network: String,
+    pub function: String,
+    pub args: Vec<(String, String)>,
+}
+
+impl Invocation {
+    fn new(config: &Config, contract_id: String, function: &str) -> Self {
+        Self {
+            contract_id,
+            source: config.source.clone(),
+            network: config.network.clone(),
+            function: function.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    fn arg(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.args.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv: Vec<String> = [
+            "soroban",
+            "contract",
+            "invoke",
+            "--id",
+            &self.contract_id,
+            "--source",
+            &self.source,
+            "--network",
+            &self.network,
+            "--",
+            &self.function,
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        for (name, value) in &self.args {
+            argv.push(format!("--{}", name));
+            argv.push(value.clone());
+        }
+        argv
+    }
+
+    pub fn to_shell(&self) -> String {
+        self.argv()
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+// factory.set_identity_wasm, approving the configured identity wasm hash as the
+// implementation create_identity deploys. `sender` is the owner or an admin.
+pub fn set_identity_wasm(config: &Config, sender: &str) -> Result<Invocation, Error> {
+    Ok(Invocation::new(
+        config,
+        required(&config.contracts.factory, "factory")?,
+        "set_identity_wasm",
+    )
+    .arg("sender", address(sender)?)
+    .arg(
+        "wasm_hash",
+        required(&config.contracts.identity_wasm_hash, "identity_wasm_hash")?,
+    ))
+}
+
+// factory.create_identity, sent by the owner or an identity creator. The factory
+// deploys `wallet`'s `index`-th identity with both `sender` and `wallet` as its
+// management keys.
+pub fn create_identity(
+    config: &Config,
+    sender: &str,
+    wallet: &str,
+    index: u32,
+) -> Result<Invocation, Error> {
+    Ok(Invocation::new(
+        config,
+        required(&config.contracts.factory, "factory")?,
+        "create_identity",
+    )
+    .arg("sender", address(sender)?)
+    .arg("wallet", address(wallet)?)
+    .arg("index", index.to_string()))
+}
+
+// factory.predict_identity_address, where `create_identity` deploys `wallet`'s
+// `index`-th identity. The factory derives the deploy salt itself.
+pub fn predict_identity_address(
+    config: &Config,
+    wallet: &str,
+    index: u32,
+) -> Result<Invocation, Error> {
+    Ok(Invocation::new(
+        config,
+        required(&config.contracts.factory, "factory")?,
+        "predict_identity_address",
+    )
+    .arg("wallet", address(wallet)?)
+    .arg("index", index.to_string()))
+}
+
+// add_key on an identity or on the claim issuer
+pub fn add_key(
+    config: &Config,
+    contract: &str,
+    manager: &str,
+    key: &str,
+    purpose: u32,
+    key_type: u32,
+) -> Result<Invocation, Error> {
+    Ok(Invocation::new(config, address(contract)?, "add_key")
+        .arg("manager", address(manager)?)
+        .arg("key", address(key)?)
+        .arg("purpose", purpose.to_string())
+        .arg("key_type", key_type.to_string()))
+}
+
+// A signed claim added to `identity` by one of its claim keys
+pub struct SignedClaim<'a> {
+    pub identity: &'a str,
+    pub topic: u128,
+    pub issuer_wallet: &'a str,
+    pub issuer: &'a str,
+    pub signature: &'a [u8; 64],
+    pub data: &'a [u8],
+    pub uri: &'a [u8],
+}
+
+pub fn add_claim(config: &Config, sender: &str, claim: &SignedClaim) -> Result<Invocation, Error> {
+    Ok(
+        Invocation::new(config, address(claim.identity)?, "add_claim")
+            .arg("sender", address(sender)?)
+            .arg("topic", claim.topic.to_string())
+            .arg("scheme", SIGNED_SCHEME.to_string())
+            .arg("issuer_wallet", address(claim.issuer_wallet)?)
+            .arg("issuer", address(claim.issuer)?)
+            .arg("signature", hex::encode(claim.signature))
+            .arg("data", hex::encode(claim.data))
+            .arg("uri", hex::encode(claim.uri)),
+    )
+}
+
+// claim_issuer.revoke_claim for a claim held by `identity`
+pub fn revoke_claim(
+    config: &Config,
+    sender: &str,
+    identity: &str,
+    claim_id: &[u8; 32],
+) -> Result<Invocation, Error> {
+    Ok(Invocation::new(
+        config,
+        required(&config.contracts.claim_issuer, "claim_issuer")?,
+        "revoke_claim",
+    )
+    .arg("sender", address(sender)?)
+    .arg("contract", address(identity)?)
+    .arg("claim_id", hex::encode(claim_id)))
+}
+
+// gated.validate_claim for `sender`'s identity
+pub fn validate_claim(config: &Config, sender: &str, topic: u128) -> Result<Invocation, Error> {
+    Ok(Invocation::new(
+        config,
+        required(&config.contracts.gated, "gated")?,
+        "validate_claim",
+    )
+    .arg("sender", address(sender)?)
+    .arg(
+        "issuer",
+        required(&config.contracts.claim_issuer, "claim_issuer")?,
+    )
+    .arg("required_topic", topic.to_string()))
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use onchainid_cli::{
+    add_claim, add_key, create_identity, parse_hex, parse_hex32, predict_identity_address,
+    revoke_claim, set_identity_wasm, validate_claim, Config, Error, Invocation, SignedClaim,
+};
+use onchainid_signer::{claim_id, key_hash, topic_from_u128, ClaimSigner};
+
+#[derive(Parser)]
+#[command(name = "onchainid", about = "0xAuth identity and claim administration")]
+struct Cli {
+    /// Network, source account and contract ids for the invocation commands
+    #[arg(long, global = true, default_value = "onchainid.toml")]
+    config: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the id a claim by `issuer` on `topic` is stored under
+    ClaimId {
+        #[arg(long)]
+        issuer: String,
+        #[arg(long)]
+        topic: u128,
+    },
+    /// Print the hash identities store a key under
+    KeyHash {
+        #[arg(long)]
+        address: String,
+    },
+    /// Sign a claim with the issuer wallet secret read from `secret_env`
+    SignClaim {
+        #[arg(long)]
+        identity: String,
+        #[arg(long)]
+        topic: u128,
+        #[arg(long, default_value = "")]
+        data: String,
+        #[arg(long, default_value = "ONCHAINID_SECRET")]
+        secret_env: String,
+    },
+    /// Print the invocation approving `identity_wasm_hash` on the factory
+    SetIdentityWasm {
+        #[arg(long)]
+        sender: String,
+    },
+    /// Print the invocation deploying `wallet`'s `index`-th identity
+    CreateIdentity {
+        #[arg(long)]
+        sender: String,
+        #[arg(long)]
+        wallet: String,
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Print the invocation predicting where `wallet`'s `index`-th identity deploys
+    PredictIdentityAddress {
+        #[arg(long)]
+        wallet: String,
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Print the invocation adding a key to an identity or the claim issuer
+    AddKey {
+        #[arg(long)]
+        contract: String,
+        #[arg(long)]
+        manager: String,
+        #[arg(long)]
+        key: String,
+        #[arg(long)]
+        purpose: u32,
+        #[arg(long, default_value_t = 1)]
+        key_type: u32,
+    },
+    /// Sign a claim and print the invocation adding it to an identity
+    AddClaim {
+        #[arg(long)]
+        identity: String,
+        #[arg(long)]
+        sender: String,
+        #[arg(long)]
+        topic: u128,
+        /// Defaults to the claim issuer from the config file
+        #[arg(long)]
+        issuer: Option<String>,
+        #[arg(long, default_value = "")]
+        data: String,
+        #[arg(long, default_value = "")]
+        uri: String,
+        #[arg(long, default_value = "ONCHAINID_SECRET")]
+        secret_env: String,
+    },
+    /// Print the invocation revoking a claim through the claim issuer
+    RevokeClaim {
+        #[arg(long)]
+        sender: String,
+        #[arg(long)]
+        identity: String,
+        #[arg(long)]
+        claim_id: String,
+    },
+    /// Print the invocation checking a wallet's claim through the gated contract
+    ValidateClaim {
+        #[arg(long)]
+        sender: String,
+        #[arg(long)]
+        topic: u128,
+    },
+}
+
+fn hex_bytes(name: &'static str, value: &str) -> Result<Vec<u8>, Error> {
+    let len = value.trim_start_matches("0x").len() / 2;
+    parse_hex(name, value, len)
+}
+
+fn signer(secret_env: &str) -> Result<ClaimSigner, Error> {
+    let seed = std::env::var(secret_env).unwrap_or_default();
+    Ok(ClaimSigner::from_secret_seed(&seed)?)
+}
+
+fn run(cli: Cli) -> Result<String, Error> {
+    let invocation: Invocation = match cli.command {
+        Command::ClaimId { issuer, topic } => {
+            return Ok(hex::encode(claim_id(&issuer, &topic_from_u128(topic))?));
+        }
+        Command::KeyHash { address } => return Ok(hex::encode(key_hash(&address)?)),
+        Command::SignClaim {
+            identity,
+            topic,
+            data,
+            secret_env,
+        } => {
+            let data = hex_bytes("data", &data)?;
+            let signature =
+                signer(&secret_env)?.sign_claim(&identity, &topic_from_u128(topic), &data)?;
+            return Ok(hex::encode(signature));
+        }
+        Command::SetIdentityWasm { sender } => {
+            set_identity_wasm(&Config::load(&cli.config)?, &sender)?
+        }
+        Command::CreateIdentity {
+            sender,
+            wallet,
+            index,
+        } => create_identity(&Config::load(&cli.config)?, &sender, &wallet, index)?,
+        Command::PredictIdentityAddress { wallet, index } => {
+            predict_identity_address(&Config::load(&cli.config)?, &wallet, index)?
+        }
+        Command::AddKey {
+            contract,
+            manager,
+            key,
+            purpose,
+            key_type,
+        } => add_key(
+            &Config::load(&cli.config)?,
+            &contract,
+            &manager,
+            &key,
+            purpose,
+            key_type,
+        )?,
+        Command::AddClaim {
+            identity,
+            sender,
+            topic,
+            issuer,
+            data,
+            uri,
+            secret_env,
+        } => {
+            let config = Config::load(&cli.config)?;
+            let issuer = match issuer.or_else(|| config.contracts.claim_issuer.clone()) {
+                Some(issuer) => issuer,
+                None => return Err(Error::MissingContract("claim_issuer")),
+            };
+            let signer = signer(&secret_env)?;
+            let data = hex_bytes("data", &data)?;
+            let signature = signer.sign_claim(&identity, &topic_from_u128(topic), &data)?;
+            let claim = SignedClaim {
+                identity: &identity,
+                topic,
+                issuer_wallet: &signer.account_id(),
+                issuer: &issuer,
+                signature: &signature,
+                data: &data,
+                uri: &hex_bytes("uri", &uri)?,
+            };
+            add_claim(&config, &sender, &claim)?
+        }
+        Command::RevokeClaim {
+            sender,
+            identity,
+            claim_id,
+        } => {
+            let claim_id = parse_hex32("claim_id", &claim_id)?;
+            revoke_claim(&Config::load(&cli.config)?, &sender, &identity, &claim_id)?
+        }
+        Command::ValidateClaim { sender, topic } => {
+            validate_claim(&Config::load(&cli.config)?, &sender, topic)?
+        }
+    };
+    Ok(invocation.to_shell())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
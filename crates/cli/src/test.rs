@@ -0,0 +1,178 @@
+use super::*;
+use onchainid_signer::{topic_from_u128, ClaimSigner};
+
+const CONFIG: &str = r#"
+network = "testnet"
+source = "issuer"
+
+[contracts]
+factory = "CDRHGLONE56BNO7DOTYYG64V2D75XWCJTVWGQRDS5GBFV5UCPR2MSOTX"
+claim_issuer = "CAV3THXYNWYNFZYUF43WJSFBQKRFQEXPFTM6A3FO6LXJ5MA3XA2OBR42"
+gated = "CA7ZX2QBT7VFPLAL6G3ITILXVEULKZPXXYZ6M5HALTCIMTB4WEWI2GCK"
+identity_wasm_hash = "3033769358c8b90be7b9f827424a7dbf59f487703f585e7b9e6a6fde734237e2"
+"#;
+
+const WALLET: &str = "GCKDZSO5Z2XLD4LJSA67ER3YSRBHYGRZN2PTANPK25THWKB72T3S5XSB";
+const IDENTITY: &str = "CA7ZX2QBT7VFPLAL6G3ITILXVEULKZPXXYZ6M5HALTCIMTB4WEWI2GCK";
+
+fn config() -> Config {
+    Config::parse(CONFIG).unwrap()
+}
+
+#[test]
+fn test_config() {
+    let config = config();
+    assert_eq!(config.network, "testnet");
+    assert_eq!(config.source, "issuer");
+
+    let partial = Config::parse("network = \"testnet\"\nsource = \"issuer\"\n").unwrap();
+    assert_eq!(partial.contracts, Contracts::default());
+    assert!(matches!(
+        validate_claim(&partial, WALLET, 1),
+        Err(Error::MissingContract("gated"))
+    ));
+
+    let bad_contract = CONFIG.replace("CAV3THXYNW", "GAV3THXYNW");
+    assert!(matches!(
+        Config::parse(&bad_contract),
+        Err(Error::Signer(_))
+    ));
+    let bad_hash = CONFIG.replace("3033769358c8", "3033");
+    assert!(matches!(
+        Config::parse(&bad_hash),
+        Err(Error::InvalidHex("identity_wasm_hash", 32))
+    ));
+}
+
+#[test]
+fn test_create_identity() {
+    let invocation = create_identity(&config(), WALLET, WALLET, 1).unwrap();
+    assert_eq!(
+        invocation.to_shell(),
+        format!(
+            "soroban contract invoke --id CDRHGLONE56BNO7DOTYYG64V2D75XWCJTVWGQRDS5GBFV5UCPR2MSOTX \
+             --source issuer --network testnet -- create_identity \
+             --sender {WALLET} --wallet {WALLET} --index 1"
+        )
+    );
+    assert_eq!(
+        set_identity_wasm(&config(), WALLET).unwrap().to_shell(),
+        format!(
+            "soroban contract invoke --id CDRHGLONE56BNO7DOTYYG64V2D75XWCJTVWGQRDS5GBFV5UCPR2MSOTX \
+             --source issuer --network testnet -- set_identity_wasm \
+             --sender {WALLET} \
+             --wasm_hash 3033769358c8b90be7b9f827424a7dbf59f487703f585e7b9e6a6fde734237e2"
+        )
+    );
+
+    assert_eq!(
+        predict_identity_address(&config(), WALLET, 1)
+            .unwrap()
+            .to_shell(),
+        format!(
+            "soroban contract invoke --id CDRHGLONE56BNO7DOTYYG64V2D75XWCJTVWGQRDS5GBFV5UCPR2MSOTX \
+             --source issuer --network testnet -- predict_identity_address \
+             --wallet {WALLET} --index 1"
+        )
+    );
+
+    assert!(matches!(
+        create_identity(&config(), WALLET, "GNOTANADDRESS", 0),
+        Err(Error::Signer(_))
+    ));
+}
+
+#[test]
+fn test_add_key() {
+    let invocation = add_key(&config(), IDENTITY, WALLET, WALLET, 3, 1).unwrap();
+    assert_eq!(invocation.contract_id, IDENTITY);
+    assert_eq!(
+        invocation.argv()[10..],
+        [
+            "add_key",
+            "--manager",
+            WALLET,
+            "--key",
+            WALLET,
+            "--purpose",
+            "3",
+            "--key_type",
+            "1"
+        ]
+    );
+}
+
+#[test]
+fn test_add_claim() {
+    let config = config();
+    let signer = ClaimSigner::from_bytes(&[1u8; 32]);
+    let issuer = config.contracts.claim_issuer.clone().unwrap();
+    let signature = signer
+        .sign_claim(IDENTITY, &topic_from_u128(6), b"data")
+        .unwrap();
+
+    let claim = SignedClaim {
+        identity: IDENTITY,
+        topic: 6,
+        issuer_wallet: &signer.account_id(),
+        issuer: &issuer,
+        signature: &signature,
+        data: b"data",
+        uri: b"",
+    };
+    let invocation = add_claim(&config, WALLET, &claim).unwrap();
+    let args: Vec<_> = invocation
+        .args
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    assert_eq!(
+        args,
+        [
+            ("sender", WALLET),
+            ("topic", "6"),
+            ("scheme", "1"),
+            ("issuer_wallet", signer.account_id().as_str()),
+            ("issuer", issuer.as_str()),
+            ("signature", hex::encode(signature).as_str()),
+            ("data", "64617461"),
+            ("uri", ""),
+        ]
+    );
+    assert!(invocation.to_shell().ends_with("--uri ''"));
+}
+
+#[test]
+fn test_revoke_and_validate_claim() {
+    let config = config();
+    let claim_id = [0xab; 32];
+
+    let revoke = revoke_claim(&config, WALLET, IDENTITY, &claim_id).unwrap();
+    assert_eq!(
+        revoke.contract_id,
+        config.contracts.claim_issuer.clone().unwrap()
+    );
+    assert_eq!(
+        revoke.args[2],
+        ("claim_id".to_string(), hex::encode(claim_id))
+    );
+
+    let validate = validate_claim(&config, WALLET, u128::MAX).unwrap();
+    assert_eq!(
+        validate.contract_id,
+        config.contracts.gated.clone().unwrap()
+    );
+    assert_eq!(
+        validate.args[2],
+        ("required_topic".to_string(), u128::MAX.to_string())
+    );
+
+    assert_eq!(
+        parse_hex32("claim_id", &format!("0x{}", hex::encode(claim_id))).unwrap(),
+        claim_id
+    );
+    assert!(matches!(
+        parse_hex32("claim_id", "abcd"),
+        Err(Error::InvalidHex("claim_id", 32))
+    ));
+}
@@ -0,0 +1,262 @@
+// Decoding of the events listed in onchainid_common::events. Only the ones
+// that change identity, key, claim or revocation state are surfaced.
+
+use onchainid_signer::{strkey, Topic};
+use serde::{Deserialize, Serialize};
+use stellar_xdr::curr::{Limits, ReadXdr, ScMapEntry, ScVal};
+
+use crate::Error;
+
+const EVENT_NAMESPACE: &str = "0xauth";
+const EVENT_VERSION: &str = "v1";
+
+// A contract event as returned by the RPC `getEvents` method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawEvent {
+    pub id: String,
+    pub ledger: u32,
+    pub contract_id: String,
+    // Base64 XDR ScVals
+    pub topic: Vec<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimData {
+    pub topic: Topic,
+    pub scheme: Topic,
+    pub issuer: String,
+    pub issuer_wallet: String,
+    pub signature: Vec<u8>,
+    pub data: Vec<u8>,
+    pub uri: Vec<u8>,
+}
+
+// `contract` is the emitting contract in every variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    // Key holders register their first management key with this one
+    Initialized {
+        contract: String,
+        owner: String,
+    },
+    IdentityCreated {
+        contract: String,
+        identity: String,
+        wallet: String,
+    },
+    TokenIdentityCreated {
+        contract: String,
+        identity: String,
+        token: String,
+    },
+    WalletLinked {
+        contract: String,
+        identity: String,
+        wallet: String,
+    },
+    WalletUnlinked {
+        contract: String,
+        identity: String,
+        wallet: String,
+    },
+    KeyAdded {
+        contract: String,
+        key: String,
+        purpose: u32,
+    },
+    KeyRemoved {
+        contract: String,
+        key: String,
+        purpose: u32,
+    },
+    ClaimAdded {
+        contract: String,
+        claim_id: [u8; 32],
+        claim: ClaimData,
+    },
+    ClaimRemoved {
+        contract: String,
+        claim_id: [u8; 32],
+    },
+    ClaimIssued {
+        contract: String,
+        identity: String,
+        claim_id: [u8; 32],
+        status_index: u32,
+    },
+    ClaimRevoked {
+        contract: String,
+        identity: String,
+        claim_id: [u8; 32],
+    },
+    StatusRevoked {
+        contract: String,
+        index: u32,
+    },
+}
+
+impl RawEvent {
+    // None for events from other namespaces and 0xAuth events the index
+    // does not depend on
+    pub fn decode(&self) -> Result<Option<Event>, Error> {
+        let topics = self
+            .topic
+            .iter()
+            .map(|topic| ScVal::from_xdr_base64(topic, Limits::none()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if topics.len() != 4 || symbol(&topics[0]).ok() != Some(EVENT_NAMESPACE) {
+            return Ok(None);
+        }
+        let version = symbol(&topics[1])?;
+        if version != EVENT_VERSION {
+            return Err(Error::UnsupportedVersion(version.to_string()));
+        }
+
+        let contract = self.contract_id.clone();
+        let subject = address(&topics[3])?;
+        let value = ScVal::from_xdr_base64(&self.value, Limits::none())?;
+        let data = Fields(&value);
+
+        let event = match symbol(&topics[2])? {
+            "initialized" => Event::Initialized {
+                contract,
+                owner: address(data.get("owner")?)?,
+            },
+            "identity_created" => Event::IdentityCreated {
+                contract,
+                identity: subject,
+                wallet: address(data.get("wallet")?)?,
+            },
+            "token_identity_created" => Event::TokenIdentityCreated {
+                contract,
+                identity: subject,
+                token: address(data.get("token")?)?,
+            },
+            "wallet_linked" => Event::WalletLinked {
+                contract,
+                identity: subject,
+                wallet: address(data.get("wallet")?)?,
+            },
+            "wallet_unlinked" => Event::WalletUnlinked {
+                contract,
+                identity: subject,
+                wallet: address(data.get("wallet")?)?,
+            },
+            "key_added" => Event::KeyAdded {
+                contract,
+                key: address(data.get("key")?)?,
+                purpose: u32_of(data.get("purpose")?)?,
+            },
+            "key_removed" => Event::KeyRemoved {
+                contract,
+                key: address(data.get("key")?)?,
+                purpose: u32_of(data.get("purpose")?)?,
+            },
+            "claim_added" => {
+                let claim = Fields(data.get("claim")?);
+                Event::ClaimAdded {
+                    contract,
+                    claim_id: bytes32(data.get("claim_id")?)?,
+                    claim: ClaimData {
+                        topic: u256(claim.get("topic")?)?,
+                        scheme: u256(claim.get("scheme")?)?,
+                        issuer: address(claim.get("issuer")?)?,
+                        issuer_wallet: address(claim.get("issuer_wallet")?)?,
+                        signature: bytes(claim.get("signature")?)?,
+                        data: bytes(claim.get("data")?)?,
+                        uri: bytes(claim.get("uri")?)?,
+                    },
+                }
+            }
+            "claim_removed" => Event::ClaimRemoved {
+                contract,
+                claim_id: bytes32(data.get("claim_id")?)?,
+            },
+            "claim_issued" => Event::ClaimIssued {
+                contract,
+                identity: subject,
+                claim_id: bytes32(data.get("claim_id")?)?,
+                status_index: u32_of(data.get("status_index")?)?,
+            },
+            "claim_revoked" => Event::ClaimRevoked {
+                contract,
+                identity: subject,
+                claim_id: bytes32(data.get("claim_id")?)?,
+            },
+            "status_revoked" => Event::StatusRevoked {
+                contract,
+                index: u32_of(data.get("index")?)?,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+}
+
+// A #[contracttype] struct, encoded as a map keyed by field name
+struct Fields<'a>(&'a ScVal);
+
+impl<'a> Fields<'a> {
+    fn get(&self, name: &'static str) -> Result<&'a ScVal, Error> {
+        let ScVal::Map(Some(map)) = self.0 else {
+            return Err(Error::Malformed("event data is not a struct"));
+        };
+        map.iter()
+            .find(|ScMapEntry { key, .. }| symbol(key).ok() == Some(name))
+            .map(|entry| &entry.val)
+            .ok_or(Error::Malformed(name))
+    }
+}
+
+fn symbol(value: &ScVal) -> Result<&str, Error> {
+    match value {
+        ScVal::Symbol(symbol) => {
+            std::str::from_utf8(symbol.as_slice()).map_err(|_| Error::Malformed("symbol"))
+        }
+        _ => Err(Error::Malformed("symbol")),
+    }
+}
+
+fn address(value: &ScVal) -> Result<String, Error> {
+    match value {
+        ScVal::Address(address) => Ok(strkey::format_address(address)),
+        _ => Err(Error::Malformed("address")),
+    }
+}
+
+fn u32_of(value: &ScVal) -> Result<u32, Error> {
+    match value {
+        ScVal::U32(value) => Ok(*value),
+        _ => Err(Error::Malformed("u32")),
+    }
+}
+
+fn u256(value: &ScVal) -> Result<Topic, Error> {
+    let ScVal::U256(parts) = value else {
+        return Err(Error::Malformed("u256"));
+    };
+    let mut topic = [0u8; 32];
+    for (i, part) in [parts.hi_hi, parts.hi_lo, parts.lo_hi, parts.lo_lo]
+        .iter()
+        .enumerate()
+    {
+        topic[i * 8..(i + 1) * 8].copy_from_slice(&part.to_be_bytes());
+    }
+    Ok(topic)
+}
+
+fn bytes(value: &ScVal) -> Result<Vec<u8>, Error> {
+    match value {
+        ScVal::Bytes(bytes) => Ok(bytes.to_vec()),
+        _ => Err(Error::Malformed("bytes")),
+    }
+}
+
+fn bytes32(value: &ScVal) -> Result<[u8; 32], Error> {
+    bytes(value)?
+        .try_into()
+        .map_err(|_| Error::Malformed("bytes32"))
+}
@@ -0,0 +1,102 @@
+// Off-chain index of 0xAuth state, rebuilt from contract events.
+//
+// Feed it the events of the factory, identity and claim issuer contracts in
+// ledger order and query wallets, keys, claims and revocations without reading
+// contract storage. Snapshots record the last applied event id, so a
+// stream can be replayed from any earlier point after a restart.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+pub mod event;
+pub mod state;
+
+pub use event::{ClaimData, Event, RawEvent};
+pub use state::{ClaimRecord, IdentityRecord, IssuerRecord, State};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("xdr decoding failed: {0}")]
+    Xdr(#[from] stellar_xdr::curr::Error),
+    #[error("malformed event: {0}")]
+    Malformed(&'static str),
+    #[error("unsupported event version: {0}")]
+    UnsupportedVersion(String),
+    #[error("cannot access snapshot: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid snapshot: {0}")]
+    Snapshot(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Indexer {
+    // Only identities created by these factories are indexed
+    pub factories: BTreeSet<String>,
+    // Id of the last applied event, RPC ids sort in ledger order
+    pub cursor: Option<String>,
+    pub state: State,
+}
+
+impl Indexer {
+    pub fn new(factories: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            factories: factories.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    // Applies one event. Returns false when it was skipped: already applied,
+    // not an 0xAuth event, or a factory event from an untrusted contract.
+    pub fn ingest(&mut self, raw: &RawEvent) -> Result<bool, Error> {
+        if self.cursor.as_ref().is_some_and(|cursor| raw.id <= *cursor) {
+            return Ok(false);
+        }
+        let event = raw.decode()?;
+        self.cursor = Some(raw.id.clone());
+
+        let Some(event) = event else {
+            return Ok(false);
+        };
+        let from_factory = matches!(
+            event,
+            Event::IdentityCreated { .. }
+                | Event::TokenIdentityCreated { .. }
+                | Event::WalletLinked { .. }
+                | Event::WalletUnlinked { .. }
+        );
+        if from_factory && !self.factories.contains(&raw.contract_id) {
+            return Ok(false);
+        }
+
+        self.state.apply(event);
+        Ok(true)
+    }
+
+    pub fn ingest_all<'a>(
+        &mut self,
+        events: impl IntoIterator<Item = &'a RawEvent>,
+    ) -> Result<usize, Error> {
+        let mut applied = 0;
+        for raw in events {
+            applied += self.ingest(raw)? as usize;
+        }
+        Ok(applied)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        // Write then rename so a crash never leaves a truncated snapshot
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod test;
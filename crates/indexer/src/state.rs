@@ -0,0 +1,244 @@
+// Materialized view of identities, keys, claims and revocations.
+// Hashes, topics and byte strings are kept hex encoded so snapshots stay readable.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use onchainid_signer::Topic;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{ClaimData, Event};
+
+// KeyPurpose::Management
+const MANAGEMENT_PURPOSE: u32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimRecord {
+    pub topic: String,
+    pub scheme: String,
+    pub issuer: String,
+    pub issuer_wallet: String,
+    pub signature: String,
+    pub data: String,
+    pub uri: String,
+    // Set for claims written by an issuer contract through issue_claim
+    pub status_index: Option<u32>,
+}
+
+impl From<ClaimData> for ClaimRecord {
+    fn from(claim: ClaimData) -> Self {
+        Self {
+            topic: hex::encode(claim.topic),
+            scheme: hex::encode(claim.scheme),
+            issuer: claim.issuer,
+            issuer_wallet: claim.issuer_wallet,
+            signature: hex::encode(claim.signature),
+            data: hex::encode(claim.data),
+            uri: hex::encode(claim.uri),
+            status_index: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityRecord {
+    pub factory: String,
+    pub wallets: BTreeSet<String>,
+    // Set for the onchainID of a token, which has no wallets
+    #[serde(default)]
+    pub token: Option<String>,
+    // By claim id
+    pub claims: BTreeMap<String, ClaimRecord>,
+}
+
+// Revocations recorded by a claim issuer contract, mirroring its storage:
+// signed claims by signature, issued claims by status list index
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuerRecord {
+    pub revoked_signatures: BTreeSet<String>,
+    pub revoked_status: BTreeSet<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    pub identities: BTreeMap<String, IdentityRecord>,
+    // Wallet to identity, as the factory's try_get_identity resolves it
+    pub wallets: BTreeMap<String, String>,
+    // Token to identity, as the factory's get_token_identity resolves it
+    #[serde(default)]
+    pub tokens: BTreeMap<String, String>,
+    // Contract to key to purposes
+    pub keys: BTreeMap<String, BTreeMap<String, BTreeSet<u32>>>,
+    pub issuers: BTreeMap<String, IssuerRecord>,
+}
+
+impl State {
+    // Factory events must already be checked against the trusted factories
+    pub(crate) fn apply(&mut self, event: Event) {
+        match event {
+            Event::Initialized { contract, owner } => {
+                let keys = self.keys.entry(contract).or_default();
+                keys.entry(owner).or_default().insert(MANAGEMENT_PURPOSE);
+            }
+            Event::IdentityCreated {
+                contract,
+                identity,
+                wallet,
+            } => {
+                let record = self.identities.entry(identity.clone()).or_default();
+                record.factory = contract;
+                record.wallets.insert(wallet.clone());
+                self.wallets.insert(wallet, identity);
+            }
+            Event::TokenIdentityCreated {
+                contract,
+                identity,
+                token,
+            } => {
+                let record = self.identities.entry(identity.clone()).or_default();
+                record.factory = contract;
+                record.token = Some(token.clone());
+                self.tokens.insert(token, identity);
+            }
+            Event::WalletLinked {
+                identity, wallet, ..
+            } => {
+                if let Some(record) = self.identities.get_mut(&identity) {
+                    record.wallets.insert(wallet.clone());
+                    self.wallets.insert(wallet, identity);
+                }
+            }
+            Event::WalletUnlinked {
+                identity, wallet, ..
+            } => {
+                if let Some(record) = self.identities.get_mut(&identity) {
+                    record.wallets.remove(&wallet);
+                }
+                self.wallets.remove(&wallet);
+            }
+            Event::KeyAdded {
+                contract,
+                key,
+                purpose,
+            } => {
+                self.keys
+                    .entry(contract)
+                    .or_default()
+                    .entry(key)
+                    .or_default()
+                    .insert(purpose);
+            }
+            Event::KeyRemoved {
+                contract,
+                key,
+                purpose,
+            } => {
+                if let Some(keys) = self.keys.get_mut(&contract) {
+                    if let Some(purposes) = keys.get_mut(&key) {
+                        purposes.remove(&purpose);
+                        if purposes.is_empty() {
+                            keys.remove(&key);
+                        }
+                    }
+                }
+            }
+            Event::ClaimAdded {
+                contract,
+                claim_id,
+                claim,
+            } => {
+                if let Some(record) = self.identities.get_mut(&contract) {
+                    record.claims.insert(hex::encode(claim_id), claim.into());
+                }
+            }
+            Event::ClaimRemoved { contract, claim_id } => {
+                if let Some(record) = self.identities.get_mut(&contract) {
+                    record.claims.remove(&hex::encode(claim_id));
+                }
+            }
+            Event::ClaimIssued {
+                contract,
+                identity,
+                claim_id,
+                status_index,
+            } => {
+                if let Some(claim) = self.claim_by(&contract, &identity, &claim_id) {
+                    claim.status_index = Some(status_index);
+                }
+            }
+            Event::ClaimRevoked {
+                contract,
+                identity,
+                claim_id,
+            } => {
+                let Some(claim) = self.claim_by(&contract, &identity, &claim_id) else {
+                    return;
+                };
+                let (status_index, signature) = (claim.status_index, claim.signature.clone());
+                let issuer = self.issuers.entry(contract).or_default();
+                match status_index {
+                    Some(index) => issuer.revoked_status.insert(index),
+                    None => issuer.revoked_signatures.insert(signature),
+                };
+            }
+            Event::StatusRevoked { contract, index } => {
+                self.issuers
+                    .entry(contract)
+                    .or_default()
+                    .revoked_status
+                    .insert(index);
+            }
+        }
+    }
+
+    // An identity's claim, only if `issuer` is the one that made it. Issuer
+    // events can come from any contract and must not touch other issuers' claims.
+    fn claim_by(
+        &mut self,
+        issuer: &str,
+        identity: &str,
+        claim_id: &[u8; 32],
+    ) -> Option<&mut ClaimRecord> {
+        self.identities
+            .get_mut(identity)?
+            .claims
+            .get_mut(&hex::encode(claim_id))
+            .filter(|claim| claim.issuer == issuer)
+    }
+
+    pub fn identity_of(&self, wallet: &str) -> Option<&str> {
+        self.wallets.get(wallet).map(String::as_str)
+    }
+
+    pub fn token_identity(&self, token: &str) -> Option<&str> {
+        self.tokens.get(token).map(String::as_str)
+    }
+
+    pub fn key_purposes(&self, contract: &str, key: &str) -> Option<&BTreeSet<u32>> {
+        self.keys.get(contract)?.get(key)
+    }
+
+    pub fn is_revoked(&self, claim: &ClaimRecord) -> bool {
+        let Some(issuer) = self.issuers.get(&claim.issuer) else {
+            return false;
+        };
+        match claim.status_index {
+            Some(index) => issuer.revoked_status.contains(&index),
+            None => issuer.revoked_signatures.contains(&claim.signature),
+        }
+    }
+
+    // Identities holding an unrevoked claim on `topic` from `issuer`.
+    // Signatures are not re-verified, the identity checked them when the claim was added.
+    pub fn identities_with_claim(&self, issuer: &str, topic: &Topic) -> Vec<&str> {
+        let topic = hex::encode(topic);
+        self.identities
+            .iter()
+            .filter(|(_, record)| {
+                record.claims.values().any(|claim| {
+                    claim.issuer == issuer && claim.topic == topic && !self.is_revoked(claim)
+                })
+            })
+            .map(|(identity, _)| identity.as_str())
+            .collect()
+    }
+}
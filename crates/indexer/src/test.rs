@@ -0,0 +1,184 @@
+// fixtures/lifecycle.jsonl was recorded from a soroban test environment running
+// the onchainid-common key and claim holders, with the factory and issuer
+// events published through the same event structs the contracts use.
+
+use super::*;
+use onchainid_signer::{strkey, topic_from_u128};
+use stellar_xdr::curr::{Limits, ScMap, ScMapEntry, ScVal, WriteXdr};
+
+const FIXTURE: &str = include_str!("../fixtures/lifecycle.jsonl");
+
+const FACTORY: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAD2KM";
+const IDENTITY_A: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAHK3M";
+const IDENTITY_B: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAITA4";
+const IDENTITY_C: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAK3IM";
+// Announced by a factory nobody trusts
+const IDENTITY_X: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAMDR4";
+const ISSUER: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAOLZM";
+const ROGUE_ISSUER: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAARQG5";
+
+const WALLET_1: &str = "GAAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQDZ7H";
+const WALLET_2: &str = "GABAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEJXA";
+const WALLET_3: &str = "GABQGAYDAMBQGAYDAMBQGAYDAMBQGAYDAMBQGAYDAMBQGAYDAMBQHGPC";
+const WALLET_4: &str = "GACAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAJJHP";
+const CLAIM_KEY: &str = "GADAMBQGAYDAMBQGAYDAMBQGAYDAMBQGAYDAMBQGAYDAMBQGAYDANWXK";
+
+fn events() -> Vec<RawEvent> {
+    FIXTURE
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+// The fixture predates token identities, so this one is encoded here
+fn token_identity_created(factory: &str, identity: &str, token: &str) -> RawEvent {
+    let symbol = |name: &str| ScVal::Symbol(name.try_into().unwrap());
+    let address = |strkey: &str| ScVal::Address(strkey::parse_address(strkey).unwrap());
+    let entry = |key: &str, val| ScMapEntry {
+        key: symbol(key),
+        val,
+    };
+    let encode = |value: ScVal| value.to_xdr_base64(Limits::none()).unwrap();
+
+    let data = ScMap::sorted_from(vec![
+        entry("salt", ScVal::Bytes(vec![7u8; 32].try_into().unwrap())),
+        entry("token", address(token)),
+        entry("token_owner", address(WALLET_1)),
+    ])
+    .unwrap();
+    RawEvent {
+        id: "0000009999999999999-0000000001".to_string(),
+        ledger: 2000,
+        contract_id: factory.to_string(),
+        topic: vec![
+            encode(symbol("0xauth")),
+            encode(symbol("v1")),
+            encode(symbol("token_identity_created")),
+            encode(address(identity)),
+        ],
+        value: encode(ScVal::Map(Some(data))),
+    }
+}
+
+fn indexer() -> Indexer {
+    Indexer::new([FACTORY.to_string()])
+}
+
+#[test]
+fn test_lifecycle() {
+    let mut indexer = indexer();
+    indexer.ingest_all(&events()).unwrap();
+    let state = &indexer.state;
+
+    // Wallets, with wallet 3 linked and unlinked again
+    assert_eq!(state.identity_of(WALLET_1), Some(IDENTITY_A));
+    assert_eq!(state.identity_of(WALLET_2), Some(IDENTITY_B));
+    assert_eq!(state.identity_of(WALLET_4), Some(IDENTITY_C));
+    assert_eq!(state.identity_of(WALLET_3), None);
+    assert!(!state.identities.contains_key(IDENTITY_X));
+    assert_eq!(state.identities[IDENTITY_A].factory, FACTORY);
+
+    // Keys, the claim key was added then removed
+    assert_eq!(
+        state
+            .key_purposes(IDENTITY_A, WALLET_1)
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>(),
+        [&1]
+    );
+    assert_eq!(state.key_purposes(IDENTITY_A, CLAIM_KEY), None);
+
+    // A's signed claim was revoked by signature, B's issued claim by status index
+    let kyc = topic_from_u128(1);
+    assert_eq!(state.identities_with_claim(ISSUER, &kyc), [IDENTITY_C]);
+    assert_eq!(
+        state.identities_with_claim(ISSUER, &topic_from_u128(2)),
+        [IDENTITY_B]
+    );
+    assert!(state.identities_with_claim(ROGUE_ISSUER, &kyc).is_empty());
+
+    let issuer = &state.issuers[ISSUER];
+    assert_eq!(issuer.revoked_status.iter().collect::<Vec<_>>(), [&0]);
+    assert_eq!(issuer.revoked_signatures.len(), 1);
+    assert!(!state.issuers.contains_key(ROGUE_ISSUER));
+
+    let claim_c = state.identities[IDENTITY_C].claims.values().next().unwrap();
+    assert_eq!(claim_c.status_index, Some(1));
+    assert!(!state.is_revoked(claim_c));
+}
+
+#[test]
+fn test_token_identity() {
+    let mut indexer = indexer();
+    indexer.ingest_all(&events()).unwrap();
+
+    // Token identities are looked up by token and never by wallet
+    assert!(indexer
+        .ingest(&token_identity_created(FACTORY, IDENTITY_X, ISSUER))
+        .unwrap());
+    let state = &indexer.state;
+    assert_eq!(state.token_identity(ISSUER), Some(IDENTITY_X));
+    assert_eq!(state.identities[IDENTITY_X].token.as_deref(), Some(ISSUER));
+    assert!(state.identities[IDENTITY_X].wallets.is_empty());
+    assert_eq!(state.identity_of(ISSUER), None);
+
+    let mut untrusted = Indexer::new([]);
+    assert!(!untrusted
+        .ingest(&token_identity_created(FACTORY, IDENTITY_X, ISSUER))
+        .unwrap());
+    assert_eq!(untrusted.state.token_identity(ISSUER), None);
+}
+
+#[test]
+fn test_snapshot_replay() {
+    let events = events();
+    let mut full = indexer();
+    assert!(full.ingest_all(&events).unwrap() > 0);
+
+    let mut partial = indexer();
+    partial.ingest_all(&events[..events.len() / 2]).unwrap();
+
+    let path = std::env::temp_dir().join(format!("onchainid-indexer-{}.json", std::process::id()));
+    partial.save(&path).unwrap();
+    let mut restored = Indexer::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(restored, partial);
+
+    // Replaying the whole stream skips what the snapshot already holds
+    restored.ingest_all(&events).unwrap();
+    assert_eq!(restored, full);
+    assert_eq!(restored.ingest_all(&events).unwrap(), 0);
+    assert_eq!(
+        restored.cursor.as_deref(),
+        Some(events.last().unwrap().id.as_str())
+    );
+}
+
+#[test]
+fn test_foreign_events() {
+    let events = events();
+    let mut indexer = indexer();
+
+    // Other namespaces are skipped
+    let mut foreign = events[0].clone();
+    foreign.topic[0] = "AAAADwAAAAh0cmFuc2Zlcg==".to_string();
+    foreign.id = "0000000000000000001-0000000001".to_string();
+    assert_eq!(foreign.decode().unwrap(), None);
+    assert!(!indexer.ingest(&foreign).unwrap());
+
+    // Unknown versions are an error rather than silently misread
+    let mut future = events[0].clone();
+    future.topic[1] = "AAAADwAAAAJ2MgAA".to_string();
+    assert!(matches!(future.decode(), Err(Error::UnsupportedVersion(v)) if v == "v2"));
+
+    let mut garbled = events[0].clone();
+    garbled.value = "AAAAAQ==".to_string();
+    assert!(matches!(garbled.decode(), Err(Error::Malformed(_))));
+
+    // Without trusted factories no identity is indexed
+    let mut untrusted = Indexer::new([]);
+    untrusted.ingest_all(&events).unwrap();
+    assert!(untrusted.state.identities.is_empty());
+    assert!(untrusted.state.wallets.is_empty());
+}
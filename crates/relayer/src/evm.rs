@@ -0,0 +1,146 @@
+// ERC-734/735 logs emitted by EVM ONCHAINID identities, ABI decoded
+
+use sha3::{Digest, Keccak256};
+
+use crate::Error;
+
+pub type EvmAddress = [u8; 20];
+pub type Word = [u8; 32];
+
+const CLAIM_ADDED: &str = "ClaimAdded(bytes32,uint256,uint256,address,bytes,bytes,string)";
+const CLAIM_REMOVED: &str = "ClaimRemoved(bytes32,uint256,uint256,address,bytes,bytes,string)";
+const KEY_ADDED: &str = "KeyAdded(bytes32,uint256,uint256)";
+const KEY_REMOVED: &str = "KeyRemoved(bytes32,uint256,uint256)";
+
+pub fn event_topic(signature: &str) -> Word {
+    Keccak256::digest(signature.as_bytes()).into()
+}
+
+// A log as returned by eth_getLogs, `address` being the emitting identity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmLog {
+    pub address: EvmAddress,
+    pub topics: Vec<Word>,
+    pub data: Vec<u8>,
+    pub tx_hash: Word,
+    pub log_index: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmClaim {
+    pub claim_id: Word,
+    pub topic: Word,
+    pub scheme: Word,
+    pub issuer: EvmAddress,
+    pub signature: Vec<u8>,
+    pub data: Vec<u8>,
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmEvent {
+    ClaimAdded(EvmClaim),
+    ClaimRemoved(EvmClaim),
+    // `key` is keccak256(abi.encode(address)) as ERC-734 stores it
+    KeyAdded {
+        key: Word,
+        purpose: u32,
+        key_type: u32,
+    },
+    KeyRemoved {
+        key: Word,
+        purpose: u32,
+        key_type: u32,
+    },
+}
+
+impl EvmLog {
+    // None for logs that are not one of the four events above
+    pub fn decode(&self) -> Result<Option<EvmEvent>, Error> {
+        let Some(signature) = self.topics.first() else {
+            return Ok(None);
+        };
+        let indexed = |i: usize| {
+            self.topics
+                .get(i)
+                .ok_or(Error::Abi("missing indexed argument"))
+        };
+
+        let event = if *signature == event_topic(CLAIM_ADDED)
+            || *signature == event_topic(CLAIM_REMOVED)
+        {
+            let claim = EvmClaim {
+                claim_id: *indexed(1)?,
+                topic: *indexed(2)?,
+                scheme: word(&self.data, 0)?,
+                issuer: address(indexed(3)?)?,
+                signature: dynamic(&self.data, 1)?,
+                data: dynamic(&self.data, 2)?,
+                uri: String::from_utf8(dynamic(&self.data, 3)?)
+                    .map_err(|_| Error::Abi("uri is not utf-8"))?,
+            };
+            if *signature == event_topic(CLAIM_ADDED) {
+                EvmEvent::ClaimAdded(claim)
+            } else {
+                EvmEvent::ClaimRemoved(claim)
+            }
+        } else if *signature == event_topic(KEY_ADDED) || *signature == event_topic(KEY_REMOVED) {
+            let key = *indexed(1)?;
+            let purpose = small(indexed(2)?)?;
+            let key_type = small(indexed(3)?)?;
+            if *signature == event_topic(KEY_ADDED) {
+                EvmEvent::KeyAdded {
+                    key,
+                    purpose,
+                    key_type,
+                }
+            } else {
+                EvmEvent::KeyRemoved {
+                    key,
+                    purpose,
+                    key_type,
+                }
+            }
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(event))
+    }
+}
+
+fn word(data: &[u8], index: usize) -> Result<Word, Error> {
+    data.get(index * 32..(index + 1) * 32)
+        .map(|w| w.try_into().unwrap())
+        .ok_or(Error::Abi("data too short"))
+}
+
+// uint256 that has to fit in a u32, as purposes and key types do
+fn small(word: &Word) -> Result<u32, Error> {
+    if word[..28].iter().any(|b| *b != 0) {
+        return Err(Error::Abi("value does not fit in u32"));
+    }
+    Ok(u32::from_be_bytes(word[28..].try_into().unwrap()))
+}
+
+fn usize_of(word: &Word) -> Result<usize, Error> {
+    small(word).map(|n| n as usize)
+}
+
+fn address(word: &Word) -> Result<EvmAddress, Error> {
+    if word[..12].iter().any(|b| *b != 0) {
+        return Err(Error::Abi("invalid address"));
+    }
+    Ok(word[12..].try_into().unwrap())
+}
+
+// `bytes` or `string` whose offset is the head word at `index`
+fn dynamic(data: &[u8], index: usize) -> Result<Vec<u8>, Error> {
+    let offset = usize_of(&word(data, index)?)?;
+    let len_word = data
+        .get(offset..offset + 32)
+        .ok_or(Error::Abi("offset out of range"))?;
+    let len = usize_of(&len_word.try_into().unwrap())?;
+    data.get(offset + 32..offset + 32 + len)
+        .map(<[u8]>::to_vec)
+        .ok_or(Error::Abi("length out of range"))
+}
@@ -0,0 +1,302 @@
+// Relays ERC-734/735 key and claim events from EVM identities to their
+// Stellar counterparts.
+//
+// The owner of an EVM identity is mapped to a Stellar wallet and from there to
+// the identity the factory deployed for it. Claims are re-signed with the
+// relayer's ed25519 key so Stellar identities verify them like any other
+// signed claim. Every relay first reads the Stellar state and submits nothing
+// if the event is already reflected, so replaying logs is harmless.
+
+use std::collections::BTreeSet;
+
+use onchainid_signer::{strkey, ClaimSigner, Topic};
+use stellar_xdr::curr::{ScBytes, ScVal, UInt256Parts};
+
+pub mod evm;
+
+pub use evm::{EvmAddress, EvmClaim, EvmEvent, EvmLog, Word};
+
+// Scheme of the claims the relayer writes: signed with its own ed25519 key
+pub const BRIDGED_SCHEME: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("abi decoding failed: {0}")]
+    Abi(&'static str),
+    #[error(transparent)]
+    Signer(#[from] onchainid_signer::Error),
+    #[error("transport failed: {0}")]
+    Transport(String),
+}
+
+// A contract call with its arguments as the ScVals the contract receives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub contract: String,
+    pub function: String,
+    pub args: Vec<ScVal>,
+}
+
+// The parts of a Stellar claim that tell whether an EVM claim was relayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StellarClaim {
+    pub issuer: String,
+    pub issuer_wallet: String,
+    pub data: Vec<u8>,
+    pub uri: Vec<u8>,
+}
+
+// The relayer's key. It signs bridged claims and must hold the Claim purpose
+// on the issuer contract and on the identities it writes to.
+pub trait Signer {
+    fn account_id(&self) -> String;
+    fn sign_claim(&self, identity: &str, topic: &Topic, data: &[u8]) -> Result<[u8; 64], Error>;
+}
+
+impl Signer for ClaimSigner {
+    fn account_id(&self) -> String {
+        ClaimSigner::account_id(self)
+    }
+
+    fn sign_claim(&self, identity: &str, topic: &Topic, data: &[u8]) -> Result<[u8; 64], Error> {
+        Ok(ClaimSigner::sign_claim(self, identity, topic, data)?)
+    }
+}
+
+// Everything the relayer reads from or writes to the outside world
+pub trait Transport {
+    // Stellar wallet of the user owning an EVM identity
+    fn stellar_wallet(&self, evm_identity: &EvmAddress) -> Result<Option<String>, Error>;
+    // Stellar account behind an ERC-734 key hash
+    fn stellar_key(&self, evm_key: &Word) -> Result<Option<String>, Error>;
+    // The factory's wallet to identity mapping
+    fn get_identity(&self, factory: &str, wallet: &str) -> Result<Option<String>, Error>;
+    fn get_claim(&self, identity: &str, claim_id: &[u8; 32])
+        -> Result<Option<StellarClaim>, Error>;
+    fn key_purposes(&self, identity: &str, key: &str) -> Result<Vec<u32>, Error>;
+    // Sends `call` from the `source` account, returns the transaction hash
+    fn submit(&self, source: &str, call: &Call) -> Result<String, Error>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayerConfig {
+    pub factory: String,
+    // Claim issuer contract bridged claims are attributed to
+    pub issuer: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Submitted { call: Call, tx: String },
+    // This log was relayed before
+    Duplicate,
+    // The Stellar identity already reflects the event
+    UpToDate,
+    UnknownIdentity,
+    UnknownKey,
+    // Not a key or claim event
+    Ignored,
+}
+
+pub struct Relayer<S, T> {
+    config: RelayerConfig,
+    signer: S,
+    transport: T,
+    // (tx hash, log index) of every log handled
+    relayed: BTreeSet<(Word, u64)>,
+}
+
+impl<S: Signer, T: Transport> Relayer<S, T> {
+    pub fn new(config: RelayerConfig, signer: S, transport: T) -> Self {
+        Self {
+            config,
+            signer,
+            transport,
+            relayed: BTreeSet::new(),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn relay(&mut self, log: &EvmLog) -> Result<Outcome, Error> {
+        let log_id = (log.tx_hash, log.log_index);
+        if self.relayed.contains(&log_id) {
+            return Ok(Outcome::Duplicate);
+        }
+        let Some(event) = log.decode()? else {
+            return Ok(Outcome::Ignored);
+        };
+
+        let identity = match self.transport.stellar_wallet(&log.address)? {
+            Some(wallet) => self.transport.get_identity(&self.config.factory, &wallet)?,
+            None => None,
+        };
+        let Some(identity) = identity else {
+            return Ok(Outcome::UnknownIdentity);
+        };
+
+        let call = match event {
+            EvmEvent::ClaimAdded(claim) => self.add_claim(&identity, &claim)?,
+            EvmEvent::ClaimRemoved(claim) => self.remove_claim(&identity, &claim)?,
+            EvmEvent::KeyAdded {
+                key,
+                purpose,
+                key_type,
+            } => {
+                let Some(key) = self.transport.stellar_key(&key)? else {
+                    return Ok(Outcome::UnknownKey);
+                };
+                self.add_key(&identity, &key, purpose, key_type)?
+            }
+            EvmEvent::KeyRemoved { key, purpose, .. } => {
+                let Some(key) = self.transport.stellar_key(&key)? else {
+                    return Ok(Outcome::UnknownKey);
+                };
+                self.remove_key(&identity, &key, purpose)?
+            }
+        };
+
+        let outcome = match call {
+            Some(call) => {
+                let tx = self.transport.submit(&self.signer.account_id(), &call)?;
+                Outcome::Submitted { call, tx }
+            }
+            None => Outcome::UpToDate,
+        };
+        self.relayed.insert(log_id);
+        Ok(outcome)
+    }
+
+    fn bridged_claim(
+        &self,
+        identity: &str,
+        claim: &EvmClaim,
+    ) -> Result<Option<StellarClaim>, Error> {
+        let claim_id = onchainid_signer::claim_id(&self.config.issuer, &claim.topic)?;
+        let existing = self.transport.get_claim(identity, &claim_id)?;
+        Ok(existing.filter(|existing| {
+            existing.issuer == self.config.issuer
+                && existing.issuer_wallet == self.signer.account_id()
+                && existing.data == claim.data
+                && existing.uri == claim.uri.as_bytes()
+        }))
+    }
+
+    fn add_claim(&self, identity: &str, claim: &EvmClaim) -> Result<Option<Call>, Error> {
+        if self.bridged_claim(identity, claim)?.is_some() {
+            return Ok(None);
+        }
+        let relayer = self.signer.account_id();
+        let signature = self
+            .signer
+            .sign_claim(identity, &claim.topic, &claim.data)?;
+        Ok(Some(call(
+            identity,
+            "add_claim",
+            vec![
+                address(&relayer)?,
+                u256(&claim.topic),
+                u256(&onchainid_signer::topic_from_u128(BRIDGED_SCHEME.into())),
+                address(&relayer)?,
+                address(&self.config.issuer)?,
+                bytes(&signature)?,
+                bytes(&claim.data)?,
+                bytes(claim.uri.as_bytes())?,
+            ],
+        )))
+    }
+
+    // Only removes the claim if it still carries the data being removed on the
+    // EVM side, a newer claim on the same topic stays
+    fn remove_claim(&self, identity: &str, claim: &EvmClaim) -> Result<Option<Call>, Error> {
+        if self.bridged_claim(identity, claim)?.is_none() {
+            return Ok(None);
+        }
+        let claim_id = onchainid_signer::claim_id(&self.config.issuer, &claim.topic)?;
+        Ok(Some(call(
+            identity,
+            "remove_claim",
+            vec![address(&self.signer.account_id())?, bytes(&claim_id)?],
+        )))
+    }
+
+    fn add_key(
+        &self,
+        identity: &str,
+        key: &str,
+        purpose: u32,
+        key_type: u32,
+    ) -> Result<Option<Call>, Error> {
+        if self
+            .transport
+            .key_purposes(identity, key)?
+            .contains(&purpose)
+        {
+            return Ok(None);
+        }
+        Ok(Some(call(
+            identity,
+            "add_key",
+            vec![
+                address(&self.signer.account_id())?,
+                address(key)?,
+                ScVal::U32(purpose),
+                ScVal::U32(key_type),
+            ],
+        )))
+    }
+
+    fn remove_key(&self, identity: &str, key: &str, purpose: u32) -> Result<Option<Call>, Error> {
+        if !self
+            .transport
+            .key_purposes(identity, key)?
+            .contains(&purpose)
+        {
+            return Ok(None);
+        }
+        Ok(Some(call(
+            identity,
+            "remove_key",
+            vec![
+                address(&self.signer.account_id())?,
+                address(key)?,
+                ScVal::U32(purpose),
+            ],
+        )))
+    }
+}
+
+fn call(contract: &str, function: &str, args: Vec<ScVal>) -> Call {
+    Call {
+        contract: contract.to_string(),
+        function: function.to_string(),
+        args,
+    }
+}
+
+fn address(address: &str) -> Result<ScVal, Error> {
+    Ok(ScVal::Address(strkey::parse_address(address)?))
+}
+
+fn u256(value: &Word) -> ScVal {
+    let part = |i: usize| u64::from_be_bytes(value[i * 8..(i + 1) * 8].try_into().unwrap());
+    ScVal::U256(UInt256Parts {
+        hi_hi: part(0),
+        hi_lo: part(1),
+        lo_hi: part(2),
+        lo_lo: part(3),
+    })
+}
+
+fn bytes(value: &[u8]) -> Result<ScVal, Error> {
+    let bytes = value
+        .to_vec()
+        .try_into()
+        .map_err(|_| Error::Abi("bytes too long"))?;
+    Ok(ScVal::Bytes(ScBytes(bytes)))
+}
+
+#[cfg(test)]
+mod test;
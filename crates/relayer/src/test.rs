@@ -0,0 +1,391 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::*;
+use onchainid_signer::topic_from_u128;
+
+// Same test key as the contract tests, do not use it anywhere else
+const RELAYER_SECRET: [u8; 32] = [
+    0xb5, 0x1a, 0x48, 0x2a, 0x45, 0x9d, 0x1b, 0x2f, 0x8f, 0x1f, 0xf5, 0xb7, 0x15, 0x9c, 0xdb, 0xf0,
+    0xab, 0x23, 0xee, 0x46, 0x42, 0x2e, 0xd0, 0x72, 0x4f, 0x28, 0x22, 0xcd, 0x55, 0x0e, 0xcf, 0x71,
+];
+const RELAYER: &str = "GARPZXSZVTI7WG3ADZQB5QGH67WS6XW6ISHIO2N4AOAC32PLNYBH7QCY";
+
+const FACTORY: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAD2KM";
+const IDENTITY: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAHK3M";
+const ISSUER: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAOLZM";
+const WALLET: &str = "GAAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQDZ7H";
+const KEY: &str = "GABAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEJXA";
+
+const EVM_IDENTITY: EvmAddress = [0x11; 20];
+const EVM_ISSUER: EvmAddress = [0x22; 20];
+const EVM_KEY: Word = [0x33; 32];
+
+// Stands in for the EVM directory and Soroban RPC, applying submitted calls
+// to its own state so a second relay sees the first one's effect
+#[derive(Default)]
+struct LocalTransport {
+    wallets: BTreeMap<EvmAddress, String>,
+    keys: BTreeMap<Word, String>,
+    identities: BTreeMap<String, String>,
+    claims: RefCell<BTreeMap<[u8; 32], StellarClaim>>,
+    purposes: RefCell<BTreeMap<String, BTreeSet<u32>>>,
+    submitted: RefCell<Vec<(String, Call)>>,
+}
+
+impl LocalTransport {
+    fn new() -> Self {
+        let mut transport = Self::default();
+        transport.wallets.insert(EVM_IDENTITY, WALLET.to_string());
+        transport.keys.insert(EVM_KEY, KEY.to_string());
+        transport
+            .identities
+            .insert(WALLET.to_string(), IDENTITY.to_string());
+        transport
+    }
+}
+
+fn bytes_arg(val: &ScVal) -> Vec<u8> {
+    match val {
+        ScVal::Bytes(bytes) => bytes.0.to_vec(),
+        _ => panic!("not bytes"),
+    }
+}
+
+fn u32_arg(val: &ScVal) -> u32 {
+    match val {
+        ScVal::U32(n) => *n,
+        _ => panic!("not u32"),
+    }
+}
+
+fn address_arg(val: &ScVal) -> String {
+    match val {
+        ScVal::Address(address) => strkey::format_address(address),
+        _ => panic!("not an address"),
+    }
+}
+
+impl Transport for LocalTransport {
+    fn stellar_wallet(&self, evm_identity: &EvmAddress) -> Result<Option<String>, Error> {
+        Ok(self.wallets.get(evm_identity).cloned())
+    }
+
+    fn stellar_key(&self, evm_key: &Word) -> Result<Option<String>, Error> {
+        Ok(self.keys.get(evm_key).cloned())
+    }
+
+    fn get_identity(&self, factory: &str, wallet: &str) -> Result<Option<String>, Error> {
+        assert_eq!(factory, FACTORY);
+        Ok(self.identities.get(wallet).cloned())
+    }
+
+    fn get_claim(
+        &self,
+        identity: &str,
+        claim_id: &[u8; 32],
+    ) -> Result<Option<StellarClaim>, Error> {
+        assert_eq!(identity, IDENTITY);
+        Ok(self.claims.borrow().get(claim_id).cloned())
+    }
+
+    fn key_purposes(&self, identity: &str, key: &str) -> Result<Vec<u32>, Error> {
+        assert_eq!(identity, IDENTITY);
+        let purposes = self.purposes.borrow();
+        Ok(purposes
+            .get(key)
+            .map(|p| p.iter().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn submit(&self, source: &str, call: &Call) -> Result<String, Error> {
+        let args = &call.args;
+        match call.function.as_str() {
+            "add_claim" => {
+                let topic: Topic = match &args[1] {
+                    ScVal::U256(parts) => {
+                        let mut topic = [0u8; 32];
+                        for (i, part) in [parts.hi_hi, parts.hi_lo, parts.lo_hi, parts.lo_lo]
+                            .iter()
+                            .enumerate()
+                        {
+                            topic[i * 8..(i + 1) * 8].copy_from_slice(&part.to_be_bytes());
+                        }
+                        topic
+                    }
+                    _ => panic!("not a topic"),
+                };
+                let issuer = address_arg(&args[4]);
+                let claim = StellarClaim {
+                    issuer: issuer.clone(),
+                    issuer_wallet: address_arg(&args[3]),
+                    data: bytes_arg(&args[6]),
+                    uri: bytes_arg(&args[7]),
+                };
+                let claim_id = onchainid_signer::claim_id(&issuer, &topic)?;
+                self.claims.borrow_mut().insert(claim_id, claim);
+            }
+            "remove_claim" => {
+                let claim_id: [u8; 32] = bytes_arg(&args[1]).try_into().unwrap();
+                self.claims.borrow_mut().remove(&claim_id);
+            }
+            "add_key" => {
+                let mut purposes = self.purposes.borrow_mut();
+                purposes
+                    .entry(address_arg(&args[1]))
+                    .or_default()
+                    .insert(u32_arg(&args[2]));
+            }
+            "remove_key" => {
+                let mut purposes = self.purposes.borrow_mut();
+                if let Some(p) = purposes.get_mut(&address_arg(&args[1])) {
+                    p.remove(&u32_arg(&args[2]));
+                }
+            }
+            function => return Err(Error::Transport(format!("unexpected call {function}"))),
+        }
+        let mut submitted = self.submitted.borrow_mut();
+        submitted.push((source.to_string(), call.clone()));
+        Ok(format!("tx{}", submitted.len()))
+    }
+}
+
+fn relayer() -> Relayer<ClaimSigner, LocalTransport> {
+    let config = RelayerConfig {
+        factory: FACTORY.to_string(),
+        issuer: ISSUER.to_string(),
+    };
+    Relayer::new(
+        config,
+        ClaimSigner::from_bytes(&RELAYER_SECRET),
+        LocalTransport::new(),
+    )
+}
+
+fn uint(n: u64) -> Word {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&n.to_be_bytes());
+    word
+}
+
+fn address_word(address: &EvmAddress) -> Word {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address);
+    word
+}
+
+// abi.encode(uint256 scheme, bytes signature, bytes data, string uri)
+fn claim_data(scheme: u64, signature: &[u8], data: &[u8], uri: &str) -> Vec<u8> {
+    let tails: Vec<&[u8]> = vec![signature, data, uri.as_bytes()];
+    let mut head = uint(scheme).to_vec();
+    let mut tail = Vec::new();
+    for value in tails {
+        head.extend(uint((4 * 32 + tail.len()) as u64));
+        tail.extend(uint(value.len() as u64));
+        tail.extend(value);
+        tail.resize(tail.len().div_ceil(32) * 32, 0);
+    }
+    head.extend(tail);
+    head
+}
+
+fn claim_log(signature: &str, topic: u128, data: &[u8], uri: &str, log_index: u64) -> EvmLog {
+    EvmLog {
+        address: EVM_IDENTITY,
+        topics: vec![
+            evm::event_topic(signature),
+            [0x44; 32],
+            topic_from_u128(topic),
+            address_word(&EVM_ISSUER),
+        ],
+        data: claim_data(1, &[0x55; 65], data, uri),
+        tx_hash: [0xaa; 32],
+        log_index,
+    }
+}
+
+fn claim_added(topic: u128, data: &[u8], uri: &str, log_index: u64) -> EvmLog {
+    claim_log(
+        "ClaimAdded(bytes32,uint256,uint256,address,bytes,bytes,string)",
+        topic,
+        data,
+        uri,
+        log_index,
+    )
+}
+
+fn claim_removed(topic: u128, data: &[u8], uri: &str, log_index: u64) -> EvmLog {
+    claim_log(
+        "ClaimRemoved(bytes32,uint256,uint256,address,bytes,bytes,string)",
+        topic,
+        data,
+        uri,
+        log_index,
+    )
+}
+
+fn key_log(signature: &str, key: Word, purpose: u64, log_index: u64) -> EvmLog {
+    EvmLog {
+        address: EVM_IDENTITY,
+        topics: vec![evm::event_topic(signature), key, uint(purpose), uint(1)],
+        data: Vec::new(),
+        tx_hash: [0xbb; 32],
+        log_index,
+    }
+}
+
+#[test]
+fn test_decode() {
+    let log = claim_added(1, b"kyc", "https://issuer.example/1", 0);
+    let Some(EvmEvent::ClaimAdded(claim)) = log.decode().unwrap() else {
+        panic!("not a claim");
+    };
+    assert_eq!(claim.claim_id, [0x44; 32]);
+    assert_eq!(claim.topic, topic_from_u128(1));
+    assert_eq!(claim.scheme, uint(1));
+    assert_eq!(claim.issuer, EVM_ISSUER);
+    assert_eq!(claim.signature, [0x55; 65]);
+    assert_eq!(claim.data, b"kyc");
+    assert_eq!(claim.uri, "https://issuer.example/1");
+
+    let log = key_log("KeyRemoved(bytes32,uint256,uint256)", EVM_KEY, 3, 0);
+    assert_eq!(
+        log.decode().unwrap(),
+        Some(EvmEvent::KeyRemoved {
+            key: EVM_KEY,
+            purpose: 3,
+            key_type: 1
+        })
+    );
+
+    // Other events are ignored, malformed ones rejected
+    let transfer = key_log("Transfer(address,address,uint256)", EVM_KEY, 3, 0);
+    assert_eq!(transfer.decode().unwrap(), None);
+
+    let mut truncated = claim_added(1, b"kyc", "", 0);
+    truncated.data.truncate(100);
+    assert!(matches!(truncated.decode(), Err(Error::Abi(_))));
+
+    let mut bad_issuer = claim_added(1, b"kyc", "", 0);
+    bad_issuer.topics[3] = [0xff; 32];
+    assert!(matches!(
+        bad_issuer.decode(),
+        Err(Error::Abi("invalid address"))
+    ));
+}
+
+#[test]
+fn test_relay_claims() {
+    let mut relayer = relayer();
+    let added = claim_added(1, b"kyc", "https://issuer.example/1", 0);
+
+    let Outcome::Submitted { call, tx } = relayer.relay(&added).unwrap() else {
+        panic!("nothing submitted");
+    };
+    assert_eq!(tx, "tx1");
+    assert_eq!(call.contract, IDENTITY);
+    assert_eq!(call.function, "add_claim");
+    assert_eq!(address_arg(&call.args[0]), RELAYER);
+    assert_eq!(address_arg(&call.args[4]), ISSUER);
+
+    // Re-signed by the relayer over the Stellar identity
+    let signer = ClaimSigner::from_bytes(&RELAYER_SECRET);
+    let signature = signer
+        .sign_claim(IDENTITY, &topic_from_u128(1), b"kyc")
+        .unwrap();
+    assert_eq!(bytes_arg(&call.args[5]), signature);
+
+    // The same log again, or a replay from a restarted relayer, sends nothing
+    assert_eq!(relayer.relay(&added).unwrap(), Outcome::Duplicate);
+    let mut restarted = Relayer::new(relayer.config.clone(), signer, LocalTransport::new());
+    *restarted.transport.claims.borrow_mut() = relayer.transport().claims.borrow().clone();
+    assert_eq!(restarted.relay(&added).unwrap(), Outcome::UpToDate);
+
+    // Removing a claim whose data was since replaced leaves the new one
+    let updated = claim_added(1, b"kyc-v2", "https://issuer.example/1", 1);
+    assert!(matches!(
+        relayer.relay(&updated).unwrap(),
+        Outcome::Submitted { .. }
+    ));
+    assert_eq!(
+        relayer
+            .relay(&claim_removed(1, b"kyc", "https://issuer.example/1", 2))
+            .unwrap(),
+        Outcome::UpToDate
+    );
+
+    let removed = claim_removed(1, b"kyc-v2", "https://issuer.example/1", 3);
+    let Outcome::Submitted { call, .. } = relayer.relay(&removed).unwrap() else {
+        panic!("nothing submitted");
+    };
+    assert_eq!(call.function, "remove_claim");
+    assert!(relayer.transport().claims.borrow().is_empty());
+    assert_eq!(relayer.transport().submitted.borrow().len(), 3);
+    assert!(relayer
+        .transport()
+        .submitted
+        .borrow()
+        .iter()
+        .all(|(source, _)| source == RELAYER));
+}
+
+#[test]
+fn test_relay_keys() {
+    let mut relayer = relayer();
+    let added = key_log("KeyAdded(bytes32,uint256,uint256)", EVM_KEY, 3, 0);
+    let Outcome::Submitted { call, .. } = relayer.relay(&added).unwrap() else {
+        panic!("nothing submitted");
+    };
+    assert_eq!(call.function, "add_key");
+    assert_eq!(address_arg(&call.args[1]), KEY);
+    assert_eq!(u32_arg(&call.args[2]), 3);
+    assert_eq!(
+        relayer.transport().key_purposes(IDENTITY, KEY).unwrap(),
+        [3]
+    );
+
+    // Already holding the purpose
+    let again = key_log("KeyAdded(bytes32,uint256,uint256)", EVM_KEY, 3, 1);
+    assert_eq!(relayer.relay(&again).unwrap(), Outcome::UpToDate);
+
+    let removed = key_log("KeyRemoved(bytes32,uint256,uint256)", EVM_KEY, 3, 2);
+    assert!(matches!(
+        relayer.relay(&removed).unwrap(),
+        Outcome::Submitted { .. }
+    ));
+    let removed_again = key_log("KeyRemoved(bytes32,uint256,uint256)", EVM_KEY, 3, 3);
+    assert_eq!(relayer.relay(&removed_again).unwrap(), Outcome::UpToDate);
+    assert_eq!(relayer.transport().submitted.borrow().len(), 2);
+}
+
+#[test]
+fn test_unknown() {
+    let mut relayer = relayer();
+
+    // A key with no known Stellar account
+    let unknown_key = key_log("KeyAdded(bytes32,uint256,uint256)", [0x99; 32], 1, 0);
+    assert_eq!(relayer.relay(&unknown_key).unwrap(), Outcome::UnknownKey);
+
+    // An EVM identity without a Stellar wallet, and a wallet without an identity
+    let mut stranger = claim_added(1, b"kyc", "", 1);
+    stranger.address = [0x99; 20];
+    assert_eq!(relayer.relay(&stranger).unwrap(), Outcome::UnknownIdentity);
+
+    let mut relayer = self::relayer();
+    relayer.transport.identities.clear();
+    assert_eq!(
+        relayer.relay(&claim_added(1, b"kyc", "", 0)).unwrap(),
+        Outcome::UnknownIdentity
+    );
+    assert!(relayer.transport().submitted.borrow().is_empty());
+
+    // Unknown logs are not remembered, they can be retried once mapped
+    relayer
+        .transport
+        .identities
+        .insert(WALLET.to_string(), IDENTITY.to_string());
+    assert!(matches!(
+        relayer.relay(&claim_added(1, b"kyc", "", 0)).unwrap(),
+        Outcome::Submitted { .. }
+    ));
+}
@@ -0,0 +1,130 @@
+// Off-chain claim signing for 0xAuth issuers.
+//
+// Reproduces, without a Soroban host, the digests the contracts compute:
+// claim ids, key hashes and the message `is_claim_valid` checks the issuer's
+// ed25519 signature against.
+
+use ed25519_dalek::{Signer, SigningKey};
+use sha3::{Digest, Keccak256};
+use stellar_xdr::curr::{Limits, ScBytes, ScVal, UInt256Parts, WriteXdr};
+
+pub mod strkey;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("invalid secret seed")]
+    InvalidSecret,
+    #[error("xdr encoding failed: {0}")]
+    Xdr(String),
+}
+
+impl From<stellar_xdr::curr::Error> for Error {
+    fn from(err: stellar_xdr::curr::Error) -> Self {
+        Error::Xdr(err.to_string())
+    }
+}
+
+// Claim topics are U256 on chain, big-endian here
+pub type Topic = [u8; 32];
+
+pub fn topic_from_u128(topic: u128) -> Topic {
+    let mut bytes = [0u8; 32];
+    bytes[16..].copy_from_slice(&topic.to_be_bytes());
+    bytes
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+// XDR of the ScVal an address converts to, as produced by `Address::to_xdr`
+pub fn address_xdr(address: &str) -> Result<Vec<u8>, Error> {
+    Ok(ScVal::Address(strkey::parse_address(address)?).to_xdr(Limits::none())?)
+}
+
+// XDR of the ScVal a U256 converts to, as produced by `U256::to_xdr`
+pub fn topic_xdr(topic: &Topic) -> Result<Vec<u8>, Error> {
+    let part = |i: usize| u64::from_be_bytes(topic[i * 8..(i + 1) * 8].try_into().unwrap());
+    let parts = UInt256Parts {
+        hi_hi: part(0),
+        hi_lo: part(1),
+        lo_hi: part(2),
+        lo_lo: part(3),
+    };
+    Ok(ScVal::U256(parts).to_xdr(Limits::none())?)
+}
+
+// keccak256(address XDR), the hash identities store keys under
+pub fn key_hash(address: &str) -> Result<[u8; 32], Error> {
+    Ok(keccak256(&address_xdr(address)?))
+}
+
+// keccak256(issuer XDR ‖ topic XDR), the id a claim is stored under
+pub fn claim_id(issuer: &str, topic: &Topic) -> Result<[u8; 32], Error> {
+    let mut bytes = address_xdr(issuer)?;
+    bytes.extend(topic_xdr(topic)?);
+    Ok(keccak256(&bytes))
+}
+
+// keccak256(wallet XDR ‖ nonce), a deterministic factory salt so a wallet's
+// n-th identity can be recomputed instead of stored
+pub fn identity_salt(wallet: &str, nonce: u32) -> Result<[u8; 32], Error> {
+    let mut bytes = address_xdr(wallet)?;
+    bytes.extend_from_slice(&nonce.to_be_bytes());
+    Ok(keccak256(&bytes))
+}
+
+// The message an issuer signs: keccak256(identity XDR ‖ topic XDR ‖ data),
+// XDR-encoded again as a Bytes ScVal
+pub fn claim_digest(identity: &str, topic: &Topic, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut bytes = address_xdr(identity)?;
+    bytes.extend(topic_xdr(topic)?);
+    bytes.extend_from_slice(data);
+
+    let hash = keccak256(&bytes).to_vec();
+    let wrapped = ScVal::Bytes(ScBytes(hash.try_into()?));
+    Ok(wrapped.to_xdr(Limits::none())?)
+}
+
+// An issuer wallet key. Its account id is the `issuer_wallet` claims carry.
+pub struct ClaimSigner {
+    signing_key: SigningKey,
+}
+
+impl ClaimSigner {
+    pub fn from_bytes(secret: &[u8; 32]) -> Self {
+        Self {
+            signing_key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    // Parses a Stellar secret seed (S...)
+    pub fn from_secret_seed(seed: &str) -> Result<Self, Error> {
+        let secret = stellar_strkey::ed25519::PrivateKey::from_string(seed)
+            .map_err(|_| Error::InvalidSecret)?;
+        Ok(Self::from_bytes(&secret.0))
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    pub fn account_id(&self) -> String {
+        strkey::encode_account(&self.public_key())
+    }
+
+    pub fn sign_claim(
+        &self,
+        identity: &str,
+        topic: &Topic,
+        data: &[u8],
+    ) -> Result<[u8; 64], Error> {
+        let digest = claim_digest(identity, topic, data)?;
+        Ok(self.signing_key.sign(&digest).to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test;
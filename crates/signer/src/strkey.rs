@@ -0,0 +1,46 @@
+// Stellar strkeys for the addresses claims refer to
+
+use stellar_strkey::{ed25519, Contract, Strkey};
+use stellar_xdr::curr::{AccountId, Hash, PublicKey, ScAddress, Uint256};
+
+use crate::Error;
+
+pub fn encode_account(pub_key: &[u8; 32]) -> String {
+    ed25519::PublicKey(*pub_key).to_string()
+}
+
+pub fn decode_account(account: &str) -> Option<[u8; 32]> {
+    ed25519::PublicKey::from_string(account)
+        .ok()
+        .map(|key| key.0)
+}
+
+pub fn encode_contract(contract_id: &[u8; 32]) -> String {
+    Contract(*contract_id).to_string()
+}
+
+pub fn decode_contract(contract: &str) -> Option<[u8; 32]> {
+    Contract::from_string(contract)
+        .ok()
+        .map(|contract| contract.0)
+}
+
+// Accounts (G...) and contracts (C...) are the only addresses a claim can name
+pub fn parse_address(address: &str) -> Result<ScAddress, Error> {
+    match Strkey::from_string(address) {
+        Ok(Strkey::PublicKeyEd25519(key)) => Ok(ScAddress::Account(AccountId(
+            PublicKey::PublicKeyTypeEd25519(Uint256(key.0)),
+        ))),
+        Ok(Strkey::Contract(contract)) => Ok(ScAddress::Contract(Hash(contract.0))),
+        _ => Err(Error::InvalidAddress(address.to_string())),
+    }
+}
+
+pub fn format_address(address: &ScAddress) -> String {
+    match address {
+        ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key)))) => {
+            encode_account(key)
+        }
+        ScAddress::Contract(Hash(contract)) => encode_contract(contract),
+    }
+}
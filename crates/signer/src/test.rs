@@ -0,0 +1,123 @@
+// Checked against the contracts' own implementations in onchainid-common
+
+use super::*;
+use onchainid_common::{
+    hash_claim, hash_key, identity_salt as contract_identity_salt, verify_claim_signature,
+};
+use soroban_sdk::{Address, Bytes, Env, U256};
+
+// Same test key as the contract tests, do not use it anywhere else
+const ISSUER_SECRET: [u8; 32] = [
+    0xb5, 0x1a, 0x48, 0x2a, 0x45, 0x9d, 0x1b, 0x2f, 0x8f, 0x1f, 0xf5, 0xb7, 0x15, 0x9c, 0xdb, 0xf0,
+    0xab, 0x23, 0xee, 0x46, 0x42, 0x2e, 0xd0, 0x72, 0x4f, 0x28, 0x22, 0xcd, 0x55, 0x0e, 0xcf, 0x71,
+];
+const ISSUER_WALLET: &str = "GARPZXSZVTI7WG3ADZQB5QGH67WS6XW6ISHIO2N4AOAC32PLNYBH7QCY";
+
+fn address(env: &Env, strkey: &str) -> Address {
+    Address::from_string(&soroban_sdk::String::from_str(env, strkey))
+}
+
+fn identity() -> String {
+    strkey::encode_contract(&[7u8; 32])
+}
+
+#[test]
+fn test_account_id() {
+    let signer = ClaimSigner::from_bytes(&ISSUER_SECRET);
+    assert_eq!(signer.account_id(), ISSUER_WALLET);
+    assert_eq!(
+        strkey::decode_account(ISSUER_WALLET),
+        Some(signer.public_key())
+    );
+
+    let identity = identity();
+    assert_eq!(strkey::decode_contract(&identity), Some([7u8; 32]));
+    assert_eq!(strkey::decode_account(&identity), None);
+    assert_eq!(
+        strkey::format_address(&strkey::parse_address(&identity).unwrap()),
+        identity
+    );
+    assert_eq!(
+        address_xdr("not an address"),
+        Err(Error::InvalidAddress("not an address".into()))
+    );
+}
+
+#[test]
+fn test_hashes_match_contracts() {
+    let env = Env::default();
+    let topic = topic_from_u128(6);
+    let issuer = strkey::encode_contract(&[9u8; 32]);
+
+    assert_eq!(
+        claim_id(&issuer, &topic).unwrap(),
+        hash_claim(&env, &address(&env, &issuer), &U256::from_u32(&env, 6)).to_array()
+    );
+    assert_eq!(
+        key_hash(ISSUER_WALLET).unwrap(),
+        hash_key(&env, &address(&env, ISSUER_WALLET)).to_array()
+    );
+    assert_eq!(
+        identity_salt(ISSUER_WALLET, 3).unwrap(),
+        contract_identity_salt(&env, &address(&env, ISSUER_WALLET), 3).to_array()
+    );
+}
+
+#[test]
+fn test_digest_matches_contracts() {
+    let env = Env::default();
+    let identity = identity();
+    let data = b"data";
+
+    let expected = onchainid_common::claim_digest(
+        &env,
+        &address(&env, &identity),
+        &U256::from_u128(&env, u128::MAX),
+        &Bytes::from_slice(&env, data),
+    );
+    let digest = claim_digest(&identity, &topic_from_u128(u128::MAX), data).unwrap();
+    assert_eq!(Bytes::from_slice(&env, &digest), expected);
+}
+
+#[test]
+fn test_signature_verifies_on_chain() {
+    let env = Env::default();
+    let signer = ClaimSigner::from_bytes(&ISSUER_SECRET);
+    let identity = identity();
+    let data = b"data";
+
+    let signature = signer
+        .sign_claim(&identity, &topic_from_u128(6), data)
+        .unwrap();
+
+    verify_claim_signature(
+        &env,
+        &address(&env, &signer.account_id()),
+        &address(&env, &identity),
+        &U256::from_u32(&env, 6),
+        &Bytes::from_slice(&env, &signature),
+        &Bytes::from_slice(&env, data),
+    )
+    .unwrap();
+}
+
+#[test]
+#[should_panic]
+fn test_tampered_claim_fails_on_chain() {
+    let env = Env::default();
+    let signer = ClaimSigner::from_bytes(&ISSUER_SECRET);
+    let identity = identity();
+
+    let signature = signer
+        .sign_claim(&identity, &topic_from_u128(6), b"data")
+        .unwrap();
+
+    let _ = verify_claim_signature(
+        &env,
+        &address(&env, &signer.account_id()),
+        &address(&env, &identity),
+        &U256::from_u32(&env, 6),
+        &Bytes::from_slice(&env, &signature),
+        &Bytes::from_slice(&env, b"other data"),
+    );
+}
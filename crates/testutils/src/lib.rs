@@ -0,0 +1,245 @@
+// Test fixtures for the 0xAuth contract suite.
+//
+// `Fixture::deploy` stands up factory, claim issuer, gated and trex contracts
+// wired together, identities are then created through the factory and claims
+// signed by an ed25519 issuer key. Contracts must be built to wasm first.
+
+use ed25519_dalek::{Signer, SigningKey};
+use onchainid_common::claim_digest;
+use rand::{rngs::StdRng, SeedableRng};
+use soroban_sdk::{
+    testutils::{Address as _, Ledger},
+    Address, Bytes, BytesN, Env, String, U256,
+};
+use std::cell::RefCell;
+
+pub mod contracts {
+    pub mod factory {
+        soroban_sdk::contractimport!(
+            file = "../../target/wasm32-unknown-unknown/release/factory.wasm"
+        );
+    }
+    pub mod identity {
+        soroban_sdk::contractimport!(
+            file = "../../target/wasm32-unknown-unknown/release/identity.wasm"
+        );
+    }
+    pub mod claim_issuer {
+        soroban_sdk::contractimport!(
+            file = "../../target/wasm32-unknown-unknown/release/claim_issuer.wasm"
+        );
+    }
+    pub mod gated {
+        soroban_sdk::contractimport!(
+            file = "../../target/wasm32-unknown-unknown/release/gated.wasm"
+        );
+    }
+    pub mod trex {
+        soroban_sdk::contractimport!(
+            file = "../../target/wasm32-unknown-unknown/release/trex.wasm"
+        );
+    }
+}
+
+use contracts::{claim_issuer, factory, gated, identity, trex};
+
+pub use onchainid_signer::strkey;
+
+// Default claim scheme used by the fixture, ECDSA in ERC-735 terms
+pub const SCHEME: u32 = 1;
+
+// An ed25519 keypair whose Stellar account acts as an issuer wallet
+pub struct IssuerKey {
+    pub signing_key: SigningKey,
+    pub wallet: Address,
+}
+
+thread_local! {
+    // Seeded per test thread so generated keys, and the snapshots recording
+    // them, are the same on every run
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+}
+
+impl IssuerKey {
+    pub fn generate(env: &Env) -> Self {
+        let signing_key = RNG.with(|rng| SigningKey::generate(&mut *rng.borrow_mut()));
+        Self::from_signing_key(env, signing_key)
+    }
+
+    pub fn from_secret(env: &Env, secret: &[u8; 32]) -> Self {
+        Self::from_signing_key(env, SigningKey::from_bytes(secret))
+    }
+
+    fn from_signing_key(env: &Env, signing_key: SigningKey) -> Self {
+        let account = strkey::encode_account(&signing_key.verifying_key().to_bytes());
+        let wallet = Address::from_string(&String::from_str(env, &account));
+        Self {
+            signing_key,
+            wallet,
+        }
+    }
+
+    // Signs the same `claim_digest` claim issuers verify
+    pub fn sign_claim(&self, env: &Env, identity: &Address, topic: &U256, data: &Bytes) -> Bytes {
+        let digest: Vec<u8> = claim_digest(env, identity, topic, data).iter().collect();
+        let signature = self.signing_key.sign(&digest);
+        Bytes::from_slice(env, &signature.to_bytes())
+    }
+}
+
+pub struct Fixture {
+    pub env: Env,
+    // Owner of the factory, gated and trex contracts and management key of the issuer
+    pub owner: Address,
+    pub factory: Address,
+    pub claim_issuer: Address,
+    pub gated: Address,
+    pub trex: Address,
+    pub identity_wasm_hash: BytesN<32>,
+    pub issuer_key: IssuerKey,
+}
+
+impl Fixture {
+    // Deploys and initializes the whole suite with all auths mocked and the
+    // budget lifted, the suite's wasm calls don't fit in one transaction
+    pub fn deploy(env: &Env) -> Self {
+        env.mock_all_auths();
+        env.budget().reset_unlimited();
+
+        let owner = Address::generate(env);
+        let issuer_key = IssuerKey::generate(env);
+
+        let factory = env.register_contract_wasm(None, factory::WASM);
+        factory::Client::new(env, &factory).initialize(&owner);
+
+        let claim_issuer = env.register_contract_wasm(None, claim_issuer::WASM);
+        let issuer_client = claim_issuer::Client::new(env, &claim_issuer);
+        issuer_client.initialize(&owner);
+        issuer_client.add_key(&owner, &issuer_key.wallet, &3, &1);
+
+        let gated = env.register_contract_wasm(None, gated::WASM);
+        gated::Client::new(env, &gated).initialize(&factory, &claim_issuer, &owner);
+
+        let trex = env.register_contract_wasm(None, trex::WASM);
+        trex::Client::new(env, &trex).initialize(&gated, &owner);
+
+        let identity_wasm_hash = env.deployer().upload_contract_wasm(identity::WASM);
+        factory::Client::new(env, &factory).set_identity_wasm(&owner, &identity_wasm_hash);
+
+        Self {
+            env: env.clone(),
+            owner,
+            factory,
+            claim_issuer,
+            gated,
+            trex,
+            identity_wasm_hash,
+            issuer_key,
+        }
+    }
+
+    pub fn factory_client(&self) -> factory::Client<'_> {
+        factory::Client::new(&self.env, &self.factory)
+    }
+
+    pub fn claim_issuer_client(&self) -> claim_issuer::Client<'_> {
+        claim_issuer::Client::new(&self.env, &self.claim_issuer)
+    }
+
+    pub fn gated_client(&self) -> gated::Client<'_> {
+        gated::Client::new(&self.env, &self.gated)
+    }
+
+    pub fn trex_client(&self) -> trex::Client<'_> {
+        trex::Client::new(&self.env, &self.trex)
+    }
+
+    pub fn identity_client(&self, identity: &Address) -> identity::Client<'_> {
+        identity::Client::new(&self.env, identity)
+    }
+
+    // Deploys an identity through the factory and links it to `wallet`.
+    // The wallet becomes both the Management and the Claim key of the identity,
+    // next to the owner that created it.
+    pub fn create_identity(&self, wallet: &Address) -> Address {
+        let identity = self
+            .factory_client()
+            .create_identity(&self.owner, wallet, &0);
+        self.identity_client(&identity)
+            .add_key(wallet, wallet, &3, &1);
+
+        identity
+    }
+
+    pub fn identity_of(&self, wallet: &Address) -> Address {
+        self.factory_client()
+            .try_get_identity(wallet)
+            .expect("wallet has no identity")
+    }
+
+    pub fn sign_claim(&self, identity: &Address, topic: u32, data: &Bytes) -> Bytes {
+        self.issuer_key
+            .sign_claim(&self.env, identity, &U256::from_u32(&self.env, topic), data)
+    }
+
+    // Signs a claim with the current issuer key and adds it to the wallet's identity
+    pub fn issue_claim(&self, wallet: &Address, topic: u32, data: &Bytes) -> BytesN<32> {
+        let identity = self.identity_of(wallet);
+        let signature = self.sign_claim(&identity, topic, data);
+
+        self.identity_client(&identity).add_claim(
+            wallet,
+            &U256::from_u32(&self.env, topic),
+            &U256::from_u32(&self.env, SCHEME),
+            &self.issuer_key.wallet,
+            &self.claim_issuer,
+            &signature,
+            data,
+            &Bytes::new(&self.env),
+        )
+    }
+
+    pub fn revoke_claim(&self, wallet: &Address, claim_id: &BytesN<32>) {
+        self.claim_issuer_client()
+            .revoke_claim(&self.owner, &self.identity_of(wallet), claim_id);
+    }
+
+    // Whether the gated contract accepts the wallet's claim for `topic`
+    pub fn is_verified(&self, wallet: &Address, topic: u32) -> bool {
+        self.gated_client().validate_claim(
+            wallet,
+            &self.claim_issuer,
+            &U256::from_u32(&self.env, topic),
+        )
+    }
+
+    // Replaces the issuer's Claim key with a fresh one and returns the old key.
+    // Claims signed by the old key stop validating.
+    pub fn rotate_issuer_key(&mut self) -> IssuerKey {
+        let new_key = IssuerKey::generate(&self.env);
+        let issuer_client = self.claim_issuer_client();
+
+        issuer_client.add_key(&self.owner, &new_key.wallet, &3, &1);
+        issuer_client.remove_key(&self.owner, &self.issuer_key.wallet, &3);
+
+        std::mem::replace(&mut self.issuer_key, new_key)
+    }
+
+    // Hands the Management purpose of the wallet's identity over to `new_key`
+    pub fn rotate_management_key(&self, wallet: &Address, new_key: &Address) {
+        let identity_client = self.identity_client(&self.identity_of(wallet));
+
+        identity_client.add_key(wallet, new_key, &1, &1);
+        identity_client.remove_key(new_key, wallet, &1);
+    }
+
+    // Moves the ledger clock forward, e.g. past a delegation expiry
+    pub fn advance_time(&self, seconds: u64) {
+        self.env.ledger().with_mut(|li| {
+            li.timestamp += seconds;
+        });
+    }
+}
+
+#[cfg(test)]
+mod test;
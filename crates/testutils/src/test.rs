@@ -0,0 +1,283 @@
+use super::*;
+use soroban_sdk::{token, vec};
+
+// Same test key as the contract tests, do not use it anywhere else
+const ISSUER_SECRET: [u8; 32] = [
+    0xb5, 0x1a, 0x48, 0x2a, 0x45, 0x9d, 0x1b, 0x2f, 0x8f, 0x1f, 0xf5, 0xb7, 0x15, 0x9c, 0xdb, 0xf0,
+    0xab, 0x23, 0xee, 0x46, 0x42, 0x2e, 0xd0, 0x72, 0x4f, 0x28, 0x22, 0xcd, 0x55, 0x0e, 0xcf, 0x71,
+];
+const ISSUER_WALLET: &str = "GARPZXSZVTI7WG3ADZQB5QGH67WS6XW6ISHIO2N4AOAC32PLNYBH7QCY";
+
+#[test]
+fn test_strkey() {
+    let env = Env::default();
+    let key = IssuerKey::from_secret(&env, &ISSUER_SECRET);
+    let pub_key = key.signing_key.verifying_key().to_bytes();
+
+    assert_eq!(strkey::encode_account(&pub_key), ISSUER_WALLET);
+    assert_eq!(strkey::decode_account(ISSUER_WALLET), Some(pub_key));
+    assert_eq!(
+        key.wallet,
+        Address::from_string(&String::from_str(&env, ISSUER_WALLET))
+    );
+
+    // Bad checksum
+    assert_eq!(
+        strkey::decode_account("GARPZXSZVTI7WG3ADZQB5QGH67WS6XW6ISHIO2N4AOAC32PLNYBH7QCA"),
+        None
+    );
+}
+
+#[test]
+fn test_claim_lifecycle() {
+    let env = Env::default();
+    let fixture = Fixture::deploy(&env);
+
+    let wallet = Address::generate(&env);
+    let identity = fixture.create_identity(&wallet);
+    assert_eq!(fixture.identity_of(&wallet), identity);
+
+    let data = Bytes::from_slice(&env, "kyc".as_bytes());
+    let claim_id = fixture.issue_claim(&wallet, 1, &data);
+    assert!(fixture.is_verified(&wallet, 1), "Claim should be valid");
+
+    fixture.revoke_claim(&wallet, &claim_id);
+    assert!(
+        !fixture.is_verified(&wallet, 1),
+        "Revoked claim should be invalid"
+    );
+}
+
+#[test]
+fn test_bridge_managed_identity() {
+    let env = Env::default();
+    let fixture = Fixture::deploy(&env);
+    let factory = fixture.factory_client();
+
+    // The bridge backend creates identities for wallets that never sign
+    let backend = Address::generate(&env);
+    factory.grant_role(&fixture.owner, &factory::Role::IdentityCreator, &backend);
+    let wallet = Address::generate(&env);
+    let identity = factory.create_identity(&backend, &wallet, &0);
+    assert_eq!(fixture.identity_of(&wallet), identity);
+
+    let identity_client = fixture.identity_client(&identity);
+    for key in [&backend, &wallet] {
+        assert!(identity_client
+            .get_key(key)
+            .purposes
+            .contains(identity::KeyPurpose::Management));
+    }
+
+    // As the bridge's add_key and add_claim actions do, signed by the backend
+    identity_client.add_key(&backend, &backend, &3, &1);
+    let data = Bytes::from_slice(&env, "kyc".as_bytes());
+    identity_client.add_claim(
+        &backend,
+        &U256::from_u32(&env, 1),
+        &U256::from_u32(&env, SCHEME),
+        &fixture.issuer_key.wallet,
+        &fixture.claim_issuer,
+        &fixture.sign_claim(&identity, 1, &data),
+        &data,
+        &Bytes::new(&env),
+    );
+    assert!(fixture.is_verified(&wallet, 1));
+}
+
+#[test]
+fn test_predicted_identity_address() {
+    let env = Env::default();
+    let fixture = Fixture::deploy(&env);
+    let factory = fixture.factory_client();
+
+    let wallet = Address::generate(&env);
+    let predicted = factory.predict_identity_address(&wallet, &0);
+
+    // Known before deployment, e.g. to have a claim signed for it up front
+    let data = Bytes::from_slice(&env, "kyc".as_bytes());
+    let signature = fixture.sign_claim(&predicted, 1, &data);
+
+    assert_eq!(fixture.create_identity(&wallet), predicted);
+    assert_eq!(
+        factory.get_identity_wasm(&factory.get_identity_wasm_version()),
+        fixture.identity_wasm_hash
+    );
+    assert!(fixture.claim_issuer_client().is_claim_valid(
+        &fixture.issuer_key.wallet,
+        &predicted,
+        &U256::from_u32(&env, 1),
+        &U256::from_u32(&env, SCHEME),
+        &signature,
+        &data
+    ));
+}
+
+#[test]
+fn test_self_service_identity() {
+    let env = Env::default();
+    let fixture = Fixture::deploy(&env);
+    let factory = fixture.factory_client();
+
+    let fee_token = env.register_stellar_asset_contract(fixture.owner.clone());
+    factory.set_self_service(
+        &fixture.owner,
+        &factory::SelfService {
+            enabled: true,
+            allowlist: true,
+        },
+    );
+    factory.set_creation_fee(&fixture.owner, &fee_token, &100);
+
+    let wallet = Address::generate(&env);
+    token::StellarAssetClient::new(&env, &fee_token).mint(&wallet, &100);
+    assert_eq!(
+        factory.try_create_my_identity(&wallet),
+        Err(Ok(factory::FactoryError::WalletNotAllowed))
+    );
+
+    factory.set_allowed(&fixture.owner, &wallet, &true);
+    let predicted = factory.predict_my_identity_address(&wallet);
+    let identity = factory.create_my_identity(&wallet);
+    assert_eq!(identity, predicted);
+
+    // Authorized by the wallet alone, which also paid the fee
+    assert_eq!(env.auths()[0].0, wallet);
+    let balances = token::Client::new(&env, &fee_token);
+    assert_eq!(balances.balance(&wallet), 0);
+    assert_eq!(balances.balance(&fixture.owner), 100);
+
+    // Managed by and linked to the wallet
+    assert_eq!(fixture.identity_of(&wallet), identity);
+    assert!(fixture
+        .identity_client(&identity)
+        .get_key(&wallet)
+        .purposes
+        .contains(identity::KeyPurpose::Management));
+
+    assert_eq!(
+        factory.try_create_my_identity(&wallet),
+        Err(Ok(factory::FactoryError::IdentityAlreadyExists))
+    );
+
+    // Unlinked, the wallet's first identity still holds the address both
+    // paths deploy to, so neither tries to deploy over it
+    factory.unlink_wallet(&wallet, &identity, &wallet);
+    assert_eq!(
+        factory.try_create_my_identity(&wallet),
+        Err(Ok(factory::FactoryError::IdentityAlreadyExists))
+    );
+    assert_eq!(
+        factory.try_create_identity(&fixture.owner, &wallet, &0),
+        Err(Ok(factory::FactoryError::IdentityAlreadyExists))
+    );
+    assert_eq!(
+        factory.create_identity(&fixture.owner, &wallet, &1),
+        factory.predict_identity_address(&wallet, &1)
+    );
+}
+
+#[test]
+fn test_token_identity() {
+    let env = Env::default();
+    let fixture = Fixture::deploy(&env);
+    let factory = fixture.factory_client();
+
+    let token_factory = Address::generate(&env);
+    factory.grant_role(&fixture.owner, &factory::Role::TokenFactory, &token_factory);
+
+    let token = fixture.trex.clone();
+    let token_owner = Address::generate(&env);
+    let salt = BytesN::from_array(&env, &[7; 32]);
+    let predicted = factory.predict_token_identity_address(&salt);
+
+    let identity = factory.create_token_identity(&token_factory, &token, &token_owner, &salt);
+    assert_eq!(identity, predicted);
+    assert_eq!(factory.get_token_identity(&token), identity);
+    assert_eq!(factory.get_token(&identity), token);
+    assert!(factory.is_identity(&identity));
+    assert!(fixture
+        .identity_client(&identity)
+        .get_key(&token_owner)
+        .purposes
+        .contains(identity::KeyPurpose::Management));
+
+    // Token and wallet mappings are separate
+    assert_eq!(factory.try_get_identity(&token), None);
+    assert_eq!(
+        factory.try_create_token_identity(&fixture.owner, &token, &token_owner, &salt),
+        Err(Ok(factory::FactoryError::TokenIdentityAlreadyExists))
+    );
+
+    // Another token can't reuse the salt, its identity would land on this one
+    let other_token = Address::generate(&env);
+    assert_eq!(
+        factory.try_create_token_identity(&token_factory, &other_token, &token_owner, &salt),
+        Err(Ok(factory::FactoryError::TokenIdentityAlreadyExists))
+    );
+}
+
+#[test]
+fn test_upgrade_identities() {
+    let env = Env::default();
+    let fixture = Fixture::deploy(&env);
+    let factory = fixture.factory_client();
+
+    let trusting = Address::generate(&env);
+    let other = Address::generate(&env);
+    let trusting_identity = fixture.create_identity(&trusting);
+    let other_identity = fixture.create_identity(&other);
+    fixture
+        .identity_client(&trusting_identity)
+        .set_upgrade_authority(&trusting, &fixture.factory);
+    assert_eq!(factory.get_identity_version(&trusting_identity), 1);
+
+    // A new release of the same code is enough to exercise the upgrade path
+    let version = factory.set_identity_wasm(&fixture.owner, &fixture.identity_wasm_hash);
+    let report = factory.upgrade_identities(&fixture.owner, &0, &10);
+    assert_eq!(report.version, version);
+    assert_eq!(report.upgraded, vec![&env, trusting_identity.clone()]);
+    assert_eq!(report.failed, vec![&env, other_identity.clone()]);
+
+    // The upgraded identity keeps its state
+    assert_eq!(factory.get_identity_version(&trusting_identity), version);
+    assert_eq!(factory.get_identity_version(&other_identity), 1);
+    assert!(fixture
+        .identity_client(&trusting_identity)
+        .get_key(&trusting)
+        .purposes
+        .contains(identity::KeyPurpose::Management));
+}
+
+#[test]
+fn test_key_rotation() {
+    let env = Env::default();
+    let mut fixture = Fixture::deploy(&env);
+
+    let wallet = Address::generate(&env);
+    fixture.create_identity(&wallet);
+
+    let data = Bytes::from_slice(&env, "kyc".as_bytes());
+    fixture.issue_claim(&wallet, 1, &data);
+
+    fixture.rotate_issuer_key();
+    assert!(
+        !fixture.is_verified(&wallet, 1),
+        "Claims signed by a removed key should be invalid"
+    );
+
+    fixture.issue_claim(&wallet, 1, &data);
+    assert!(fixture.is_verified(&wallet, 1));
+
+    let new_manager = Address::generate(&env);
+    fixture.rotate_management_key(&wallet, &new_manager);
+    let identity_client = fixture.identity_client(&fixture.identity_of(&wallet));
+    assert!(identity_client
+        .get_key(&new_manager)
+        .purposes
+        .contains(identity::KeyPurpose::Management));
+    assert!(!identity_client
+        .get_key(&wallet)
+        .purposes
+        .contains(identity::KeyPurpose::Management));
+}